@@ -0,0 +1,166 @@
+// --- Provider Error Parsing ---
+//
+// 将各家 API 返回的错误体解析为结构化的 ApiError。支持的格式：
+// - OpenAI 及兼容网关: {"error": {"type", "code", "message", "param"}}
+// - Anthropic:         {"type": "error", "error": {"type", "message"}}
+// - Gemini:            {"error": {"code": 400, "status": "INVALID_ARGUMENT", "message"}}
+
+use regex::Regex;
+
+use crate::ApiError;
+
+/// 从错误体中提取出的统一字段
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ErrorEnvelope {
+    pub error_type: Option<String>,
+    pub code: Option<String>,
+    pub message: String,
+    pub param: Option<String>,
+}
+
+fn value_to_string(value: &serde_json::Value) -> Option<String> {
+    match value {
+        serde_json::Value::String(s) if !s.is_empty() => Some(s.clone()),
+        serde_json::Value::Number(n) => Some(n.to_string()),
+        _ => None,
+    }
+}
+
+/// 尝试从响应体中解析错误信封，无法识别时返回 None
+pub fn parse_envelope(body: &str) -> Option<ErrorEnvelope> {
+    let json: serde_json::Value = serde_json::from_str(body).ok()?;
+    // Gemini 有时会把错误包在数组中返回
+    let json = match json {
+        serde_json::Value::Array(mut items) if !items.is_empty() => items.swap_remove(0),
+        other => other,
+    };
+
+    let error = &json["error"];
+    match error {
+        serde_json::Value::Object(_) => {
+            // Gemini 的 `status` 字段承担了 type 的角色，`code` 为 HTTP 状态码
+            let error_type = value_to_string(&error["type"]).or_else(|| value_to_string(&error["status"]));
+            Some(ErrorEnvelope {
+                error_type,
+                code: value_to_string(&error["code"]),
+                message: value_to_string(&error["message"]).unwrap_or_default(),
+                param: value_to_string(&error["param"]),
+            })
+        }
+        // 部分网关直接返回 {"error": "message"}
+        serde_json::Value::String(message) => Some(ErrorEnvelope {
+            message: message.clone(),
+            ..Default::default()
+        }),
+        _ => None,
+    }
+}
+
+/// 解析 OpenAI 风格的上下文长度错误信息中的 token 数
+fn parse_token_counts(message: &str) -> (Option<u32>, Option<u32>) {
+    let max_re = Regex::new(r"maximum (?:context length|number of tokens)[^\d]*(\d+)").unwrap();
+    let requested_re = Regex::new(r"(?:resulted in|requested|input token count[^\d]*)\s*(\d+)").unwrap();
+    let max_tokens = max_re.captures(message).and_then(|c| c[1].parse().ok());
+    let requested_tokens = requested_re.captures(message).and_then(|c| c[1].parse().ok());
+    (max_tokens, requested_tokens)
+}
+
+fn matches_any(haystack: &str, needles: &[&str]) -> bool {
+    needles.iter().any(|needle| haystack.contains(needle))
+}
+
+/// 根据 HTTP 状态码和错误体推断具体的错误类型
+pub fn classify(status: u16, body: &str, retry_after_secs: Option<u32>, model: &str) -> ApiError {
+    let envelope = parse_envelope(body);
+    let (error_type, code, message) = match &envelope {
+        Some(e) => (
+            e.error_type.clone().unwrap_or_default().to_lowercase(),
+            e.code.clone().unwrap_or_default().to_lowercase(),
+            e.message.clone(),
+        ),
+        None => (String::new(), String::new(), String::new()),
+    };
+    let lower_message = message.to_lowercase();
+    let kind = format!("{} {}", error_type, code);
+
+    if matches_any(&kind, &["context_length_exceeded", "string_above_max_length"])
+        || matches_any(&lower_message, &["maximum context length", "prompt is too long", "exceeds the maximum number of tokens", "context window"])
+    {
+        let (max_tokens, requested_tokens) = parse_token_counts(&lower_message);
+        return ApiError::ContextLengthExceeded { message, max_tokens, requested_tokens };
+    }
+
+    if matches_any(&kind, &["insufficient_quota", "billing"])
+        || matches_any(&lower_message, &["exceeded your current quota", "credit balance is too low"])
+    {
+        return ApiError::InsufficientQuota { message };
+    }
+
+    if matches_any(&kind, &["content_filter", "content_policy_violation", "safety"])
+        || matches_any(&lower_message, &["content management policy", "blocked due to safety"])
+    {
+        return ApiError::ContentFiltered { message, category: envelope.and_then(|e| e.param) };
+    }
+
+    // 只认明确表示模型不存在的信号；"model ... not supported" 之类通常是参数不被该模型支持
+    if matches_any(&kind, &["model_not_found"])
+        || (lower_message.contains("model") && (lower_message.contains("does not exist") || status == 404))
+    {
+        return ApiError::ModelNotFound { model: model.to_string(), message };
+    }
+
+    if matches_any(&kind, &["invalid_api_key", "authentication_error", "api_key_invalid", "unauthenticated"])
+        || status == 401
+    {
+        return ApiError::InvalidApiKey;
+    }
+
+    if matches_any(&kind, &["rate_limit", "resource_exhausted"]) || status == 429 {
+        return ApiError::RateLimitExceeded;
+    }
+
+    if matches_any(&kind, &["overloaded", "server_overloaded", "unavailable"]) || status == 503 || status == 529 {
+        return ApiError::Overloaded { message, retry_after_secs };
+    }
+
+    if matches_any(&kind, &["timeout", "deadline_exceeded"]) || status == 408 || status == 504 {
+        return ApiError::Timeout { timeout_secs: None };
+    }
+
+    if status == 404 {
+        return ApiError::InvalidApiUrl("API endpoint not found. Please check the URL.".to_string());
+    }
+
+    if status == 400 {
+        let message = if message.is_empty() { body.to_string() } else { message };
+        return ApiError::BadRequest { message, param: envelope.and_then(|e| e.param) };
+    }
+
+    match envelope {
+        Some(e) => ApiError::ProviderError {
+            status,
+            error_type: e.error_type,
+            code: e.code,
+            message: e.message,
+            param: e.param,
+        },
+        None => ApiError::ApiResponseError(format!("API request failed with status {}: {}", status, body)),
+    }
+}
+
+/// 请求成功但响应中只有内容过滤标记时（例如 `finish_reason: "content_filter"`），返回对应错误
+pub fn content_filter_from_success(body: &str) -> Option<ApiError> {
+    let json: serde_json::Value = serde_json::from_str(body).ok()?;
+    let finish_reason = json["choices"][0]["finish_reason"].as_str()
+        .or_else(|| json["candidates"][0]["finishReason"].as_str())
+        .or_else(|| json["promptFeedback"]["blockReason"].as_str())?;
+
+    if matches_any(&finish_reason.to_lowercase(), &["content_filter", "safety", "blocklist", "prohibited_content"]) {
+        Some(ApiError::ContentFiltered {
+            message: "The response was blocked by the provider's content filter".to_string(),
+            category: Some(finish_reason.to_string()),
+        })
+    } else {
+        None
+    }
+}
@@ -0,0 +1,175 @@
+// --- Message Attachments ---
+//
+// 一条用户消息可以携带多个附件（图片和已提取文本的文档）。这里负责检查每条消息的
+// 附件数量与总大小限制，并将文本和附件组合成各 API 所需的多段内容格式。
+// 请求都以 OpenAI 兼容的 `{model, messages}` 发送，图片使用 `image_url` 段；只有 Ollama 的原生
+// /api/chat 接受同样的请求结构但把图片放在消息的 `images` 数组中，按 API 地址选择。
+
+use serde_json::{json, Value};
+use url::Url;
+
+use crate::image_processing::{self, ImageLimits};
+use crate::providers::ProviderKind;
+use crate::Attachment;
+
+/// 每条消息最多允许的附件数量
+pub const MAX_ATTACHMENTS_PER_MESSAGE: usize = 10;
+/// 每条消息所有附件内容的总字节数上限（20MB）
+pub const MAX_ATTACHMENT_BYTES_PER_MESSAGE: usize = 20 * 1024 * 1024;
+
+/// 消息内容的格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContentFormat {
+    /// `text` 和 `image_url` 段。OpenAI 以及所有 OpenAI 兼容地址（包括 Gemini、Ollama 的兼容地址）
+    OpenAi,
+    /// 正文为字符串，图片为消息上不带前缀的 base64 `images` 数组（Ollama /api/chat）
+    Ollama,
+}
+
+impl ContentFormat {
+    /// 根据服务商和 API 地址的路径判断格式
+    pub fn detect(provider: ProviderKind, api_url: &str) -> Self {
+        let path = Url::parse(api_url).map(|url| url.path().to_string()).unwrap_or_default();
+        match provider {
+            ProviderKind::Ollama if path.ends_with("/api/chat") => ContentFormat::Ollama,
+            _ => ContentFormat::OpenAi,
+        }
+    }
+}
+
+/// 检查单条消息的附件数量和总大小
+pub fn validate_limits(attachments: &[Attachment]) -> Result<(), String> {
+    if attachments.len() > MAX_ATTACHMENTS_PER_MESSAGE {
+        return Err(format!(
+            "Too many attachments: {} (maximum {} per message)",
+            attachments.len(),
+            MAX_ATTACHMENTS_PER_MESSAGE
+        ));
+    }
+
+    let total_bytes: usize = attachments.iter().map(|a| a.content.len()).sum();
+    if total_bytes > MAX_ATTACHMENT_BYTES_PER_MESSAGE {
+        return Err(format!(
+            "Attachments are too large: {} bytes (maximum {}MB per message)",
+            total_bytes,
+            MAX_ATTACHMENT_BYTES_PER_MESSAGE / 1024 / 1024
+        ));
+    }
+
+    Ok(())
+}
+
+fn is_image(attachment: &Attachment) -> bool {
+    attachment.attachment_type == "image"
+}
+
+/// 导入的对话中只保留了名称和原始地址的附件（`image_ref`、`file_ref`），内容不可用
+fn is_reference(attachment: &Attachment) -> bool {
+    attachment.attachment_type.ends_with("_ref")
+}
+
+/// 将消息正文与文本附件拼接为一段文本，内容不可用的附件只留下一行说明
+fn combined_text(text: &str, attachments: &[Attachment]) -> String {
+    let mut sections = Vec::new();
+    if !text.is_empty() {
+        sections.push(text.to_string());
+    }
+    for attachment in attachments.iter().filter(|a| !is_image(a)) {
+        if is_reference(attachment) {
+            sections.push(format!("[Attachment not available: {}]", attachment.name));
+        } else {
+            sections.push(format!("--- Attached File: {} ---\n{}", attachment.name, attachment.content));
+        }
+    }
+    sections.join("\n\n")
+}
+
+/// 拆分消息内容：字符串原样作为正文；多段内容中的文本段合并为正文，图片段取出其地址
+fn split_content(content: &Value) -> (String, Vec<String>) {
+    let Some(parts) = content.as_array() else {
+        return (content.as_str().unwrap_or_default().to_string(), Vec::new());
+    };
+    let mut texts = Vec::new();
+    let mut images = Vec::new();
+    for part in parts {
+        match part["type"].as_str() {
+            Some("text") => texts.push(part["text"].as_str().unwrap_or_default()),
+            Some("image_url") => {
+                let url = part["image_url"]["url"].as_str().or_else(|| part["image_url"].as_str());
+                images.extend(url.map(str::to_string));
+            }
+            _ => {}
+        }
+    }
+    (texts.join("\n"), images)
+}
+
+/// `data:image/png;base64,...` 中的 base64 数据
+fn data_url_payload(data_url: &str) -> &str {
+    data_url.split_once(',').map_or(data_url, |(_, data)| data)
+}
+
+/// 构建发送给 API 的一条消息，文本原样发送不做任何改写。
+/// 没有图片时内容为普通字符串，以兼容只支持文本的网关；有图片时按 `format` 组成多段内容。
+/// 图片会按 `limits` 重新缩放和编码，并去除元数据；内容中不是 data URL 的图片地址只有
+/// OpenAI 格式可以直接引用，Ollama 格式改为在正文中列出地址。
+pub fn build_message(
+    role: &str,
+    content: Value,
+    attachments: &[Attachment],
+    limits: ImageLimits,
+    format: ContentFormat,
+) -> Result<Value, String> {
+    if format == ContentFormat::OpenAi && attachments.is_empty() {
+        return Ok(json!({ "role": role, "content": content }));
+    }
+
+    let (text, content_images) = split_content(&content);
+    let mut linked = Vec::new();
+    let mut images = Vec::new();
+    for (index, url) in content_images.into_iter().enumerate() {
+        if !url.starts_with("data:") {
+            linked.push(url);
+            continue;
+        }
+        let processed =
+            image_processing::process_data_url(&url, limits).map_err(|e| format!("image {}: {}", index + 1, e))?;
+        images.push(processed.data_url);
+    }
+    for image in attachments.iter().filter(|a| is_image(a)) {
+        let processed = image_processing::process_data_url(&image.content, limits)
+            .map_err(|e| format!("{}: {}", image.name, e))?;
+        images.push(processed.data_url);
+    }
+
+    let mut text = combined_text(&text, attachments);
+    if format != ContentFormat::OpenAi && !linked.is_empty() {
+        let notes = linked.drain(..).map(|url| format!("[Image: {}]", url));
+        text = std::iter::once(text).filter(|t| !t.is_empty()).chain(notes).collect::<Vec<_>>().join("\n\n");
+    }
+
+    let message = match format {
+        ContentFormat::OpenAi => {
+            if images.is_empty() && linked.is_empty() {
+                return Ok(json!({ "role": role, "content": text }));
+            }
+            let mut parts = Vec::with_capacity(images.len() + linked.len() + 1);
+            if !text.is_empty() {
+                parts.push(json!({ "type": "text", "text": text }));
+            }
+            for url in images.into_iter().chain(linked) {
+                parts.push(json!({ "type": "image_url", "image_url": { "url": url } }));
+            }
+            json!({ "role": role, "content": parts })
+        }
+        ContentFormat::Ollama => {
+            let mut message = json!({ "role": role, "content": text });
+            if !images.is_empty() {
+                let images: Vec<&str> = images.iter().map(|image| data_url_payload(image)).collect();
+                message["images"] = json!(images);
+            }
+            message
+        }
+    };
+    Ok(message)
+}
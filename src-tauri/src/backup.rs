@@ -0,0 +1,425 @@
+// --- Backup & Restore ---
+//
+// 把设置（包括配置档案，API 密钥可选择不包含）、提示库和整个历史数据库（附件内容保存在数据库中）
+// 打包为一个 zip 文件。`manifest.json` 记录备份格式版本以及每个文件的大小和 SHA-256，
+// 恢复前先校验全部文件，校验失败时不修改任何数据。替换模式先准备好历史数据库、保存设置，
+// 最后才替换数据库文件；替换失败时恢复原来的设置。
+// 启用了静态加密时，备份中的历史数据库和密钥保持加密，并附带 `vault.json`；
+// 恢复这样的备份需要创建备份时的主密码。较早版本创建的备份没有提示库，恢复时保留当前的提示库。
+
+use std::fs::File;
+use std::io::{Read, Seek, Write};
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tauri::{AppHandle, Manager};
+use ts_rs::TS;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+use crate::{history, prompts, register_shortcut, settings, vault, SecurityLogger};
+
+pub const BACKUP_EXTENSION: &str = "zip";
+const BACKUP_FORMAT: &str = "ai-window-backup";
+const BACKUP_VERSION: u32 = 1;
+
+const MANIFEST_ENTRY: &str = "manifest.json";
+const SETTINGS_ENTRY: &str = "settings.json";
+const HISTORY_ENTRY: &str = "history.db";
+const PROMPTS_ENTRY: &str = prompts::PROMPTS_FILE;
+const VAULT_ENTRY: &str = "vault.json";
+/// JSON 文件读入内存，限制大小以防损坏或恶意的备份
+const MAX_JSON_ENTRY_BYTES: u64 = 16 * 1024 * 1024;
+const COPY_BUFFER_SIZE: usize = 64 * 1024;
+
+#[derive(Serialize, Deserialize, Debug, Clone, TS)]
+#[ts(export)]
+pub struct BackupFile {
+    pub path: String,
+    #[ts(type = "number")]
+    pub size: u64,
+    pub sha256: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, TS)]
+#[ts(export)]
+pub struct BackupManifest {
+    pub format: String,
+    pub version: u32,
+    pub app_version: String,
+    pub created_at: DateTime<Utc>,
+    pub includes_secrets: bool,
+    /// 历史数据库和密钥用备份中 vault.json 的数据密钥加密
+    pub encrypted: bool,
+    pub conversation_count: u32,
+    pub files: Vec<BackupFile>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, TS)]
+#[serde(rename_all = "lowercase")]
+#[ts(export)]
+pub enum RestoreMode {
+    /// 只添加当前没有的对话、配置档案和设置项
+    Merge,
+    /// 用备份中的设置和历史替换当前数据
+    Replace,
+}
+
+#[derive(Serialize, Debug, Clone, TS)]
+#[ts(export)]
+pub struct RestoreReport {
+    pub mode: RestoreMode,
+    pub manifest: BackupManifest,
+    pub conversations_added: u32,
+    pub conversations_skipped: u32,
+    /// 写入的设置项数
+    pub settings_restored: u32,
+    /// 添加或替换的提示数
+    pub prompts_restored: u32,
+}
+
+pub fn default_file_name() -> String {
+    format!("ai-window-backup-{}.{}", chrono::Local::now().format("%Y%m%d-%H%M%S"), BACKUP_EXTENSION)
+}
+
+/// 复制数据并计算大小和 SHA-256
+fn copy_hashed(reader: &mut impl Read, writer: &mut impl Write) -> std::io::Result<(u64, String)> {
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0u8; COPY_BUFFER_SIZE];
+    let mut size = 0u64;
+    loop {
+        let read = reader.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+        writer.write_all(&buffer[..read])?;
+        size += read as u64;
+    }
+    Ok((size, format!("{:x}", hasher.finalize())))
+}
+
+fn add_entry<W: Write + Seek>(
+    zip: &mut ZipWriter<W>,
+    path: &str,
+    reader: &mut impl Read,
+    options: SimpleFileOptions,
+) -> Result<BackupFile, String> {
+    zip.start_file(path, options).map_err(|e| e.to_string())?;
+    let (size, sha256) = copy_hashed(reader, zip).map_err(|e| e.to_string())?;
+    Ok(BackupFile { path: path.to_string(), size, sha256 })
+}
+
+/// 写入备份文件。先写同目录下的临时文件，完成后再重命名。
+pub fn create_backup(app: &AppHandle, path: &Path, include_secrets: bool) -> Result<BackupManifest, String> {
+    let config_dir = app.path().app_config_dir().map_err(|e| e.to_string())?;
+    let data_dir = app.path().app_data_dir().map_err(|e| e.to_string())?;
+    let history = app.state::<history::HistoryStore>();
+    let database_key = vault::database_key(&config_dir)?;
+
+    let mut settings: serde_json::Map<String, serde_json::Value> = app.state::<settings::SettingsService>().store().entries().into_iter().collect();
+    if !include_secrets {
+        vault::map_secret_values(&mut settings, |_| Ok(String::new()))?;
+    }
+    let settings = serde_json::to_vec_pretty(&settings).map_err(|e| e.to_string())?;
+    let prompts = app.state::<prompts::PromptLibrary>().to_json()?;
+
+    let history_copy = data_dir.join("backup-history.db.tmp");
+    history.export_copy(&history_copy, database_key.as_ref().map(|key| key.as_str()))?;
+
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| "Backup path must include a file name".to_string())?
+        .to_string_lossy();
+    let temp_path: PathBuf = path.with_file_name(format!(".{}.tmp", file_name));
+
+    let result = (|| -> Result<BackupManifest, String> {
+        let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+        let mut zip = ZipWriter::new(File::create(&temp_path).map_err(|e| e.to_string())?);
+        let mut manifest = BackupManifest {
+            format: BACKUP_FORMAT.to_string(),
+            version: BACKUP_VERSION,
+            app_version: app.package_info().version.to_string(),
+            created_at: Utc::now(),
+            includes_secrets: include_secrets,
+            encrypted: database_key.is_some(),
+            conversation_count: history.conversation_count()?,
+            files: Vec::new(),
+        };
+
+        manifest.files.push(add_entry(&mut zip, SETTINGS_ENTRY, &mut settings.as_slice(), options)?);
+        manifest.files.push(add_entry(&mut zip, PROMPTS_ENTRY, &mut prompts.as_slice(), options)?);
+        let mut history_file = File::open(&history_copy).map_err(|e| e.to_string())?;
+        manifest.files.push(add_entry(&mut zip, HISTORY_ENTRY, &mut history_file, options.large_file(true))?);
+        if manifest.encrypted {
+            let mut vault_file = File::open(config_dir.join(vault::VAULT_FILE)).map_err(|e| e.to_string())?;
+            manifest.files.push(add_entry(&mut zip, VAULT_ENTRY, &mut vault_file, options)?);
+        }
+
+        let manifest_json = serde_json::to_vec_pretty(&manifest).map_err(|e| e.to_string())?;
+        zip.start_file(MANIFEST_ENTRY, options).map_err(|e| e.to_string())?;
+        zip.write_all(&manifest_json).map_err(|e| e.to_string())?;
+        zip.finish().map_err(|e| e.to_string())?.sync_all().map_err(|e| e.to_string())?;
+        std::fs::rename(&temp_path, path).map_err(|e| e.to_string())?;
+        Ok(manifest)
+    })();
+    let _ = std::fs::remove_file(&history_copy);
+    if result.is_err() {
+        let _ = std::fs::remove_file(&temp_path);
+    }
+    result.map_err(|e| format!("Failed to write backup to {}: {}", path.display(), e))
+}
+
+/// 读出一个文件并校验大小和 SHA-256
+fn read_verified<R: Read + Seek>(archive: &mut ZipArchive<R>, file: &BackupFile, dest: &mut impl Write) -> Result<(), String> {
+    let entry = archive
+        .by_name(&file.path)
+        .map_err(|_| format!("The backup is missing {}", file.path))?;
+    // 多读一个字节，以便发现比清单中记录的更大的文件
+    let (size, sha256) = copy_hashed(&mut entry.take(file.size + 1), dest)
+        .map_err(|e| format!("Failed to read {} from the backup: {}", file.path, e))?;
+    if size != file.size || sha256 != file.sha256 {
+        return Err(format!("The backup is corrupted: checksum mismatch for {}", file.path));
+    }
+    Ok(())
+}
+
+fn read_json_entry<R: Read + Seek>(archive: &mut ZipArchive<R>, file: &BackupFile) -> Result<Vec<u8>, String> {
+    if file.size > MAX_JSON_ENTRY_BYTES {
+        return Err(format!("The backup is corrupted: {} is too large", file.path));
+    }
+    let mut bytes = Vec::with_capacity(file.size as usize);
+    read_verified(archive, file, &mut bytes)?;
+    Ok(bytes)
+}
+
+fn read_manifest<R: Read + Seek>(archive: &mut ZipArchive<R>) -> Result<BackupManifest, String> {
+    let entry = archive
+        .by_name(MANIFEST_ENTRY)
+        .map_err(|_| "This file is not an AI Window backup".to_string())?;
+    let mut bytes = Vec::new();
+    entry
+        .take(MAX_JSON_ENTRY_BYTES)
+        .read_to_end(&mut bytes)
+        .map_err(|e| format!("Failed to read the backup manifest: {}", e))?;
+    let manifest: BackupManifest =
+        serde_json::from_slice(&bytes).map_err(|e| format!("The backup manifest is invalid: {}", e))?;
+    if manifest.format != BACKUP_FORMAT {
+        return Err("This file is not an AI Window backup".to_string());
+    }
+    if manifest.version > BACKUP_VERSION {
+        return Err(format!(
+            "The backup uses format version {}, which is newer than this version of AI Window supports",
+            manifest.version
+        ));
+    }
+    Ok(manifest)
+}
+
+fn manifest_file<'a>(manifest: &'a BackupManifest, path: &str) -> Result<&'a BackupFile, String> {
+    manifest
+        .files
+        .iter()
+        .find(|file| file.path == path)
+        .ok_or_else(|| format!("The backup is missing {}", path))
+}
+
+/// 备份中没有包含的密钥（空字符串）沿用当前设置中的值，配置档案按名称对应
+fn keep_current_secrets(
+    settings: &mut serde_json::Map<String, serde_json::Value>,
+    store: &tauri_plugin_store::Store<tauri::Wry>,
+) {
+    let is_empty = |value: Option<&serde_json::Value>| value.and_then(|v| v.as_str()).unwrap_or_default().is_empty();
+    let current_profiles = store.get("profiles").and_then(|v| v.as_array().cloned()).unwrap_or_default();
+    if let Some(serde_json::Value::Array(profiles)) = settings.get_mut("profiles") {
+        for profile in profiles {
+            if !is_empty(profile.get("api_key")) {
+                continue;
+            }
+            let current = current_profiles.iter().find(|p| p.get("name").is_some() && p.get("name") == profile.get("name"));
+            if let Some(api_key) = current.and_then(|p| p.get("api_key")) {
+                profile["api_key"] = api_key.clone();
+            }
+        }
+    }
+}
+
+/// 合并设置：只添加当前没有的设置项，配置档案按名称添加。返回写入的设置项数。
+fn merge_settings(settings: serde_json::Map<String, serde_json::Value>, store: &tauri_plugin_store::Store<tauri::Wry>) -> u32 {
+    let mut restored = 0;
+    for (key, value) in settings {
+        if key == "profiles" {
+            let Some(backup_profiles) = value.as_array() else {
+                continue;
+            };
+            let mut profiles = store.get("profiles").and_then(|v| v.as_array().cloned()).unwrap_or_default();
+            let before = profiles.len();
+            for profile in backup_profiles {
+                if !profiles.iter().any(|p| p.get("name") == profile.get("name")) {
+                    profiles.push(profile.clone());
+                }
+            }
+            if profiles.len() > before {
+                store.set("profiles", serde_json::Value::Array(profiles));
+                restored += 1;
+            }
+        } else if !store.has(&key) {
+            store.set(key, value);
+            restored += 1;
+        }
+    }
+    restored
+}
+
+/// 校验并恢复备份。`passphrase` 为创建加密备份时的主密码。
+pub fn restore_backup(
+    app: &AppHandle,
+    path: &Path,
+    mode: RestoreMode,
+    passphrase: Option<&str>,
+) -> Result<RestoreReport, String> {
+    let config_dir = app.path().app_config_dir().map_err(|e| e.to_string())?;
+    let data_dir = app.path().app_data_dir().map_err(|e| e.to_string())?;
+    let history = app.state::<history::HistoryStore>();
+    // 启用加密时恢复的数据需要用当前的数据密钥重新加密
+    let current_key = vault::database_key(&config_dir)?;
+
+    let file = File::open(path).map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+    let mut archive = ZipArchive::new(file).map_err(|_| "This file is not an AI Window backup".to_string())?;
+    let manifest = read_manifest(&mut archive)?;
+
+    let settings_bytes = read_json_entry(&mut archive, manifest_file(&manifest, SETTINGS_ENTRY)?)?;
+    let mut settings: serde_json::Map<String, serde_json::Value> =
+        serde_json::from_slice(&settings_bytes).map_err(|e| format!("The backup's settings are invalid: {}", e))?;
+    // 较早版本创建的备份先升级到当前的设置结构
+    settings::upgrade_entries(&mut settings)?;
+
+    let backup_prompts = match manifest.files.iter().find(|file| file.path == PROMPTS_ENTRY) {
+        Some(file) => {
+            let bytes = read_json_entry(&mut archive, file)?;
+            let prompts = prompts::read_prompts(&String::from_utf8_lossy(&bytes))
+                .map_err(|e| format!("The backup's prompt library is invalid: {}", e))?;
+            Some(prompts)
+        }
+        None => None,
+    };
+
+    let backup_key = if manifest.encrypted {
+        let vault_text = read_json_entry(&mut archive, manifest_file(&manifest, VAULT_ENTRY)?)?;
+        let passphrase = passphrase.ok_or_else(|| {
+            "This backup is encrypted. Enter the master passphrase that was in use when it was created.".to_string()
+        })?;
+        Some(vault::unwrap_vault_text(&String::from_utf8_lossy(&vault_text), passphrase)?)
+    } else {
+        None
+    };
+    let source_key = backup_key.as_ref().map(|key| key.database_key());
+
+    let history_copy = data_dir.join("restore-history.db.tmp");
+    let result = (|| -> Result<(u32, u32, u32, u32), String> {
+        let mut history_file = File::create(&history_copy).map_err(|e| e.to_string())?;
+        read_verified(&mut archive, manifest_file(&manifest, HISTORY_ENTRY)?, &mut history_file)?;
+        drop(history_file);
+
+        // 密钥先用备份的数据密钥解密，再按当前的加密状态重新加密
+        let current_enabled = current_key.is_some();
+        vault::map_secret_values(&mut settings, |value| {
+            let plain = match &backup_key {
+                Some(key) => vault::reveal_with_key(value, key)?,
+                None => value.to_string(),
+            };
+            if current_enabled {
+                vault::seal_secret(&plain)
+            } else {
+                Ok(plain)
+            }
+        })?;
+
+        let source_key = source_key.as_ref().map(|key| key.as_str());
+        let current_key = current_key.as_ref().map(|key| key.as_str());
+        // 替换模式先准备好恢复的历史数据库，设置保存之后才替换当前的数据库
+        let (added, skipped) = match mode {
+            RestoreMode::Replace => (history.prepare_replace_from(&history_copy, source_key, current_key)?, 0),
+            RestoreMode::Merge => {
+                let merged = history.merge_from(&history_copy, source_key)?;
+                (merged.added, merged.skipped)
+            }
+        };
+
+        let service = app.state::<settings::SettingsService>();
+        let store = service.store();
+        let previous_entries = store.entries();
+        let previous_shortcut = service.get().shortcut;
+        let settings_restored = match mode {
+            RestoreMode::Replace => {
+                keep_current_secrets(&mut settings, &store);
+                let count = settings.len() as u32;
+                store.clear();
+                for (key, value) in settings {
+                    store.set(key, value);
+                }
+                count
+            }
+            RestoreMode::Merge => merge_settings(settings, &store),
+        };
+
+        // 与 save_settings 相同：快捷键改变时重新注册，无法注册时保留原来的快捷键
+        let restored_shortcut = settings::Settings::from_store(&store).shortcut;
+        let shortcut_changed = restored_shortcut != previous_shortcut;
+        if shortcut_changed && register_shortcut(app.clone(), restored_shortcut).is_err() {
+            let _ = register_shortcut(app.clone(), previous_shortcut.clone());
+            store.set("shortcut", previous_shortcut.clone());
+        }
+
+        let committed = service.commit(app).and_then(|()| match mode {
+            RestoreMode::Replace => history.commit_reencrypt(current_key),
+            RestoreMode::Merge => Ok(()),
+        });
+        if let Err(e) = committed {
+            // 恢复原来的设置和快捷键，历史数据库保持不变
+            history.discard_reencrypt();
+            store.clear();
+            for (key, value) in previous_entries {
+                store.set(key, value);
+            }
+            let _ = service.commit(app);
+            if shortcut_changed {
+                let _ = register_shortcut(app.clone(), previous_shortcut);
+            }
+            return Err(e);
+        }
+
+        let library = app.state::<prompts::PromptLibrary>();
+        let prompts_restored = match (backup_prompts, mode) {
+            (Some(prompts), RestoreMode::Replace) => {
+                let count = prompts.len() as u32;
+                library.replace_all(prompts)?;
+                count
+            }
+            (Some(prompts), RestoreMode::Merge) => {
+                let report = library.merge(prompts)?;
+                report.added + report.updated
+            }
+            (None, _) => 0,
+        };
+        Ok((added, skipped, settings_restored, prompts_restored))
+    })();
+    let _ = std::fs::remove_file(&history_copy);
+    let (conversations_added, conversations_skipped, settings_restored, prompts_restored) = result?;
+
+    SecurityLogger::log_security_event_with_file(
+        app,
+        &format!("Backup restored ({:?}) from {}", mode, path.display()),
+        "INFO",
+    );
+    Ok(RestoreReport {
+        mode,
+        manifest,
+        conversations_added,
+        conversations_skipped,
+        settings_restored,
+        prompts_restored,
+    })
+}
@@ -0,0 +1,174 @@
+// --- Context Compaction ---
+//
+// 对话接近模型的上下文窗口时，用模型生成的摘要替换最早的若干轮对话。
+// 固定的消息和最近 N 轮对话始终原样保留；压缩记录在历史中，可以查看和撤销。
+// token 数按字符粗略估算：拉丁文字约 4 个字符一个 token，CJK 字符每字一个 token。
+
+use tauri::{AppHandle, Emitter};
+
+use crate::history::{self, message_text, summary_prompt, Compaction, ContextEntry};
+use crate::settings::Settings;
+use crate::{
+    resolve_profile, send_chat_request, ApiError, ApiProfile, RequestOptions, SecurityLogger, CANCEL_REQUESTS,
+};
+
+/// 压缩完成后通知前端
+pub const CONVERSATION_COMPACTED_EVENT: &str = "conversation-compacted";
+/// 未配置上下文窗口时使用的默认值（token）
+pub const DEFAULT_CONTEXT_WINDOW: u32 = 128_000;
+/// 估算的上下文占用达到窗口的该比例时触发压缩
+pub const DEFAULT_THRESHOLD: f64 = 0.8;
+/// 默认原样保留的最近轮数（一轮从一条用户消息开始）
+pub const DEFAULT_KEEP_TURNS: usize = 4;
+/// 每个图片附件按固定 token 数估算
+const IMAGE_TOKENS: usize = 1_000;
+/// 每条消息的格式开销
+const MESSAGE_OVERHEAD_TOKENS: usize = 4;
+
+/// 压缩相关设置
+#[derive(Debug, Clone)]
+pub struct CompactionSettings {
+    pub enabled: bool,
+    pub context_window: u32,
+    pub threshold: f64,
+    pub keep_turns: usize,
+}
+
+impl CompactionSettings {
+    pub fn load(settings: &Settings) -> Self {
+        CompactionSettings {
+            enabled: settings.auto_compaction,
+            context_window: Some(settings.context_window_tokens)
+                .filter(|&v| v >= 1_000)
+                .unwrap_or(DEFAULT_CONTEXT_WINDOW),
+            threshold: Some(settings.compaction_threshold)
+                .filter(|v| (0.1..=1.0).contains(v))
+                .unwrap_or(DEFAULT_THRESHOLD),
+            keep_turns: settings.compaction_keep_turns as usize,
+        }
+    }
+}
+
+pub fn estimate_text_tokens(text: &str) -> usize {
+    let (wide, narrow) = text.chars().fold((0, 0), |(wide, narrow), c| {
+        if (c as u32) >= 0x2E80 { (wide + 1, narrow) } else { (wide, narrow + 1) }
+    });
+    wide + narrow.div_ceil(4)
+}
+
+/// 粗略估算消息列表占用的 token 数
+pub fn estimate_tokens<'a>(messages: impl IntoIterator<Item = &'a crate::ConversationMessage>) -> usize {
+    messages
+        .into_iter()
+        .map(|message| {
+            let attachments: usize = message
+                .attachments
+                .iter()
+                .map(|a| if a.attachment_type == "image" { IMAGE_TOKENS } else { estimate_text_tokens(&a.content) })
+                .sum();
+            MESSAGE_OVERHEAD_TOKENS + estimate_text_tokens(&message_text(&message.content)) + attachments
+        })
+        .sum()
+}
+
+/// 选出需要被摘要的消息：最近 `keep_turns` 轮之前、未固定的消息
+fn select_candidates(entries: &[ContextEntry], keep_turns: usize) -> Vec<&ContextEntry> {
+    let user_positions: Vec<usize> = entries
+        .iter()
+        .enumerate()
+        .filter(|(_, entry)| entry.message.role == "user")
+        .map(|(index, _)| index)
+        .collect();
+    let boundary = if keep_turns == 0 {
+        entries.len()
+    } else if user_positions.len() > keep_turns {
+        user_positions[user_positions.len() - keep_turns]
+    } else {
+        return Vec::new();
+    };
+    entries[..boundary].iter().filter(|entry| !entry.pinned).collect()
+}
+
+/// 之前的摘要放在最前面，新的摘要取代它
+fn transcript(previous_summary: Option<&str>, entries: &[&ContextEntry]) -> String {
+    let previous = previous_summary.map(|summary| format!("system: {}", summary_prompt(summary)));
+    previous
+        .into_iter()
+        .chain(entries.iter().map(|entry| {
+            let mut text = format!("{}: {}", entry.message.role, message_text(&entry.message.content).trim());
+            for attachment in &entry.message.attachments {
+                text.push_str(&format!("\n[attachment: {}]", attachment.name));
+            }
+            text
+        }))
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+/// 在需要时压缩对话上下文。`reserved_tokens` 为系统提示等额外占用的 token 数；
+/// 为 None 时表示手动压缩，忽略开关和阈值。没有可压缩的消息时返回 None。
+/// `profile` 为生成摘要使用的配置，确实需要压缩时才校验；自动压缩时配置无效只记录错误并跳过压缩。
+/// mock 配置不生成摘要，不进行压缩。
+pub async fn compact_if_needed(
+    app: &AppHandle,
+    history: &history::HistoryStore,
+    conversation_id: &str,
+    profile: ApiProfile,
+    settings: &CompactionSettings,
+    reserved_tokens: Option<usize>,
+    endpoint_allowlist: Vec<String>,
+) -> Result<Option<Compaction>, ApiError> {
+    // mock 服务会向界面推送流式事件，不能用来生成摘要
+    if profile.api_type == "mock" {
+        return Ok(None);
+    }
+    let context = history.context(conversation_id).map_err(ApiError::InternalError)?;
+    let entries = &context.entries;
+    if let Some(reserved_tokens) = reserved_tokens {
+        if !settings.enabled {
+            return Ok(None);
+        }
+        let summary_tokens =
+            context.summary.as_deref().map_or(0, |summary| estimate_text_tokens(&summary_prompt(summary)));
+        let used = reserved_tokens + summary_tokens + estimate_tokens(entries.iter().map(|entry| &entry.message));
+        if (used as f64) < settings.context_window as f64 * settings.threshold {
+            return Ok(None);
+        }
+    }
+
+    let candidates = select_candidates(entries, settings.keep_turns);
+    let message_ids: Vec<String> = candidates.iter().map(|entry| entry.message_id.clone()).collect();
+    // 只有一条消息时摘要并不会节省空间
+    if message_ids.len() < 2 {
+        return Ok(None);
+    }
+
+    let profile = match resolve_profile(app, profile, &endpoint_allowlist) {
+        Ok(profile) => profile,
+        Err(e) if reserved_tokens.is_some() => {
+            SecurityLogger::log_error(app, &format!("Skipping context compaction, the summary profile is invalid: {:?}", e));
+            return Ok(None);
+        }
+        Err(e) => return Err(e),
+    };
+
+    let prompt = format!(
+        "The following is the beginning of a long conversation between a user and an assistant. \
+         Write a concise summary that preserves everything needed to continue the conversation: \
+         the user's goals, key facts, code, file names, decisions made, errors encountered and open questions. \
+         Write in the same language as the conversation. Reply with the summary only.\n\n{}",
+        transcript(context.summary.as_deref(), &candidates)
+    );
+    let messages = vec![serde_json::json!({ "role": "user", "content": prompt })];
+    let options = RequestOptions { endpoint_allowlist, cancel: Some(&CANCEL_REQUESTS), ..Default::default() };
+    let summary = send_chat_request(app, &profile, messages, options).await?.trim().to_string();
+    if summary.is_empty() {
+        return Err(ApiError::ApiResponseError("The model returned an empty summary".to_string()));
+    }
+
+    let compaction = history
+        .record_compaction(conversation_id, &summary, Some(profile.model_name.clone()), &message_ids)
+        .map_err(ApiError::InternalError)?;
+    let _ = app.emit(CONVERSATION_COMPACTED_EVENT, &compaction);
+    Ok(Some(compaction))
+}
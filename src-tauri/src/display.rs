@@ -0,0 +1,18 @@
+// --- Display Sanitization ---
+//
+// 模型回答在发送和存储时保持原样，只在渲染到界面之前经过 HTML 清理。
+// 前端先用 marked 把 Markdown 转成 HTML，再交给这里用 ammonia 去除脚本、事件属性和危险链接。
+
+/// 清理渲染后的 HTML，只保留安全的标签和属性
+pub fn sanitize_html(html: &str) -> String {
+    let mut builder = ammonia::Builder::default();
+    builder
+        // 代码块的语言标记用于语法高亮
+        .add_tag_attributes("code", &["class"])
+        .add_tag_attributes("pre", &["class"])
+        .add_tag_attributes("span", &["class"])
+        // GFM 任务列表的复选框
+        .add_tags(&["input"])
+        .add_tag_attributes("input", &["type", "checked", "disabled"]);
+    builder.clean(html).to_string()
+}
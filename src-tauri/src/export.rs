@@ -0,0 +1,378 @@
+// --- Conversation Export ---
+//
+// 将历史中的对话（当前激活分支）导出为 Markdown、JSON、自包含 HTML、DOCX 和 PDF。
+// 导出内容包含每条消息的角色、时间、模型和附件名称；HTML 会以 data URL 内嵌图片附件。
+// 文件先写入同目录下的临时文件再重命名，避免中途失败留下损坏的导出文件。
+
+use std::fs::File;
+use std::io::{Cursor, Write};
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+use crate::history::{message_text, Conversation, StoredMessage};
+
+/// PDF 页面尺寸（A4）与版式，单位为毫米
+const PAGE_WIDTH_MM: f32 = 210.0;
+const PAGE_HEIGHT_MM: f32 = 297.0;
+const PAGE_MARGIN_MM: f32 = 18.0;
+const PDF_FONT_SIZE: f32 = 10.0;
+const PDF_LINE_HEIGHT_MM: f32 = 5.0;
+/// 每行可容纳的宽度（以半角字符计，全角字符占两个）
+const PDF_LINE_WIDTH: usize = 92;
+
+/// 可以显示中日韩文字的常见系统字体；都找不到时退回到内置的 Helvetica
+const PDF_FONT_CANDIDATES: &[&str] = &[
+    "C:\\Windows\\Fonts\\simhei.ttf",
+    "/Library/Fonts/Arial Unicode.ttf",
+    "/System/Library/Fonts/Supplemental/Arial Unicode.ttf",
+    "/usr/share/fonts/truetype/droid/DroidSansFallbackFull.ttf",
+    "/usr/share/fonts/truetype/wqy/wqy-microhei.ttf",
+    "/usr/share/fonts/truetype/arphic/uming.ttf",
+];
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, TS)]
+#[serde(rename_all = "lowercase")]
+#[ts(export)]
+pub enum ExportFormat {
+    Markdown,
+    Json,
+    Html,
+    Docx,
+    Pdf,
+}
+
+impl ExportFormat {
+    pub const ALL: [ExportFormat; 5] = [
+        ExportFormat::Markdown,
+        ExportFormat::Json,
+        ExportFormat::Html,
+        ExportFormat::Docx,
+        ExportFormat::Pdf,
+    ];
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Markdown => "md",
+            ExportFormat::Json => "json",
+            ExportFormat::Html => "html",
+            ExportFormat::Docx => "docx",
+            ExportFormat::Pdf => "pdf",
+        }
+    }
+
+    pub fn filter_name(self) -> &'static str {
+        match self {
+            ExportFormat::Markdown => "Markdown",
+            ExportFormat::Json => "JSON",
+            ExportFormat::Html => "HTML",
+            ExportFormat::Docx => "Word Document",
+            ExportFormat::Pdf => "PDF",
+        }
+    }
+
+    /// 根据保存对话框返回的文件扩展名推断格式
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "md" | "markdown" => Some(ExportFormat::Markdown),
+            "json" => Some(ExportFormat::Json),
+            "html" | "htm" => Some(ExportFormat::Html),
+            "docx" => Some(ExportFormat::Docx),
+            "pdf" => Some(ExportFormat::Pdf),
+            _ => None,
+        }
+    }
+}
+
+/// 根据对话标题生成默认文件名，去掉文件系统不允许的字符
+pub fn default_file_name(conversation: &Conversation, format: ExportFormat) -> String {
+    let title: String = conversation
+        .title
+        .chars()
+        .map(|c| if c.is_control() || "<>:\"/\\|?*".contains(c) { '_' } else { c })
+        .take(80)
+        .collect();
+    let title = title.trim();
+    let stem = if title.is_empty() { "conversation" } else { title };
+    format!("{}.{}", stem, format.extension())
+}
+
+/// 渲染对话并原子地写入 `path`
+pub fn export_conversation(conversation: &Conversation, format: ExportFormat, path: &Path) -> Result<(), String> {
+    let bytes = match format {
+        ExportFormat::Markdown => render_markdown(conversation).into_bytes(),
+        ExportFormat::Json => serde_json::to_vec_pretty(conversation).map_err(|e| e.to_string())?,
+        ExportFormat::Html => render_html(conversation).into_bytes(),
+        ExportFormat::Docx => render_docx(conversation)?,
+        ExportFormat::Pdf => render_pdf(conversation)?,
+    };
+    write_atomically(path, &bytes)
+}
+
+fn write_atomically(path: &Path, bytes: &[u8]) -> Result<(), String> {
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| "Export path must include a file name".to_string())?
+        .to_string_lossy();
+    let temp_path: PathBuf = path.with_file_name(format!(".{}.tmp", file_name));
+
+    let result = (|| {
+        let mut file = File::create(&temp_path)?;
+        file.write_all(bytes)?;
+        file.sync_all()?;
+        std::fs::rename(&temp_path, path)
+    })();
+    if let Err(e) = result {
+        let _ = std::fs::remove_file(&temp_path);
+        return Err(format!("Failed to write export to {}: {}", path.display(), e));
+    }
+    Ok(())
+}
+
+fn role_label(role: &str) -> &str {
+    match role {
+        "user" => "User",
+        "assistant" => "Assistant",
+        "system" => "System",
+        other => other,
+    }
+}
+
+/// 消息的元数据行：时间和模型
+fn message_meta(message: &StoredMessage) -> String {
+    let time = message.created_at.format("%Y-%m-%d %H:%M:%S UTC");
+    match &message.model {
+        Some(model) => format!("{} | {}", time, model),
+        None => time.to_string(),
+    }
+}
+
+fn attachment_names(message: &StoredMessage) -> Vec<&str> {
+    message.attachments.iter().map(|a| a.name.as_str()).collect()
+}
+
+fn conversation_meta(conversation: &Conversation) -> String {
+    format!(
+        "Created {} | Updated {} | {} messages",
+        conversation.created_at.format("%Y-%m-%d %H:%M UTC"),
+        conversation.updated_at.format("%Y-%m-%d %H:%M UTC"),
+        conversation.messages.len()
+    )
+}
+
+fn render_markdown(conversation: &Conversation) -> String {
+    let mut out = format!("# {}\n\n_{}_\n", conversation.title, conversation_meta(conversation));
+    for message in &conversation.messages {
+        out.push_str(&format!("\n---\n\n## {}\n\n_{}_\n\n", role_label(&message.role), message_meta(message)));
+        let names = attachment_names(message);
+        if !names.is_empty() {
+            out.push_str(&format!("**Attachments:** {}\n\n", names.join(", ")));
+        }
+        out.push_str(message_text(&message.content).trim_end());
+        out.push('\n');
+    }
+    out
+}
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// 只内嵌光栅图片的 data URL，其它内容（包括 SVG）不会出现在导出的 HTML 中
+fn is_embeddable_image(data_url: &str) -> bool {
+    ["data:image/png;base64,", "data:image/jpeg;base64,", "data:image/gif;base64,", "data:image/webp;base64,"]
+        .iter()
+        .any(|prefix| data_url.starts_with(prefix))
+}
+
+const HTML_STYLE: &str = "body{font-family:-apple-system,'Segoe UI','PingFang SC','Microsoft YaHei',sans-serif;max-width:820px;margin:2rem auto;padding:0 1rem;color:#1f2328;line-height:1.6}\
+header{border-bottom:1px solid #d0d7de;margin-bottom:1rem}\
+.meta{color:#656d76;font-size:.85rem}\
+.message{border:1px solid #d0d7de;border-radius:8px;padding:.75rem 1rem;margin:1rem 0}\
+.message.user{background:#f6f8fa}\
+.role{font-weight:600}\
+.content{white-space:pre-wrap;word-wrap:break-word}\
+.attachments{font-size:.85rem;color:#656d76}\
+.attachments img{display:block;max-width:100%;margin:.5rem 0;border-radius:4px}";
+
+fn render_html(conversation: &Conversation) -> String {
+    let title = escape_html(&conversation.title);
+    let mut out = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{title}</title>\n<style>{HTML_STYLE}</style>\n</head>\n<body>\n<header><h1>{title}</h1><p class=\"meta\">{}</p></header>\n",
+        escape_html(&conversation_meta(conversation))
+    );
+    for message in &conversation.messages {
+        out.push_str(&format!(
+            "<section class=\"message {}\">\n<div class=\"role\">{}</div>\n<div class=\"meta\">{}</div>\n",
+            escape_html(&message.role),
+            escape_html(role_label(&message.role)),
+            escape_html(&message_meta(message))
+        ));
+        if !message.attachments.is_empty() {
+            out.push_str("<div class=\"attachments\">\n");
+            for attachment in &message.attachments {
+                out.push_str(&format!("<div>📎 {}</div>\n", escape_html(&attachment.name)));
+                if attachment.attachment_type == "image" && is_embeddable_image(&attachment.content) {
+                    out.push_str(&format!(
+                        "<img src=\"{}\" alt=\"{}\">\n",
+                        escape_html(&attachment.content),
+                        escape_html(&attachment.name)
+                    ));
+                }
+            }
+            out.push_str("</div>\n");
+        }
+        out.push_str(&format!(
+            "<div class=\"content\">{}</div>\n</section>\n",
+            escape_html(message_text(&message.content).trim_end())
+        ));
+    }
+    out.push_str("</body>\n</html>\n");
+    out
+}
+
+fn render_docx(conversation: &Conversation) -> Result<Vec<u8>, String> {
+    use docx_rs::{Docx, Paragraph, Run};
+
+    let meta_run = |text: String| Run::new().add_text(text).italic().size(18).color("656D76");
+
+    let mut docx = Docx::new()
+        .add_paragraph(Paragraph::new().add_run(Run::new().add_text(&conversation.title).bold().size(36)))
+        .add_paragraph(Paragraph::new().add_run(meta_run(conversation_meta(conversation))));
+
+    for message in &conversation.messages {
+        docx = docx
+            .add_paragraph(Paragraph::new())
+            .add_paragraph(Paragraph::new().add_run(Run::new().add_text(role_label(&message.role)).bold().size(26)))
+            .add_paragraph(Paragraph::new().add_run(meta_run(message_meta(message))));
+        let names = attachment_names(message);
+        if !names.is_empty() {
+            docx = docx.add_paragraph(
+                Paragraph::new()
+                    .add_run(Run::new().add_text("Attachments: ").bold())
+                    .add_run(Run::new().add_text(names.join(", "))),
+            );
+        }
+        // Word 不会把换行符当作段落分隔，逐行生成段落
+        for line in message_text(&message.content).trim_end().lines() {
+            docx = docx.add_paragraph(Paragraph::new().add_run(Run::new().add_text(line)));
+        }
+    }
+
+    let mut buffer = Cursor::new(Vec::new());
+    docx.build()
+        .pack(&mut buffer)
+        .map_err(|e| format!("Failed to build DOCX: {}", e))?;
+    Ok(buffer.into_inner())
+}
+
+fn is_wide(c: char) -> bool {
+    matches!(c,
+        '\u{1100}'..='\u{115F}'
+        | '\u{2E80}'..='\u{A4CF}'
+        | '\u{AC00}'..='\u{D7A3}'
+        | '\u{F900}'..='\u{FAFF}'
+        | '\u{FE30}'..='\u{FE4F}'
+        | '\u{FF00}'..='\u{FF60}'
+        | '\u{FFE0}'..='\u{FFE6}'
+        | '\u{1F300}'..='\u{1FAFF}'
+        | '\u{20000}'..='\u{3FFFD}'
+    )
+}
+
+/// 按显示宽度折行（全角字符占两个宽度）
+fn wrap_line(line: &str, width: usize) -> Vec<String> {
+    if line.is_empty() {
+        return vec![String::new()];
+    }
+    let mut lines = Vec::new();
+    let mut current = String::new();
+    let mut current_width = 0;
+    for c in line.chars() {
+        let char_width = if is_wide(c) { 2 } else { 1 };
+        if current_width + char_width > width {
+            lines.push(std::mem::take(&mut current));
+            current_width = 0;
+        }
+        current.push(c);
+        current_width += char_width;
+    }
+    lines.push(current);
+    lines
+}
+
+fn render_pdf(conversation: &Conversation) -> Result<Vec<u8>, String> {
+    use printpdf::{BuiltinFont, Mm, PdfDocument};
+
+    let (doc, first_page, first_layer) =
+        PdfDocument::new(&conversation.title, Mm(PAGE_WIDTH_MM), Mm(PAGE_HEIGHT_MM), "Layer 1");
+
+    let external_font = PDF_FONT_CANDIDATES
+        .iter()
+        .filter_map(|path| File::open(path).ok())
+        .find_map(|file| doc.add_external_font(file).ok());
+    // 内置字体只支持 WinAnsi 编码，其它字符替换为问号
+    let unicode_font = external_font.is_some();
+    let font = match external_font {
+        Some(font) => font,
+        None => doc
+            .add_builtin_font(BuiltinFont::Helvetica)
+            .map_err(|e| format!("Failed to load PDF font: {}", e))?,
+    };
+    let encode = |text: &str| -> String {
+        if unicode_font {
+            text.to_string()
+        } else {
+            text.chars().map(|c| if (c as u32) < 0x100 { c } else { '?' }).collect()
+        }
+    };
+
+    // 字号不同的行按比例调整每行的宽度
+    let mut lines: Vec<(String, f32)> = Vec::new();
+    let mut push_wrapped = |text: &str, size: f32| {
+        let width = (PDF_LINE_WIDTH as f32 * PDF_FONT_SIZE / size) as usize;
+        lines.extend(wrap_line(text, width).into_iter().map(|l| (l, size)));
+    };
+    push_wrapped(&conversation.title, 16.0);
+    push_wrapped(&conversation_meta(conversation), 8.0);
+    for message in &conversation.messages {
+        push_wrapped("", PDF_FONT_SIZE);
+        push_wrapped(&format!("{} - {}", role_label(&message.role), message_meta(message)), 11.0);
+        let names = attachment_names(message);
+        if !names.is_empty() {
+            push_wrapped(&format!("Attachments: {}", names.join(", ")), 8.0);
+        }
+        for line in message_text(&message.content).trim_end().lines() {
+            push_wrapped(line, PDF_FONT_SIZE);
+        }
+    }
+
+    let mut layer = doc.get_page(first_page).get_layer(first_layer);
+    let mut y = PAGE_HEIGHT_MM - PAGE_MARGIN_MM;
+    for (text, size) in lines {
+        if y < PAGE_MARGIN_MM {
+            let (page, page_layer) = doc.add_page(Mm(PAGE_WIDTH_MM), Mm(PAGE_HEIGHT_MM), "Layer 1");
+            layer = doc.get_page(page).get_layer(page_layer);
+            y = PAGE_HEIGHT_MM - PAGE_MARGIN_MM;
+        }
+        if !text.is_empty() {
+            layer.use_text(encode(&text), size, Mm(PAGE_MARGIN_MM), Mm(y), &font);
+        }
+        y -= PDF_LINE_HEIGHT_MM.max(size * 0.5);
+    }
+
+    doc.save_to_bytes().map_err(|e| format!("Failed to build PDF: {}", e))
+}
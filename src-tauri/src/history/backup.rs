@@ -0,0 +1,119 @@
+// --- History Backup & Restore ---
+//
+// 备份时把整个历史数据库（包括附件内容）导出为一个独立的文件；恢复时先把备份中的数据库
+// 迁移到当前版本，再整体替换当前数据库，或把当前数据库中没有的对话合并进来。
+// 合并以对话为单位：已存在的对话（相同 id 或相同的导入来源）保持不变，不会逐条合并消息。
+
+use std::path::Path;
+
+use rusqlite::{params, Connection};
+
+use super::{export_database, migrate, open_raw, pending_path, search, HistoryStore, MIGRATIONS};
+
+#[derive(Debug, Clone)]
+pub struct MergeReport {
+    /// 合并进来的对话数
+    pub added: u32,
+    /// 当前历史中已存在而跳过的对话数
+    pub skipped: u32,
+}
+
+/// 打开备份中的数据库并迁移到当前版本。由更新版本的应用创建的备份无法恢复。
+fn open_backup(path: &Path, key: Option<&str>) -> Result<Connection, String> {
+    let mut conn = open_raw(path, key).map_err(|e| format!("The backup's history database cannot be opened: {}", e))?;
+    let version: i64 = conn.query_row("PRAGMA user_version", [], |row| row.get(0)).map_err(|e| e.to_string())?;
+    if version > MIGRATIONS.len() as i64 {
+        return Err("The backup was created by a newer version of AI Window".to_string());
+    }
+    migrate(&mut conn).map_err(|e| format!("Failed to upgrade the backup's history database: {}", e))?;
+    Ok(conn)
+}
+
+impl HistoryStore {
+    /// 把数据库完整复制到 `dest`，`key` 为 None 时复制为明文
+    pub fn export_copy(&self, dest: &Path, key: Option<&str>) -> Result<(), String> {
+        self.with_conn(|conn| export_database(conn, dest, key))
+            .map_err(|e| format!("Failed to copy history: {}", e))
+    }
+
+    pub fn conversation_count(&self) -> Result<u32, String> {
+        self.with_conn(|conn| conn.query_row("SELECT COUNT(*) FROM conversations", [], |row| row.get(0)))
+    }
+
+    /// 准备用备份中的数据库替换当前数据库。`source_key` 为备份数据库的密钥，`key` 为替换后使用的密钥。
+    /// 与重新加密相同，调用 `commit_reencrypt` 后才替换，`discard_reencrypt` 放弃。返回恢复的对话数。
+    pub fn prepare_replace_from(
+        &self,
+        source: &Path,
+        source_key: Option<&str>,
+        key: Option<&str>,
+    ) -> Result<u32, String> {
+        let conn = open_backup(source, source_key)?;
+        let count: u32 =
+            conn.query_row("SELECT COUNT(*) FROM conversations", [], |row| row.get(0)).map_err(|e| e.to_string())?;
+        // 用当前的密钥重新导出，之后像重新加密一样替换数据库文件
+        export_database(&conn, &pending_path(&self.path), key)
+            .map_err(|e| format!("Failed to prepare the restored history: {}", e))?;
+        Ok(count)
+    }
+
+    /// 把备份中当前历史没有的对话（连同文件夹、标签和压缩记录）合并到当前数据库
+    pub fn merge_from(&self, source: &Path, source_key: Option<&str>) -> Result<MergeReport, String> {
+        // 先单独打开一次以完成迁移，保证两边的表结构（包括列顺序）一致
+        let total: u32 = open_backup(source, source_key)?
+            .query_row("SELECT COUNT(*) FROM conversations", [], |row| row.get(0))
+            .map_err(|e| e.to_string())?;
+
+        let added = self.with_conn(|conn| {
+            conn.execute(
+                "ATTACH DATABASE ?1 AS backup KEY ?2",
+                params![source.to_string_lossy(), source_key.unwrap_or("")],
+            )?;
+            let merged = merge_attached(conn);
+            let detached = conn.execute("DETACH DATABASE backup", []);
+            let added = merged?;
+            detached?;
+            if added > 0 {
+                search::rebuild_index(conn)?;
+            }
+            Ok(added)
+        })?;
+        Ok(MergeReport { added, skipped: total - added })
+    }
+}
+
+/// 在一个事务中复制附加的 `backup` 数据库中的新对话，返回复制的对话数
+fn merge_attached(conn: &mut Connection) -> rusqlite::Result<u32> {
+    let tx = conn.transaction()?;
+    tx.execute_batch(
+        "CREATE TEMP TABLE restore_ids AS
+             SELECT b.id FROM backup.conversations b
+             WHERE NOT EXISTS (SELECT 1 FROM main.conversations c WHERE c.id = b.id)
+               AND (b.import_id IS NULL OR NOT EXISTS (
+                   SELECT 1 FROM main.conversations c
+                   WHERE c.import_source = b.import_source AND c.import_id = b.import_id
+               ));
+
+         INSERT OR IGNORE INTO main.folders SELECT * FROM backup.folders;
+         INSERT INTO main.conversations
+             SELECT * FROM backup.conversations WHERE id IN (SELECT id FROM temp.restore_ids);
+         INSERT INTO main.messages
+             SELECT * FROM backup.messages WHERE conversation_id IN (SELECT id FROM temp.restore_ids);
+         INSERT INTO main.attachments (message_id, position, name, attachment_type, content)
+             SELECT a.message_id, a.position, a.name, a.attachment_type, a.content
+             FROM backup.attachments a JOIN backup.messages m ON m.id = a.message_id
+             WHERE m.conversation_id IN (SELECT id FROM temp.restore_ids)
+             ORDER BY a.id;
+         INSERT INTO main.conversation_tags
+             SELECT * FROM backup.conversation_tags WHERE conversation_id IN (SELECT id FROM temp.restore_ids);
+         INSERT INTO main.compactions
+             SELECT * FROM backup.compactions WHERE conversation_id IN (SELECT id FROM temp.restore_ids);
+         INSERT INTO main.compacted_messages
+             SELECT cm.* FROM backup.compacted_messages cm JOIN backup.compactions c ON c.id = cm.compaction_id
+             WHERE c.conversation_id IN (SELECT id FROM temp.restore_ids);",
+    )?;
+    let added: u32 = tx.query_row("SELECT COUNT(*) FROM temp.restore_ids", [], |row| row.get(0))?;
+    tx.execute_batch("DROP TABLE temp.restore_ids")?;
+    tx.commit()?;
+    Ok(added)
+}
@@ -0,0 +1,76 @@
+// --- Conversation Branches ---
+//
+// 消息以树的形式保存：每条消息记录 `parent_id`，对话记录当前激活分支的末端 `active_leaf_id`。
+// 编辑过去的消息或重新生成回答会在同一父节点下创建兄弟节点，原来的分支保持不变。
+
+use rusqlite::{params, Connection, OptionalExtension};
+
+pub fn active_leaf(conn: &Connection, conversation_id: &str) -> rusqlite::Result<Option<String>> {
+    conn.query_row(
+        "SELECT active_leaf_id FROM conversations WHERE id = ?1",
+        params![conversation_id],
+        |row| row.get(0),
+    )
+    .optional()
+    .map(Option::flatten)
+}
+
+pub fn set_active_leaf(conn: &Connection, conversation_id: &str, leaf_id: &str) -> rusqlite::Result<()> {
+    conn.execute(
+        "UPDATE conversations SET active_leaf_id = ?1 WHERE id = ?2",
+        params![leaf_id, conversation_id],
+    )?;
+    Ok(())
+}
+
+/// 查找消息的父节点；消息不属于该对话时返回 None
+pub fn parent_of(conn: &Connection, conversation_id: &str, message_id: &str) -> rusqlite::Result<Option<Option<String>>> {
+    conn.query_row(
+        "SELECT parent_id FROM messages WHERE id = ?1 AND conversation_id = ?2",
+        params![message_id, conversation_id],
+        |row| row.get(0),
+    )
+    .optional()
+}
+
+/// 从根节点到 `leaf_id` 的消息 id 列表。`leaf_id` 不属于该对话时返回空列表。
+pub fn path_to(conn: &Connection, conversation_id: &str, leaf_id: &str) -> rusqlite::Result<Vec<String>> {
+    let mut stmt = conn.prepare(
+        "WITH RECURSIVE path(id, parent_id, depth) AS (
+             SELECT id, parent_id, 0 FROM messages WHERE id = ?1 AND conversation_id = ?2
+             UNION ALL
+             SELECT m.id, m.parent_id, path.depth + 1 FROM messages m JOIN path ON m.id = path.parent_id
+             WHERE m.conversation_id = ?2
+         )
+         SELECT id FROM path ORDER BY depth DESC",
+    )?;
+    let ids = stmt.query_map(params![leaf_id, conversation_id], |row| row.get(0))?;
+    ids.collect()
+}
+
+/// 沿着最新的子节点一直向下，找到该分支当前的末端
+pub fn deepest_leaf(conn: &Connection, message_id: &str) -> rusqlite::Result<String> {
+    let mut current = message_id.to_string();
+    loop {
+        let child: Option<String> = conn
+            .query_row(
+                "SELECT id FROM messages WHERE parent_id = ?1 ORDER BY seq DESC LIMIT 1",
+                params![current],
+                |row| row.get(0),
+            )
+            .optional()?;
+        match child {
+            Some(child) => current = child,
+            None => return Ok(current),
+        }
+    }
+}
+
+/// 同一父节点下的所有消息 id（包括自身），按创建顺序排列
+pub fn siblings(conn: &Connection, conversation_id: &str, parent_id: Option<&str>) -> rusqlite::Result<Vec<String>> {
+    let mut stmt = conn.prepare(
+        "SELECT id FROM messages WHERE conversation_id = ?1 AND parent_id IS ?2 ORDER BY seq",
+    )?;
+    let ids = stmt.query_map(params![conversation_id, parent_id], |row| row.get(0))?;
+    ids.collect()
+}
@@ -0,0 +1,263 @@
+// --- Context Compaction Records ---
+//
+// 压缩把激活分支上较早的消息替换为一条摘要，只影响发送给模型的上下文：原消息仍保存在树中，
+// 界面照常显示。每次压缩记录摘要和被替换的消息，新的压缩会取代同一分支上之前仍然有效的压缩，
+// 撤销时恢复被取代的压缩。压缩只在被替换的消息都位于当前分支上时生效，切换到其它分支不受影响。
+
+use std::collections::{HashMap, HashSet};
+
+use chrono::{DateTime, Utc};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::Serialize;
+use ts_rs::TS;
+
+use super::{branches, load_path, new_id, HistoryStore};
+use crate::ConversationMessage;
+
+#[derive(Serialize, Debug, Clone, TS)]
+#[ts(export)]
+pub struct Compaction {
+    pub id: String,
+    pub conversation_id: String,
+    pub summary: String,
+    /// 生成摘要所用的模型
+    pub model: Option<String>,
+    /// 被摘要替换的消息
+    pub message_ids: Vec<String>,
+    pub created_at: DateTime<Utc>,
+    /// 被撤销的时间
+    pub undone_at: Option<DateTime<Utc>>,
+    /// 被之后的压缩取代时为取代它的压缩 id
+    pub superseded_by: Option<String>,
+    /// 最后一条被替换的消息。它在当前分支上时，所有被替换的消息都在当前分支上
+    pub leaf_id: Option<String>,
+}
+
+/// 发送给模型的上下文中的一条消息
+#[derive(Debug, Clone)]
+pub struct ContextEntry {
+    pub message_id: String,
+    pub pinned: bool,
+    pub message: ConversationMessage,
+}
+
+/// 发送给模型的上下文：有效压缩的摘要和没有被替换的消息
+#[derive(Debug, Clone, Default)]
+pub struct Context {
+    pub summary: Option<String>,
+    pub entries: Vec<ContextEntry>,
+}
+
+/// 摘要在系统提示中的呈现方式。摘要并入开头的系统提示，不在对话中间插入系统消息。
+pub fn summary_prompt(summary: &str) -> String {
+    format!("Summary of the earlier conversation:\n\n{}", summary)
+}
+
+fn load_compaction(conn: &Connection, id: &str) -> rusqlite::Result<Option<Compaction>> {
+    let compaction = conn
+        .query_row(
+            "SELECT id, conversation_id, summary, model, created_at, undone_at, superseded_by, leaf_id
+             FROM compactions WHERE id = ?1",
+            params![id],
+            |row| {
+                Ok(Compaction {
+                    id: row.get(0)?,
+                    conversation_id: row.get(1)?,
+                    summary: row.get(2)?,
+                    model: row.get(3)?,
+                    message_ids: Vec::new(),
+                    created_at: row.get(4)?,
+                    undone_at: row.get(5)?,
+                    superseded_by: row.get(6)?,
+                    leaf_id: row.get(7)?,
+                })
+            },
+        )
+        .optional()?;
+    let Some(mut compaction) = compaction else {
+        return Ok(None);
+    };
+    let mut stmt = conn.prepare(
+        "SELECT cm.message_id FROM compacted_messages cm JOIN messages m ON m.id = cm.message_id
+         WHERE cm.compaction_id = ?1 ORDER BY m.seq",
+    )?;
+    compaction.message_ids = stmt.query_map(params![id], |row| row.get(0))?.collect::<rusqlite::Result<_>>()?;
+    Ok(Some(compaction))
+}
+
+/// 激活分支上的消息 id
+fn active_path(conn: &Connection, conversation_id: &str) -> rusqlite::Result<HashSet<String>> {
+    match branches::active_leaf(conn, conversation_id)? {
+        Some(leaf) => Ok(branches::path_to(conn, conversation_id, &leaf)?.into_iter().collect()),
+        None => Ok(HashSet::new()),
+    }
+}
+
+/// 在 `path` 上有效的压缩（未撤销、未被取代），返回 id 和摘要
+fn compactions_on_path(
+    conn: &Connection,
+    conversation_id: &str,
+    path: &HashSet<String>,
+) -> rusqlite::Result<Vec<(String, String)>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, summary, leaf_id FROM compactions
+         WHERE conversation_id = ?1 AND undone_at IS NULL AND superseded_by IS NULL",
+    )?;
+    let rows = stmt.query_map(params![conversation_id], |row| {
+        Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get::<_, Option<String>>(2)?))
+    })?;
+    let mut compactions = Vec::new();
+    for row in rows {
+        let (id, summary, leaf_id) = row?;
+        if leaf_id.is_some_and(|leaf_id| path.contains(&leaf_id)) {
+            compactions.push((id, summary));
+        }
+    }
+    Ok(compactions)
+}
+
+impl HistoryStore {
+    pub fn set_pinned(&self, conversation_id: &str, message_id: &str, pinned: bool) -> Result<(), String> {
+        let changed = self.with_conn(|conn| {
+            conn.execute(
+                "UPDATE messages SET pinned = ?1 WHERE id = ?2 AND conversation_id = ?3",
+                params![pinned, message_id, conversation_id],
+            )
+        })?;
+        if changed == 0 {
+            return Err(format!("Message '{}' not found in conversation '{}'", message_id, conversation_id));
+        }
+        Ok(())
+    }
+
+    /// 激活分支应用有效压缩之后的上下文：被替换的消息移除，摘要单独返回，由调用方并入系统提示
+    pub fn context(&self, conversation_id: &str) -> Result<Context, String> {
+        self.with_conn(|conn| {
+            let Some(leaf) = branches::active_leaf(conn, conversation_id)? else {
+                return Ok(Context::default());
+            };
+            let path = load_path(conn, conversation_id, &leaf)?;
+            let path_ids: HashSet<String> = path.iter().map(|message| message.id.clone()).collect();
+
+            let mut replaced: HashMap<String, (String, String)> = HashMap::new();
+            let mut stmt = conn.prepare("SELECT message_id FROM compacted_messages WHERE compaction_id = ?1")?;
+            for (compaction_id, summary) in compactions_on_path(conn, conversation_id, &path_ids)? {
+                let message_ids = stmt.query_map(params![compaction_id], |row| row.get::<_, String>(0))?;
+                for message_id in message_ids {
+                    replaced.insert(message_id?, (compaction_id.clone(), summary.clone()));
+                }
+            }
+
+            let mut inserted = HashSet::new();
+            let mut summaries = Vec::new();
+            let mut entries = Vec::with_capacity(path.len());
+            for message in path {
+                match replaced.get(&message.id) {
+                    Some((compaction_id, summary)) => {
+                        if inserted.insert(compaction_id.clone()) {
+                            summaries.push(summary.as_str());
+                        }
+                    }
+                    None => entries.push(ContextEntry {
+                        message_id: message.id,
+                        pinned: message.pinned,
+                        message: ConversationMessage {
+                            role: message.role,
+                            content: message.content,
+                            attachments: message.attachments,
+                        },
+                    }),
+                }
+            }
+            let summary = Some(summaries.join("\n\n")).filter(|summary| !summary.is_empty());
+            Ok(Context { summary, entries })
+        })
+    }
+
+    /// 记录一次压缩，并让激活分支上此前仍然有效的压缩被它取代
+    pub fn record_compaction(
+        &self,
+        conversation_id: &str,
+        summary: &str,
+        model: Option<String>,
+        message_ids: &[String],
+    ) -> Result<Compaction, String> {
+        let id = new_id();
+        self.with_conn(|conn| {
+            let tx = conn.transaction()?;
+            tx.execute(
+                "INSERT INTO compactions (id, conversation_id, summary, model, created_at) VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![id, conversation_id, summary, model, Utc::now()],
+            )?;
+            // 被取代的压缩所替换的消息也归入新的压缩，它的摘要已经包含在新摘要中。
+            // 其它分支上的压缩保持不变。
+            let path = active_path(&tx, conversation_id)?;
+            for (previous, _) in compactions_on_path(&tx, conversation_id, &path)? {
+                tx.execute(
+                    "INSERT OR IGNORE INTO compacted_messages (compaction_id, message_id)
+                     SELECT ?1, message_id FROM compacted_messages WHERE compaction_id = ?2",
+                    params![id, previous],
+                )?;
+                tx.execute("UPDATE compactions SET superseded_by = ?1 WHERE id = ?2", params![id, previous])?;
+            }
+            {
+                let mut stmt = tx.prepare(
+                    "INSERT OR IGNORE INTO compacted_messages (compaction_id, message_id)
+                     SELECT ?1, id FROM messages WHERE id = ?2 AND conversation_id = ?3",
+                )?;
+                for message_id in message_ids {
+                    stmt.execute(params![id, message_id, conversation_id])?;
+                }
+            }
+            tx.execute(
+                "UPDATE compactions SET leaf_id = (
+                     SELECT cm.message_id FROM compacted_messages cm JOIN messages m ON m.id = cm.message_id
+                     WHERE cm.compaction_id = ?1 ORDER BY m.seq DESC LIMIT 1
+                 ) WHERE id = ?1",
+                params![id],
+            )?;
+            let compaction = load_compaction(&tx, &id)?;
+            tx.commit()?;
+            Ok(compaction)
+        })?
+        .ok_or_else(|| "Failed to record compaction".to_string())
+    }
+
+    /// 撤销一次压缩，并恢复被它取代的压缩
+    pub fn undo_compaction(&self, conversation_id: &str, compaction_id: &str) -> Result<Compaction, String> {
+        self.with_conn(|conn| {
+            let tx = conn.transaction()?;
+            let changed = tx.execute(
+                "UPDATE compactions SET undone_at = ?1
+                 WHERE id = ?2 AND conversation_id = ?3 AND undone_at IS NULL AND superseded_by IS NULL",
+                params![Utc::now(), compaction_id, conversation_id],
+            )?;
+            if changed == 0 {
+                return Ok(None);
+            }
+            tx.execute(
+                "UPDATE compactions SET superseded_by = NULL WHERE superseded_by = ?1",
+                params![compaction_id],
+            )?;
+            let compaction = load_compaction(&tx, compaction_id)?;
+            tx.commit()?;
+            Ok(compaction)
+        })?
+        .ok_or_else(|| format!("No active compaction '{}' in conversation '{}'", compaction_id, conversation_id))
+    }
+
+    /// 对话的所有压缩记录，最新的在前
+    pub fn list_compactions(&self, conversation_id: &str) -> Result<Vec<Compaction>, String> {
+        self.with_conn(|conn| {
+            let mut stmt =
+                conn.prepare("SELECT id FROM compactions WHERE conversation_id = ?1 ORDER BY created_at DESC")?;
+            let ids: Vec<String> =
+                stmt.query_map(params![conversation_id], |row| row.get(0))?.collect::<rusqlite::Result<_>>()?;
+            let mut compactions = Vec::with_capacity(ids.len());
+            for id in ids {
+                compactions.extend(load_compaction(conn, &id)?);
+            }
+            Ok(compactions)
+        })
+    }
+}
@@ -0,0 +1,580 @@
+// --- History Import ---
+//
+// 从其它应用的导出文件导入对话：ChatGPT 的 `conversations.json`、Claude 的导出、本应用的 JSON 导出，
+// 以及旧版本聊天窗口导出的 `{exportDate, messageCount, messages}`（作为一个对话导入）。
+// 各种格式先转换成统一的 `ImportedConversation`（消息树），再在一个事务中写入历史数据库。
+// 每个对话单独解析和写入，某个对话出错不会影响其它对话；来源 id 记录在对话上，用于识别重复导入。
+
+use std::collections::{HashMap, HashSet};
+
+use chrono::{DateTime, TimeZone, Utc};
+use rusqlite::{params, OptionalExtension};
+use serde::Serialize;
+use serde_json::Value;
+use ts_rs::TS;
+
+use super::{branches, insert_attachments, new_id, search, Conversation, HistoryStore};
+use crate::Attachment;
+
+/// Claude 导出中表示“没有父消息”的占位 id
+const CLAUDE_ROOT_PARENT: &str = "00000000-0000-4000-8000-000000000000";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportSource {
+    ChatGpt,
+    Claude,
+    AiWindow,
+    /// 旧版本聊天窗口的“导出 JSON”
+    ChatExport,
+}
+
+impl ImportSource {
+    fn as_str(self) -> &'static str {
+        match self {
+            ImportSource::ChatGpt => "chatgpt",
+            ImportSource::Claude => "claude",
+            ImportSource::AiWindow => "ai-window",
+            ImportSource::ChatExport => "ai-window-chat",
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, TS)]
+#[serde(rename_all = "lowercase")]
+#[ts(export)]
+pub enum ImportStatus {
+    Imported,
+    Duplicate,
+    Failed,
+}
+
+/// 单个对话的导入结果
+#[derive(Serialize, Debug, Clone, TS)]
+#[ts(export)]
+pub struct ImportOutcome {
+    pub title: String,
+    /// 导出文件中的对话 id
+    pub source_id: Option<String>,
+    pub status: ImportStatus,
+    /// 新建（或重复时已存在）的对话 id
+    pub conversation_id: Option<String>,
+    pub message_count: u32,
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Debug, Clone, TS)]
+#[ts(export)]
+pub struct ImportReport {
+    /// 识别出的导出格式
+    pub source: String,
+    pub imported: u32,
+    pub duplicates: u32,
+    pub failed: u32,
+    pub conversations: Vec<ImportOutcome>,
+}
+
+struct ImportedMessage {
+    source_id: String,
+    parent_source_id: Option<String>,
+    role: String,
+    text: String,
+    attachments: Vec<Attachment>,
+    model: Option<String>,
+    created_at: Option<DateTime<Utc>>,
+}
+
+struct ImportedConversation {
+    source_id: String,
+    title: String,
+    created_at: Option<DateTime<Utc>>,
+    updated_at: Option<DateTime<Utc>>,
+    messages: Vec<ImportedMessage>,
+    /// 导出时激活的分支末端；为空时使用最新的分支
+    active_leaf_source_id: Option<String>,
+}
+
+/// 根据内容判断导出格式
+fn detect_source(root: &Value) -> Option<ImportSource> {
+    let sample = match root {
+        Value::Array(items) => items.first()?,
+        Value::Object(_) => root,
+        _ => return None,
+    };
+    if sample.get("mapping").is_some() {
+        Some(ImportSource::ChatGpt)
+    } else if sample.get("chat_messages").is_some() {
+        Some(ImportSource::Claude)
+    } else if sample.get("messages").is_some() && sample.get("id").is_some() {
+        Some(ImportSource::AiWindow)
+    } else if sample.get("messages").is_some() && sample.get("exportDate").is_some() {
+        Some(ImportSource::ChatExport)
+    } else {
+        None
+    }
+}
+
+fn unix_time(value: &Value) -> Option<DateTime<Utc>> {
+    let seconds = value.as_f64()?;
+    Utc.timestamp_millis_opt((seconds * 1000.0) as i64).single()
+}
+
+fn iso_time(value: &Value) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(value.as_str()?).ok().map(|t| t.with_timezone(&Utc))
+}
+
+fn str_field(value: &Value, key: &str) -> Option<String> {
+    value.get(key).and_then(Value::as_str).map(str::to_string)
+}
+
+/// 图片引用：data URL 直接作为图片附件保存，其它引用（例如 ChatGPT 的 file-service 指针）
+/// 保存为 `image_ref`，保留名称和原始地址以便日后查找
+fn image_attachment(name: String, reference: String) -> Attachment {
+    let attachment_type = if reference.starts_with("data:image/") { "image" } else { "image_ref" };
+    Attachment {
+        name,
+        attachment_type: attachment_type.to_string(),
+        content: reference,
+        preview_url: None,
+    }
+}
+
+fn parse_chatgpt(conversation: &Value) -> Result<ImportedConversation, String> {
+    let mapping = conversation
+        .get("mapping")
+        .and_then(Value::as_object)
+        .ok_or("missing 'mapping'")?;
+    let source_id = str_field(conversation, "conversation_id")
+        .or_else(|| str_field(conversation, "id"))
+        .ok_or("missing conversation id")?;
+
+    // 没有内容的节点（根节点、隐藏的系统消息、工具调用）会被跳过，其子节点挂到最近的保留祖先上
+    let mut kept: HashMap<&str, ImportedMessage> = HashMap::new();
+    for (node_id, node) in mapping {
+        let Some(message) = node.get("message").filter(|m| !m.is_null()) else {
+            continue;
+        };
+        let role = message["author"]["role"].as_str().unwrap_or_default();
+        if !matches!(role, "user" | "assistant" | "system") {
+            continue;
+        }
+        if message["metadata"]["is_visually_hidden_from_conversation"].as_bool() == Some(true) {
+            continue;
+        }
+
+        let mut texts = Vec::new();
+        let mut attachments = Vec::new();
+        let content = &message["content"];
+        if let Some(text) = content["text"].as_str() {
+            texts.push(text.to_string());
+        }
+        for part in content["parts"].as_array().into_iter().flatten() {
+            match part {
+                Value::String(text) if !text.is_empty() => texts.push(text.clone()),
+                Value::Object(_) if part["content_type"] == "image_asset_pointer" => {
+                    let pointer = part["asset_pointer"].as_str().unwrap_or_default().to_string();
+                    let name = pointer.rsplit('/').next().unwrap_or("image").to_string();
+                    attachments.push(image_attachment(name, pointer));
+                }
+                _ => {}
+            }
+        }
+        // 上传的文件只保留名称和文件 id；已作为图片指针出现的文件不重复记录
+        for file in message["metadata"]["attachments"].as_array().into_iter().flatten() {
+            let file_id = str_field(file, "id").unwrap_or_default();
+            if !file_id.is_empty() && attachments.iter().any(|a: &Attachment| a.content.ends_with(&file_id)) {
+                continue;
+            }
+            attachments.push(Attachment {
+                name: str_field(file, "name").unwrap_or_else(|| "file".to_string()),
+                attachment_type: "file_ref".to_string(),
+                content: file_id,
+                preview_url: None,
+            });
+        }
+        let text = texts.join("\n");
+        if text.trim().is_empty() && attachments.is_empty() {
+            continue;
+        }
+
+        kept.insert(
+            node_id.as_str(),
+            ImportedMessage {
+                source_id: node_id.clone(),
+                parent_source_id: None,
+                role: role.to_string(),
+                text,
+                attachments,
+                model: message["metadata"]["model_slug"].as_str().map(str::to_string),
+                created_at: unix_time(&message["create_time"]),
+            },
+        );
+    }
+
+    let nearest_kept = |start: Option<&str>| -> Option<String> {
+        let mut current = start;
+        let mut visited = HashSet::new();
+        while let Some(id) = current {
+            if !visited.insert(id) {
+                break;
+            }
+            if kept.contains_key(id) {
+                return Some(id.to_string());
+            }
+            current = mapping.get(id).and_then(|node| node["parent"].as_str());
+        }
+        None
+    };
+
+    let parents: Vec<(String, Option<String>)> = kept
+        .keys()
+        .map(|id| {
+            let parent = mapping[*id]["parent"].as_str();
+            (id.to_string(), nearest_kept(parent))
+        })
+        .collect();
+    let active_leaf_source_id = nearest_kept(conversation["current_node"].as_str());
+
+    let mut messages: Vec<ImportedMessage> = kept.into_values().collect();
+    let parents: HashMap<String, Option<String>> = parents.into_iter().collect();
+    for message in &mut messages {
+        message.parent_source_id = parents.get(&message.source_id).cloned().flatten();
+    }
+
+    Ok(ImportedConversation {
+        source_id,
+        title: str_field(conversation, "title").unwrap_or_default(),
+        created_at: unix_time(&conversation["create_time"]),
+        updated_at: unix_time(&conversation["update_time"]),
+        messages,
+        active_leaf_source_id,
+    })
+}
+
+fn parse_claude(conversation: &Value) -> Result<ImportedConversation, String> {
+    let source_id = str_field(conversation, "uuid").ok_or("missing 'uuid'")?;
+    let chat_messages = conversation
+        .get("chat_messages")
+        .and_then(Value::as_array)
+        .ok_or("missing 'chat_messages'")?;
+
+    // 旧版导出没有 parent_message_uuid，按顺序串成单一分支
+    let has_tree = chat_messages.iter().any(|m| m.get("parent_message_uuid").is_some());
+    let mut messages = Vec::new();
+    let mut previous: Option<String> = None;
+    for (index, message) in chat_messages.iter().enumerate() {
+        let id = str_field(message, "uuid").unwrap_or_else(|| format!("{}-{}", source_id, index));
+        let role = match message["sender"].as_str() {
+            Some("human") => "user",
+            Some("assistant") => "assistant",
+            other => return Err(format!("message {} has unknown sender {:?}", index, other)),
+        };
+
+        let mut text = message["text"].as_str().unwrap_or_default().to_string();
+        if text.is_empty() {
+            text = message["content"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter(|part| part["type"] == "text")
+                .filter_map(|part| part["text"].as_str())
+                .collect::<Vec<_>>()
+                .join("\n");
+        }
+
+        let mut attachments = Vec::new();
+        for attachment in message["attachments"].as_array().into_iter().flatten() {
+            attachments.push(Attachment {
+                name: str_field(attachment, "file_name").unwrap_or_else(|| "attachment".to_string()),
+                attachment_type: "text".to_string(),
+                content: str_field(attachment, "extracted_content").unwrap_or_default(),
+                preview_url: None,
+            });
+        }
+        for file in message["files"].as_array().into_iter().flatten() {
+            let name = str_field(file, "file_name").unwrap_or_else(|| "file".to_string());
+            let reference = str_field(file, "preview_url").or_else(|| str_field(file, "file_uuid")).unwrap_or_default();
+            attachments.push(image_attachment(name, reference));
+        }
+
+        let parent_source_id = if has_tree {
+            str_field(message, "parent_message_uuid").filter(|p| p != CLAUDE_ROOT_PARENT)
+        } else {
+            previous.clone()
+        };
+        previous = Some(id.clone());
+        messages.push(ImportedMessage {
+            source_id: id,
+            parent_source_id,
+            role: role.to_string(),
+            text,
+            attachments,
+            model: None,
+            created_at: iso_time(&message["created_at"]),
+        });
+    }
+
+    Ok(ImportedConversation {
+        source_id,
+        title: str_field(conversation, "name").unwrap_or_default(),
+        created_at: iso_time(&conversation["created_at"]),
+        updated_at: iso_time(&conversation["updated_at"]),
+        messages,
+        active_leaf_source_id: None,
+    })
+}
+
+/// 本应用的 JSON 导出只包含导出时的激活分支，按顺序重建父子关系
+fn parse_ai_window(conversation: &Value) -> Result<ImportedConversation, String> {
+    let conversation: Conversation = serde_json::from_value(conversation.clone()).map_err(|e| e.to_string())?;
+    let mut previous: Option<String> = None;
+    let messages = conversation
+        .messages
+        .into_iter()
+        .map(|message| {
+            let parent_source_id = message.parent_id.clone().or_else(|| previous.clone());
+            previous = Some(message.id.clone());
+            ImportedMessage {
+                source_id: message.id,
+                parent_source_id,
+                role: message.role,
+                text: super::message_text(&message.content),
+                attachments: message.attachments,
+                model: message.model,
+                created_at: Some(message.created_at),
+            }
+        })
+        .collect();
+    Ok(ImportedConversation {
+        source_id: conversation.id,
+        title: conversation.title,
+        created_at: Some(conversation.created_at),
+        updated_at: Some(conversation.updated_at),
+        messages,
+        active_leaf_source_id: conversation.active_leaf_id,
+    })
+}
+
+/// 旧版本聊天窗口的导出只有消息列表，没有 id 和时间；导出时间作为来源 id 识别重复导入。
+/// 旧版本每条消息最多一个 `attachment`，较新的版本为 `attachments` 数组。
+fn parse_chat_export(export: &Value) -> Result<ImportedConversation, String> {
+    let export_date = str_field(export, "exportDate").ok_or("missing 'exportDate'")?;
+    let items = export.get("messages").and_then(Value::as_array).ok_or("missing 'messages'")?;
+
+    let mut messages = Vec::new();
+    for (index, item) in items.iter().enumerate() {
+        let role = match item["role"].as_str() {
+            Some(role @ ("user" | "assistant" | "system")) => role,
+            other => return Err(format!("message {} has unknown role {:?}", index, other)),
+        };
+        let mut attachments = Vec::new();
+        let listed = item["attachments"].as_array().into_iter().flatten();
+        for attachment in listed.chain(item.get("attachment").filter(|a| a.is_object())) {
+            let name = str_field(attachment, "name").unwrap_or_else(|| "attachment".to_string());
+            let content = str_field(attachment, "content").unwrap_or_default();
+            attachments.push(match attachment["type"].as_str() {
+                Some("image") => image_attachment(name, content),
+                _ => Attachment { name, attachment_type: "text".to_string(), content, preview_url: None },
+            });
+        }
+        let id = format!("{}-{}", export_date, index);
+        messages.push(ImportedMessage {
+            parent_source_id: index.checked_sub(1).map(|previous| format!("{}-{}", export_date, previous)),
+            source_id: id,
+            role: role.to_string(),
+            text: super::message_text(&item["content"]),
+            attachments,
+            model: None,
+            created_at: None,
+        });
+    }
+
+    let exported_at = iso_time(&export["exportDate"]);
+    Ok(ImportedConversation {
+        source_id: export_date,
+        title: String::new(),
+        created_at: exported_at,
+        updated_at: exported_at,
+        messages,
+        active_leaf_source_id: None,
+    })
+}
+
+/// 按父节点在前的顺序排列消息，兄弟节点按时间排序；父节点缺失的消息作为根节点
+fn topological_order(messages: Vec<ImportedMessage>) -> Vec<ImportedMessage> {
+    let ids: HashSet<String> = messages.iter().map(|m| m.source_id.clone()).collect();
+    let mut children: HashMap<Option<String>, Vec<ImportedMessage>> = HashMap::new();
+    for mut message in messages {
+        if message.parent_source_id.as_ref().is_some_and(|p| !ids.contains(p) || *p == message.source_id) {
+            message.parent_source_id = None;
+        }
+        children.entry(message.parent_source_id.clone()).or_default().push(message);
+    }
+    for siblings in children.values_mut() {
+        siblings.sort_by_key(|m| m.created_at);
+    }
+
+    let mut ordered = Vec::new();
+    let mut stack: Vec<ImportedMessage> = children.remove(&None).unwrap_or_default().into_iter().rev().collect();
+    while let Some(message) = stack.pop() {
+        if let Some(kids) = children.remove(&Some(message.source_id.clone())) {
+            stack.extend(kids.into_iter().rev());
+        }
+        ordered.push(message);
+    }
+    // 剩下的消息处于环中，无法确定位置，直接丢弃
+    ordered
+}
+
+impl HistoryStore {
+    /// 写入一个导入的对话。已经导入过（或本身就来自本数据库）时返回已存在的对话 id 和 false。
+    fn import_conversation(
+        &self,
+        source: ImportSource,
+        imported: ImportedConversation,
+    ) -> Result<(String, bool, u32), String> {
+        let messages = topological_order(imported.messages);
+        let message_count = messages.len() as u32;
+        let now = Utc::now();
+        let fallback_time = imported.created_at.or(imported.updated_at).unwrap_or(now);
+        let created_at = imported.created_at.unwrap_or(fallback_time);
+        let updated_at = imported
+            .updated_at
+            .or_else(|| messages.iter().filter_map(|m| m.created_at).max())
+            .unwrap_or(created_at);
+        let title = Some(imported.title.trim().to_string())
+            .filter(|t| !t.is_empty())
+            .or_else(|| {
+                messages
+                    .iter()
+                    .find(|m| m.role == "user")
+                    .map(|m| m.text.lines().next().unwrap_or_default().chars().take(60).collect())
+            })
+            .filter(|t: &String| !t.trim().is_empty())
+            .unwrap_or_else(|| super::DEFAULT_TITLE.to_string());
+
+        self.with_conn(|conn| {
+            let tx = conn.transaction()?;
+            let existing: Option<String> = tx
+                .query_row(
+                    "SELECT id FROM conversations
+                     WHERE (import_source = ?1 AND import_id = ?2) OR (?1 = 'ai-window' AND id = ?2)",
+                    params![source.as_str(), imported.source_id],
+                    |row| row.get(0),
+                )
+                .optional()?;
+            if let Some(existing) = existing {
+                return Ok((existing, false, message_count));
+            }
+
+            let conversation_id = new_id();
+            tx.execute(
+                "INSERT INTO conversations (id, title, profile, created_at, updated_at, import_source, import_id)
+                 VALUES (?1, ?2, '', ?3, ?4, ?5, ?6)",
+                params![conversation_id, title, created_at, updated_at, source.as_str(), imported.source_id],
+            )?;
+
+            let mut id_map: HashMap<String, String> = HashMap::new();
+            let mut last_time = created_at;
+            for (seq, message) in messages.iter().enumerate() {
+                let id = new_id();
+                let parent_id = message.parent_source_id.as_ref().and_then(|p| id_map.get(p)).cloned();
+                // 缺少时间的消息沿用前一条消息的时间
+                let message_time = message.created_at.unwrap_or(last_time);
+                last_time = message_time;
+                let content = Value::String(message.text.clone());
+                let model = (message.role == "assistant").then(|| message.model.clone()).flatten();
+                tx.execute(
+                    "INSERT INTO messages (id, conversation_id, parent_id, seq, role, content, model, created_at)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                    params![id, conversation_id, parent_id, (seq + 1) as i64, message.role, content, model, message_time],
+                )?;
+                insert_attachments(&tx, &id, &message.attachments)?;
+                search::index_message(&tx, &id, &content, &message.attachments)?;
+                id_map.insert(message.source_id.clone(), id);
+            }
+
+            let active_leaf = match imported.active_leaf_source_id.as_ref().and_then(|id| id_map.get(id)) {
+                Some(leaf) => Some(leaf.clone()),
+                // 默认选择最后一个根节点下最新的分支
+                None => match messages.iter().rev().find(|m| m.parent_source_id.is_none()) {
+                    Some(root) => Some(branches::deepest_leaf(&tx, &id_map[&root.source_id])?),
+                    None => None,
+                },
+            };
+            if let Some(leaf) = active_leaf {
+                branches::set_active_leaf(&tx, &conversation_id, &leaf)?;
+            }
+            tx.commit()?;
+            Ok((conversation_id, true, message_count))
+        })
+    }
+
+    /// 导入一个导出文件的内容，返回每个对话的导入结果
+    pub fn import_export(&self, data: &str) -> Result<ImportReport, String> {
+        let root: Value = serde_json::from_str(data).map_err(|e| format!("Import file is not valid JSON: {}", e))?;
+        let source = detect_source(&root)
+            .ok_or("Unrecognized export format. Expected a ChatGPT, Claude or AI Window export.")?;
+        let items = match root {
+            Value::Array(items) => items,
+            single => vec![single],
+        };
+
+        let mut report = ImportReport {
+            source: source.as_str().to_string(),
+            imported: 0,
+            duplicates: 0,
+            failed: 0,
+            conversations: Vec::with_capacity(items.len()),
+        };
+        for item in &items {
+            let title = str_field(item, "title").or_else(|| str_field(item, "name")).unwrap_or_default();
+            let parsed = match source {
+                ImportSource::ChatGpt => parse_chatgpt(item),
+                ImportSource::Claude => parse_claude(item),
+                ImportSource::AiWindow => parse_ai_window(item),
+                ImportSource::ChatExport => parse_chat_export(item),
+            };
+            let outcome = match parsed {
+                Ok(conversation) => {
+                    let source_id = Some(conversation.source_id.clone());
+                    match self.import_conversation(source, conversation) {
+                        Ok((conversation_id, inserted, message_count)) => ImportOutcome {
+                            title,
+                            source_id,
+                            status: if inserted { ImportStatus::Imported } else { ImportStatus::Duplicate },
+                            conversation_id: Some(conversation_id),
+                            message_count,
+                            error: None,
+                        },
+                        Err(e) => ImportOutcome {
+                            title,
+                            source_id,
+                            status: ImportStatus::Failed,
+                            conversation_id: None,
+                            message_count: 0,
+                            error: Some(e),
+                        },
+                    }
+                }
+                Err(e) => ImportOutcome {
+                    title,
+                    source_id: str_field(item, "conversation_id")
+                        .or_else(|| str_field(item, "uuid"))
+                        .or_else(|| str_field(item, "id"))
+                        .or_else(|| str_field(item, "exportDate")),
+                    status: ImportStatus::Failed,
+                    conversation_id: None,
+                    message_count: 0,
+                    error: Some(e),
+                },
+            };
+            match outcome.status {
+                ImportStatus::Imported => report.imported += 1,
+                ImportStatus::Duplicate => report.duplicates += 1,
+                ImportStatus::Failed => report.failed += 1,
+            }
+            report.conversations.push(outcome);
+        }
+        Ok(report)
+    }
+}
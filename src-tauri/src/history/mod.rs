@@ -0,0 +1,819 @@
+// --- Conversation History ---
+//
+// 对话历史保存在应用数据目录下的 SQLite 数据库中。数据库结构通过 `MIGRATIONS` 管理，
+// 每个迁移只追加不修改，已执行的版本号记录在 `PRAGMA user_version` 中。
+// 启用静态加密时数据库由 SQLCipher 加密，解锁之前连接保持关闭。
+
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use chrono::{DateTime, Utc};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+use crate::{Attachment, ConversationMessage};
+
+mod backup;
+mod branches;
+mod compaction;
+mod import;
+mod organize;
+mod retention;
+mod search;
+
+pub use backup::MergeReport;
+pub use compaction::{summary_prompt, Compaction, ContextEntry};
+pub use import::{ImportOutcome, ImportReport, ImportStatus};
+pub use organize::{normalize_tag, ConversationFilter, Folder, TagCount};
+pub use retention::RetentionPolicy;
+pub use search::{SearchHit, SearchQuery};
+
+/// 数据库文件名（位于应用数据目录）
+pub const DATABASE_FILE: &str = "history.db";
+/// 新建对话的默认标题
+pub const DEFAULT_TITLE: &str = "Untitled";
+/// 分页列表每页的最大条数
+const MAX_PAGE_SIZE: u32 = 200;
+
+/// 一次数据库迁移
+struct Migration {
+    sql: &'static str,
+    /// 需要在 Rust 中完成的数据迁移（例如重建索引），在 SQL 之后于同一事务内执行
+    post: Option<fn(&Connection) -> rusqlite::Result<()>>,
+}
+
+/// 按顺序执行的数据库迁移，下标 + 1 即为迁移后的版本号
+const MIGRATIONS: &[Migration] = &[
+    // v1: 对话、消息和附件
+    Migration {
+        sql: r#"
+    CREATE TABLE conversations (
+        id TEXT PRIMARY KEY,
+        title TEXT NOT NULL,
+        profile TEXT NOT NULL DEFAULT '',
+        created_at TEXT NOT NULL,
+        updated_at TEXT NOT NULL
+    );
+    CREATE INDEX idx_conversations_updated_at ON conversations(updated_at DESC);
+
+    CREATE TABLE messages (
+        id TEXT PRIMARY KEY,
+        conversation_id TEXT NOT NULL REFERENCES conversations(id) ON DELETE CASCADE,
+        seq INTEGER NOT NULL,
+        role TEXT NOT NULL,
+        content TEXT NOT NULL,
+        created_at TEXT NOT NULL
+    );
+    CREATE INDEX idx_messages_conversation ON messages(conversation_id, seq);
+
+    CREATE TABLE attachments (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        message_id TEXT NOT NULL REFERENCES messages(id) ON DELETE CASCADE,
+        position INTEGER NOT NULL,
+        name TEXT NOT NULL,
+        attachment_type TEXT NOT NULL,
+        content TEXT NOT NULL
+    );
+    CREATE INDEX idx_attachments_message ON attachments(message_id, position);
+    "#,
+        post: None,
+    },
+    // v2: 记录回答所用的模型，并建立全文索引
+    Migration {
+        sql: r#"
+    ALTER TABLE messages ADD COLUMN model TEXT;
+    CREATE INDEX idx_messages_created_at ON messages(created_at);
+
+    CREATE VIRTUAL TABLE message_fts USING fts5(
+        message_id UNINDEXED,
+        body,
+        tokenize = 'unicode61 remove_diacritics 2'
+    );
+    CREATE TRIGGER messages_fts_delete AFTER DELETE ON messages BEGIN
+        DELETE FROM message_fts WHERE message_id = old.id;
+    END;
+    "#,
+        post: Some(search::rebuild_index),
+    },
+    // v3: 消息树。已有消息按顺序串成单一分支
+    Migration {
+        sql: r#"
+    ALTER TABLE messages ADD COLUMN parent_id TEXT REFERENCES messages(id) ON DELETE CASCADE;
+    ALTER TABLE conversations ADD COLUMN active_leaf_id TEXT;
+    CREATE INDEX idx_messages_parent ON messages(parent_id, seq);
+
+    UPDATE messages SET parent_id = (
+        SELECT p.id FROM messages p
+        WHERE p.conversation_id = messages.conversation_id AND p.seq < messages.seq
+        ORDER BY p.seq DESC LIMIT 1
+    );
+    UPDATE conversations SET active_leaf_id = (
+        SELECT m.id FROM messages m WHERE m.conversation_id = conversations.id ORDER BY m.seq DESC LIMIT 1
+    );
+    "#,
+        post: None,
+    },
+    // v4: 记录导入对话的来源，用于识别重复导入
+    Migration {
+        sql: r#"
+    ALTER TABLE conversations ADD COLUMN import_source TEXT;
+    ALTER TABLE conversations ADD COLUMN import_id TEXT;
+    CREATE UNIQUE INDEX idx_conversations_import ON conversations(import_source, import_id)
+        WHERE import_id IS NOT NULL;
+    "#,
+        post: None,
+    },
+    // v5: 自动生成的滚动摘要。summary_message_count 为摘要覆盖的激活分支消息数
+    Migration {
+        sql: r#"
+    ALTER TABLE conversations ADD COLUMN summary TEXT;
+    ALTER TABLE conversations ADD COLUMN summary_message_count INTEGER NOT NULL DEFAULT 0;
+    "#,
+        post: None,
+    },
+    // v6: 固定消息和上下文压缩记录
+    Migration {
+        sql: r#"
+    ALTER TABLE messages ADD COLUMN pinned INTEGER NOT NULL DEFAULT 0;
+
+    CREATE TABLE compactions (
+        id TEXT PRIMARY KEY,
+        conversation_id TEXT NOT NULL REFERENCES conversations(id) ON DELETE CASCADE,
+        summary TEXT NOT NULL,
+        model TEXT,
+        created_at TEXT NOT NULL,
+        undone_at TEXT,
+        superseded_by TEXT
+    );
+    CREATE INDEX idx_compactions_conversation ON compactions(conversation_id, created_at);
+
+    CREATE TABLE compacted_messages (
+        compaction_id TEXT NOT NULL REFERENCES compactions(id) ON DELETE CASCADE,
+        message_id TEXT NOT NULL REFERENCES messages(id) ON DELETE CASCADE,
+        PRIMARY KEY (compaction_id, message_id)
+    );
+    "#,
+        post: None,
+    },
+    // v7: 文件夹、标签、置顶和归档
+    Migration {
+        sql: r#"
+    CREATE TABLE folders (
+        id TEXT PRIMARY KEY,
+        name TEXT NOT NULL,
+        created_at TEXT NOT NULL
+    );
+
+    ALTER TABLE conversations ADD COLUMN folder_id TEXT REFERENCES folders(id) ON DELETE SET NULL;
+    ALTER TABLE conversations ADD COLUMN pinned INTEGER NOT NULL DEFAULT 0;
+    ALTER TABLE conversations ADD COLUMN archived INTEGER NOT NULL DEFAULT 0;
+    CREATE INDEX idx_conversations_folder ON conversations(folder_id);
+
+    CREATE TABLE conversation_tags (
+        conversation_id TEXT NOT NULL REFERENCES conversations(id) ON DELETE CASCADE,
+        tag TEXT NOT NULL,
+        PRIMARY KEY (conversation_id, tag)
+    );
+    CREATE INDEX idx_conversation_tags_tag ON conversation_tags(tag);
+    "#,
+        post: None,
+    },
+    // v8: 压缩所在的分支。leaf_id 为最后一条被替换的消息
+    Migration {
+        sql: r#"
+    ALTER TABLE compactions ADD COLUMN leaf_id TEXT;
+    UPDATE compactions SET leaf_id = (
+        SELECT cm.message_id FROM compacted_messages cm JOIN messages m ON m.id = cm.message_id
+        WHERE cm.compaction_id = compactions.id ORDER BY m.seq DESC LIMIT 1
+    );
+    "#,
+        post: None,
+    },
+];
+
+#[derive(Serialize, Deserialize, Debug, Clone, TS)]
+#[ts(export)]
+pub struct StoredMessage {
+    pub id: String,
+    pub role: String,
+    #[ts(type = "any")]
+    pub content: serde_json::Value,
+    pub attachments: Vec<Attachment>,
+    /// 生成该回答的模型（仅助手消息）
+    pub model: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub parent_id: Option<String>,
+    /// 同一父节点下的所有消息 id（包括自身），用于在分支间切换
+    pub sibling_ids: Vec<String>,
+    /// 固定的消息在压缩上下文时始终原样保留
+    #[serde(default)]
+    pub pinned: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, TS)]
+#[ts(export)]
+pub struct Conversation {
+    pub id: String,
+    pub title: String,
+    pub profile: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    /// 自动生成的对话摘要
+    #[serde(default)]
+    pub summary: Option<String>,
+    #[serde(default)]
+    pub folder_id: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub pinned: bool,
+    #[serde(default)]
+    pub archived: bool,
+    pub active_leaf_id: Option<String>,
+    /// 当前激活分支上从根到末端的消息
+    pub messages: Vec<StoredMessage>,
+}
+
+#[derive(Serialize, Debug, Clone, TS)]
+#[ts(export)]
+pub struct ConversationSummary {
+    pub id: String,
+    pub title: String,
+    pub profile: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub summary: Option<String>,
+    pub folder_id: Option<String>,
+    pub tags: Vec<String>,
+    pub pinned: bool,
+    pub archived: bool,
+    /// 激活分支上的消息数，其它分支上的消息不计入
+    pub message_count: u32,
+}
+
+#[derive(Serialize, Debug, Clone, TS)]
+#[ts(export)]
+pub struct ConversationPage {
+    pub items: Vec<ConversationSummary>,
+    pub total: u32,
+    pub offset: u32,
+    pub limit: u32,
+}
+
+/// 由 Tauri 托管的历史存储，所有命令共用一个数据库连接
+pub struct HistoryStore {
+    path: PathBuf,
+    /// 未解锁（或已锁定）时为 None
+    conn: Mutex<Option<Connection>>,
+}
+
+/// 执行尚未应用的迁移
+fn migrate(conn: &mut Connection) -> rusqlite::Result<()> {
+    let version: i64 = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip(version.max(0) as usize) {
+        let tx = conn.transaction()?;
+        tx.execute_batch(migration.sql)?;
+        if let Some(post) = migration.post {
+            post(&tx)?;
+        }
+        tx.pragma_update(None, "user_version", (index + 1) as i64)?;
+        tx.commit()?;
+    }
+    Ok(())
+}
+
+fn new_id() -> String {
+    uuid::Uuid::new_v4().to_string()
+}
+
+/// 重新加密时临时文件的路径（`history.db.pending`）
+fn pending_path(path: &Path) -> PathBuf {
+    let mut file_name = path.file_name().unwrap_or_default().to_os_string();
+    file_name.push(".pending");
+    path.with_file_name(file_name)
+}
+
+/// 打开数据库连接并验证密钥。`key` 为 SQLCipher 原始密钥（`x'..'`），未加密时为 None
+fn open_raw(path: &Path, key: Option<&str>) -> rusqlite::Result<Connection> {
+    let conn = Connection::open(path)?;
+    if let Some(key) = key {
+        conn.pragma_update(None, "key", key)?;
+    }
+    // 密钥错误时读取 schema 会失败
+    conn.query_row("SELECT COUNT(*) FROM sqlite_master", [], |row| row.get::<_, i64>(0))?;
+    Ok(conn)
+}
+
+/// 上一次重新加密在替换文件前中断时，用当前密钥能打开的临时文件就是新的数据库
+fn finish_pending(path: &Path, key: Option<&str>) -> std::io::Result<()> {
+    let pending = pending_path(path);
+    if !pending.exists() {
+        return Ok(());
+    }
+    if open_raw(&pending, key).is_ok() {
+        replace_database(path, &pending)
+    } else {
+        std::fs::remove_file(&pending)
+    }
+}
+
+/// 把 `conn` 的完整内容（包括迁移版本号）导出到新文件 `dest`，`key` 为 None 时导出为明文。
+/// 失败时删除不完整的文件。
+fn export_database(conn: &Connection, dest: &Path, key: Option<&str>) -> rusqlite::Result<()> {
+    let _ = std::fs::remove_file(dest);
+    let version: i64 = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+    conn.execute("ATTACH DATABASE ?1 AS export KEY ?2", params![dest.to_string_lossy(), key.unwrap_or("")])?;
+    let exported = conn
+        .query_row("SELECT sqlcipher_export('export')", [], |_| Ok(()))
+        .and_then(|_| conn.pragma_update(Some("export"), "user_version", version));
+    let detached = conn.execute("DETACH DATABASE export", []);
+    if exported.is_err() {
+        let _ = std::fs::remove_file(dest);
+    }
+    exported.and(detached.map(|_| ()))
+}
+
+fn replace_database(path: &Path, pending: &Path) -> std::io::Result<()> {
+    for suffix in ["-wal", "-shm"] {
+        let mut file_name = path.file_name().unwrap_or_default().to_os_string();
+        file_name.push(suffix);
+        let _ = std::fs::remove_file(path.with_file_name(file_name));
+    }
+    std::fs::rename(pending, path)
+}
+
+impl HistoryStore {
+    /// 创建尚未打开的存储，调用 `unlock` 后才能使用
+    pub fn new(path: &Path) -> Self {
+        HistoryStore { path: path.to_path_buf(), conn: Mutex::new(None) }
+    }
+
+    /// 打开（或创建）数据库并执行迁移。`key` 为 SQLCipher 密钥，未启用加密时为 None
+    pub fn unlock(&self, key: Option<&str>) -> Result<(), String> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+        }
+        finish_pending(&self.path, key).map_err(|e| format!("Failed to finish re-encrypting history: {}", e))?;
+        let mut conn =
+            open_raw(&self.path, key).map_err(|e| format!("Failed to open history database: {}", e))?;
+        conn.pragma_update(None, "foreign_keys", true).map_err(|e| e.to_string())?;
+        // 删除的对话内容被覆盖，不会残留在数据库文件的空闲页中
+        conn.pragma_update(None, "secure_delete", true).map_err(|e| e.to_string())?;
+        conn.pragma_update_and_check(None, "journal_mode", "WAL", |_| Ok(()))
+            .map_err(|e| e.to_string())?;
+        migrate(&mut conn).map_err(|e| format!("Failed to migrate history database: {}", e))?;
+        *self.conn.lock().map_err(|_| "History database lock poisoned".to_string())? = Some(conn);
+        Ok(())
+    }
+
+    /// 关闭数据库连接，之后的操作都会失败直到再次解锁
+    pub fn lock(&self) {
+        if let Ok(mut conn) = self.conn.lock() {
+            *conn = None;
+        }
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.conn.lock().map(|conn| conn.is_some()).unwrap_or(false)
+    }
+
+    /// 用新的密钥把数据库导出到临时文件（`new_key` 为 None 时导出为明文），原数据库保持不变
+    pub fn prepare_reencrypt(&self, new_key: Option<&str>) -> Result<(), String> {
+        let pending = pending_path(&self.path);
+        self.with_conn(|conn| export_database(conn, &pending, new_key))
+            .map_err(|e| format!("Failed to re-encrypt history: {}", e))
+    }
+
+    /// 用 `prepare_reencrypt` 生成的文件替换数据库，并用新密钥重新打开
+    pub fn commit_reencrypt(&self, new_key: Option<&str>) -> Result<(), String> {
+        self.lock();
+        replace_database(&self.path, &pending_path(&self.path))
+            .map_err(|e| format!("Failed to replace history database: {}", e))?;
+        self.unlock(new_key)
+    }
+
+    /// 删除 `prepare_reencrypt` 生成的文件，否则下次启动时它会被当作新的数据库
+    pub fn discard_reencrypt(&self) {
+        let _ = std::fs::remove_file(pending_path(&self.path));
+    }
+
+    fn with_conn<T>(&self, f: impl FnOnce(&mut Connection) -> rusqlite::Result<T>) -> Result<T, String> {
+        let mut conn = self.conn.lock().map_err(|_| "History database lock poisoned".to_string())?;
+        let conn = conn
+            .as_mut()
+            .ok_or_else(|| "History is locked. Unlock it with your master passphrase first.".to_string())?;
+        f(conn).map_err(|e| e.to_string())
+    }
+
+    pub fn create_conversation(&self, title: Option<String>, profile: Option<String>) -> Result<Conversation, String> {
+        let now = Utc::now();
+        let conversation = Conversation {
+            id: new_id(),
+            title: title.filter(|t| !t.trim().is_empty()).unwrap_or_else(|| DEFAULT_TITLE.to_string()),
+            profile: profile.unwrap_or_default(),
+            created_at: now,
+            updated_at: now,
+            summary: None,
+            folder_id: None,
+            tags: Vec::new(),
+            pinned: false,
+            archived: false,
+            active_leaf_id: None,
+            messages: Vec::new(),
+        };
+        self.with_conn(|conn| {
+            conn.execute(
+                "INSERT INTO conversations (id, title, profile, created_at, updated_at) VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![conversation.id, conversation.title, conversation.profile, now, now],
+            )
+        })?;
+        Ok(conversation)
+    }
+
+    /// 在指定父节点下插入一条消息，并将其设为激活分支的末端
+    fn insert_message(
+        &self,
+        conversation_id: &str,
+        parent: ParentSelector,
+        message: ConversationMessage,
+        model: Option<String>,
+    ) -> Result<StoredMessage, String> {
+        let mut stored = StoredMessage {
+            id: new_id(),
+            role: message.role,
+            content: message.content,
+            attachments: message.attachments,
+            model,
+            created_at: Utc::now(),
+            parent_id: None,
+            sibling_ids: Vec::new(),
+            pinned: false,
+        };
+        let found = self.with_conn(|conn| {
+            let tx = conn.transaction()?;
+            let exists: Option<String> = tx
+                .query_row("SELECT id FROM conversations WHERE id = ?1", params![conversation_id], |row| row.get(0))
+                .optional()?;
+            if exists.is_none() {
+                return Ok(false);
+            }
+            stored.parent_id = match &parent {
+                ParentSelector::ActiveLeaf => branches::active_leaf(&tx, conversation_id)?,
+                ParentSelector::SiblingOf(message_id) => match branches::parent_of(&tx, conversation_id, message_id)? {
+                    Some(parent_id) => parent_id,
+                    None => return Ok(false),
+                },
+            };
+            let seq: i64 = tx.query_row(
+                "SELECT COALESCE(MAX(seq), 0) + 1 FROM messages WHERE conversation_id = ?1",
+                params![conversation_id],
+                |row| row.get(0),
+            )?;
+            tx.execute(
+                "INSERT INTO messages (id, conversation_id, parent_id, seq, role, content, model, created_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                params![
+                    stored.id,
+                    conversation_id,
+                    stored.parent_id,
+                    seq,
+                    stored.role,
+                    stored.content,
+                    stored.model,
+                    stored.created_at
+                ],
+            )?;
+            insert_attachments(&tx, &stored.id, &stored.attachments)?;
+            search::index_message(&tx, &stored.id, &stored.content, &stored.attachments)?;
+            branches::set_active_leaf(&tx, conversation_id, &stored.id)?;
+            tx.execute(
+                "UPDATE conversations SET updated_at = ?1 WHERE id = ?2",
+                params![stored.created_at, conversation_id],
+            )?;
+            stored.sibling_ids = branches::siblings(&tx, conversation_id, stored.parent_id.as_deref())?;
+            tx.commit()?;
+            Ok(true)
+        })?;
+        if !found {
+            return Err(match parent {
+                ParentSelector::SiblingOf(message_id) => {
+                    format!("Message '{}' not found in conversation '{}'", message_id, conversation_id)
+                }
+                ParentSelector::ActiveLeaf => format!("Conversation '{}' not found", conversation_id),
+            });
+        }
+        Ok(stored)
+    }
+
+    /// 在当前激活分支的末尾追加一条消息（连同附件），并写入全文索引
+    pub fn append_message(
+        &self,
+        conversation_id: &str,
+        message: ConversationMessage,
+        model: Option<String>,
+    ) -> Result<StoredMessage, String> {
+        self.insert_message(conversation_id, ParentSelector::ActiveLeaf, message, model)
+    }
+
+    /// 以 `message_id` 的兄弟节点创建新分支，用于编辑过去的消息或重新生成回答
+    pub fn fork_message(
+        &self,
+        conversation_id: &str,
+        message_id: &str,
+        message: ConversationMessage,
+        model: Option<String>,
+    ) -> Result<StoredMessage, String> {
+        self.insert_message(conversation_id, ParentSelector::SiblingOf(message_id.to_string()), message, model)
+    }
+
+    /// 切换到包含 `message_id` 的分支（沿最新的后续消息到达末端）
+    pub fn switch_branch(&self, conversation_id: &str, message_id: &str) -> Result<Conversation, String> {
+        let found = self.with_conn(|conn| {
+            if branches::parent_of(conn, conversation_id, message_id)?.is_none() {
+                return Ok(false);
+            }
+            let leaf = branches::deepest_leaf(conn, message_id)?;
+            branches::set_active_leaf(conn, conversation_id, &leaf)?;
+            Ok(true)
+        })?;
+        if !found {
+            return Err(format!("Message '{}' not found in conversation '{}'", message_id, conversation_id));
+        }
+        self.load_conversation(conversation_id)
+    }
+
+    /// 重建 ask_ai 需要发送的线性消息列表（从根到 `leaf_id`，默认为激活分支末端）
+    pub fn conversation_path(&self, conversation_id: &str, leaf_id: Option<&str>) -> Result<Vec<ConversationMessage>, String> {
+        let messages = self.with_conn(|conn| {
+            let leaf = match leaf_id {
+                Some(leaf) => Some(leaf.to_string()),
+                None => branches::active_leaf(conn, conversation_id)?,
+            };
+            match leaf {
+                Some(leaf) if branches::parent_of(conn, conversation_id, &leaf)?.is_none() => Ok(None),
+                Some(leaf) => load_path(conn, conversation_id, &leaf).map(Some),
+                None => Ok(Some(Vec::new())),
+            }
+        })?
+        .ok_or_else(|| "Message not found in this conversation".to_string())?;
+        Ok(messages
+            .into_iter()
+            .map(|message| ConversationMessage {
+                role: message.role,
+                content: message.content,
+                attachments: message.attachments,
+            })
+            .collect())
+    }
+
+    /// 按过滤条件分页列出对话，置顶的对话在前，其余按最近更新时间倒序
+    pub fn list_conversations(&self, filter: &ConversationFilter, offset: u32, limit: u32) -> Result<ConversationPage, String> {
+        let limit = limit.clamp(1, MAX_PAGE_SIZE);
+        let tags: Vec<String> = filter.tags.iter().filter_map(|t| organize::normalize_tag(t)).collect();
+        let tags = serde_json::to_string(&tags).map_err(|e| e.to_string())?;
+        const FILTER: &str = "c.archived = ?1
+               AND (?2 IS NULL OR c.folder_id = ?2)
+               AND (?3 = 0 OR c.folder_id IS NULL)
+               AND (?4 IS NULL OR c.pinned = ?4)
+               AND NOT EXISTS (
+                   SELECT 1 FROM json_each(?5) t
+                   WHERE t.value NOT IN (SELECT tag FROM conversation_tags ct WHERE ct.conversation_id = c.id)
+               )";
+        self.with_conn(|conn| {
+            let filter_params = params![filter.archived, filter.folder_id, filter.unfiled, filter.pinned, tags];
+            let total: u32 = conn.query_row(
+                &format!("SELECT COUNT(*) FROM conversations c WHERE {}", FILTER),
+                filter_params,
+                |row| row.get(0),
+            )?;
+            let mut stmt = conn.prepare(&format!(
+                "SELECT c.id, c.title, c.profile, c.created_at, c.updated_at, c.summary,
+                        c.folder_id, c.pinned, c.archived,
+                        (WITH RECURSIVE path(id, parent_id) AS (
+                             SELECT id, parent_id FROM messages WHERE id = c.active_leaf_id AND conversation_id = c.id
+                             UNION ALL
+                             SELECT m.id, m.parent_id FROM messages m JOIN path ON m.id = path.parent_id
+                             WHERE m.conversation_id = c.id
+                         ) SELECT COUNT(*) FROM path)
+                 FROM conversations c
+                 WHERE {}
+                 ORDER BY c.pinned DESC, c.updated_at DESC
+                 LIMIT ?6 OFFSET ?7",
+                FILTER
+            ))?;
+            let mut items = stmt
+                .query_map(
+                    params![filter.archived, filter.folder_id, filter.unfiled, filter.pinned, tags, limit, offset],
+                    |row| {
+                        Ok(ConversationSummary {
+                            id: row.get(0)?,
+                            title: row.get(1)?,
+                            profile: row.get(2)?,
+                            created_at: row.get(3)?,
+                            updated_at: row.get(4)?,
+                            summary: row.get(5)?,
+                            folder_id: row.get(6)?,
+                            tags: Vec::new(),
+                            pinned: row.get(7)?,
+                            archived: row.get(8)?,
+                            message_count: row.get(9)?,
+                        })
+                    },
+                )?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+            for item in &mut items {
+                item.tags = organize::load_tags(conn, &item.id)?;
+            }
+            Ok(ConversationPage { items, total, offset, limit })
+        })
+    }
+
+    pub fn load_conversation(&self, id: &str) -> Result<Conversation, String> {
+        self.with_conn(|conn| {
+            let conversation = conn
+                .query_row(
+                    "SELECT id, title, profile, created_at, updated_at, summary, folder_id, pinned, archived, active_leaf_id
+                     FROM conversations WHERE id = ?1",
+                    params![id],
+                    |row| {
+                        Ok(Conversation {
+                            id: row.get(0)?,
+                            title: row.get(1)?,
+                            profile: row.get(2)?,
+                            created_at: row.get(3)?,
+                            updated_at: row.get(4)?,
+                            summary: row.get(5)?,
+                            folder_id: row.get(6)?,
+                            tags: Vec::new(),
+                            pinned: row.get(7)?,
+                            archived: row.get(8)?,
+                            active_leaf_id: row.get(9)?,
+                            messages: Vec::new(),
+                        })
+                    },
+                )
+                .optional()?;
+            let Some(mut conversation) = conversation else {
+                return Ok(None);
+            };
+            conversation.tags = organize::load_tags(conn, id)?;
+            if let Some(leaf) = &conversation.active_leaf_id {
+                conversation.messages = load_path(conn, id, leaf)?;
+            }
+            Ok(Some(conversation))
+        })?
+        .ok_or_else(|| format!("Conversation '{}' not found", id))
+    }
+
+    pub fn rename_conversation(&self, id: &str, title: &str) -> Result<(), String> {
+        let title = title.trim();
+        if title.is_empty() {
+            return Err("Title cannot be empty".to_string());
+        }
+        let changed = self.with_conn(|conn| {
+            conn.execute(
+                "UPDATE conversations SET title = ?1, updated_at = ?2 WHERE id = ?3",
+                params![title, Utc::now(), id],
+            )
+        })?;
+        if changed == 0 {
+            return Err(format!("Conversation '{}' not found", id));
+        }
+        Ok(())
+    }
+
+    /// 仅当对话仍使用默认标题时设置自动生成的标题，不覆盖用户改过的标题
+    pub fn set_generated_title(&self, id: &str, title: &str) -> Result<bool, String> {
+        let changed = self.with_conn(|conn| {
+            conn.execute(
+                "UPDATE conversations SET title = ?1 WHERE id = ?2 AND title = ?3",
+                params![title, id, DEFAULT_TITLE],
+            )
+        })?;
+        Ok(changed > 0)
+    }
+
+    /// 当前摘要及其覆盖的消息数
+    pub fn summary_state(&self, id: &str) -> Result<(Option<String>, u32), String> {
+        self.with_conn(|conn| {
+            conn.query_row(
+                "SELECT summary, summary_message_count FROM conversations WHERE id = ?1",
+                params![id],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()
+        })?
+        .ok_or_else(|| format!("Conversation '{}' not found", id))
+    }
+
+    pub fn set_summary(&self, id: &str, summary: &str, message_count: u32) -> Result<(), String> {
+        self.with_conn(|conn| {
+            conn.execute(
+                "UPDATE conversations SET summary = ?1, summary_message_count = ?2 WHERE id = ?3",
+                params![summary, message_count, id],
+            )
+        })?;
+        Ok(())
+    }
+
+    /// 全文搜索消息内容和附件文本
+    pub fn search(&self, query: &SearchQuery) -> Result<Vec<SearchHit>, String> {
+        self.with_conn(|conn| search::search(conn, query))
+    }
+
+    pub fn delete_conversation(&self, id: &str) -> Result<(), String> {
+        let changed = self.with_conn(|conn| conn.execute("DELETE FROM conversations WHERE id = ?1", params![id]))?;
+        if changed == 0 {
+            return Err(format!("Conversation '{}' not found", id));
+        }
+        Ok(())
+    }
+}
+
+/// 新消息挂载的位置
+enum ParentSelector {
+    /// 当前激活分支的末端
+    ActiveLeaf,
+    /// 与指定消息共享同一父节点
+    SiblingOf(String),
+}
+
+/// 提取消息内容中的文本（兼容字符串和多段内容数组）
+pub fn message_text(content: &serde_json::Value) -> String {
+    match content {
+        serde_json::Value::String(text) => text.clone(),
+        serde_json::Value::Array(parts) => parts
+            .iter()
+            .filter_map(|part| part["text"].as_str())
+            .collect::<Vec<_>>()
+            .join("\n"),
+        _ => String::new(),
+    }
+}
+
+fn insert_attachments(conn: &Connection, message_id: &str, attachments: &[Attachment]) -> rusqlite::Result<()> {
+    let mut stmt = conn.prepare(
+        "INSERT INTO attachments (message_id, position, name, attachment_type, content) VALUES (?1, ?2, ?3, ?4, ?5)",
+    )?;
+    for (position, attachment) in attachments.iter().enumerate() {
+        stmt.execute(params![
+            message_id,
+            position as i64,
+            attachment.name,
+            attachment.attachment_type,
+            attachment.content
+        ])?;
+    }
+    Ok(())
+}
+
+fn load_attachments(conn: &Connection, message_id: &str) -> rusqlite::Result<Vec<Attachment>> {
+    let mut stmt = conn.prepare(
+        "SELECT name, attachment_type, content FROM attachments WHERE message_id = ?1 ORDER BY position",
+    )?;
+    let rows = stmt.query_map(params![message_id], |row| {
+        let attachment_type: String = row.get(1)?;
+        let content: String = row.get(2)?;
+        // 图片的预览直接使用保存的 data URL
+        let preview_url = (attachment_type == "image").then(|| content.clone());
+        Ok(Attachment {
+            name: row.get(0)?,
+            attachment_type,
+            content,
+            preview_url,
+        })
+    })?;
+    rows.collect()
+}
+
+fn load_message(conn: &Connection, conversation_id: &str, message_id: &str) -> rusqlite::Result<StoredMessage> {
+    let mut message = conn.query_row(
+        "SELECT id, role, content, model, created_at, parent_id, pinned FROM messages WHERE id = ?1 AND conversation_id = ?2",
+        params![message_id, conversation_id],
+        |row| {
+            Ok(StoredMessage {
+                id: row.get(0)?,
+                role: row.get(1)?,
+                content: row.get(2)?,
+                attachments: Vec::new(),
+                model: row.get(3)?,
+                created_at: row.get(4)?,
+                parent_id: row.get(5)?,
+                sibling_ids: Vec::new(),
+                pinned: row.get(6)?,
+            })
+        },
+    )?;
+    message.attachments = load_attachments(conn, &message.id)?;
+    message.sibling_ids = branches::siblings(conn, conversation_id, message.parent_id.as_deref())?;
+    Ok(message)
+}
+
+/// 加载从根到 `leaf_id` 的分支上的所有消息
+fn load_path(conn: &Connection, conversation_id: &str, leaf_id: &str) -> rusqlite::Result<Vec<StoredMessage>> {
+    branches::path_to(conn, conversation_id, leaf_id)?
+        .iter()
+        .map(|id| load_message(conn, conversation_id, id))
+        .collect()
+}
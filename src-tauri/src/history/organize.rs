@@ -0,0 +1,217 @@
+// --- Conversation Organization ---
+//
+// 文件夹、标签以及置顶和归档标记。每个对话最多属于一个文件夹，删除文件夹时其中的对话回到未分类；
+// 标签是自由文本，保存前去掉首尾空白并转为小写，因此 `Work` 和 `work` 是同一个标签。
+
+use chrono::{DateTime, Utc};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+use super::{new_id, HistoryStore};
+
+/// 标签的最大长度（字符）
+const MAX_TAG_CHARS: usize = 64;
+
+#[derive(Serialize, Debug, Clone, TS)]
+#[ts(export)]
+pub struct Folder {
+    pub id: String,
+    pub name: String,
+    pub created_at: DateTime<Utc>,
+    pub conversation_count: u32,
+}
+
+#[derive(Serialize, Debug, Clone, TS)]
+#[ts(export)]
+pub struct TagCount {
+    pub tag: String,
+    pub conversation_count: u32,
+}
+
+/// 列出对话时的过滤条件，所有条件同时满足
+#[derive(Deserialize, Debug, Clone, Default, TS)]
+#[ts(export)]
+pub struct ConversationFilter {
+    /// 只列出该文件夹中的对话
+    #[serde(default)]
+    pub folder_id: Option<String>,
+    /// 只列出不属于任何文件夹的对话
+    #[serde(default)]
+    pub unfiled: bool,
+    /// 必须同时带有这些标签
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub pinned: Option<bool>,
+    /// 为 true 时只列出归档的对话，否则只列出未归档的对话
+    #[serde(default)]
+    pub archived: bool,
+}
+
+/// 规范化标签，空标签被丢弃
+pub fn normalize_tag(tag: &str) -> Option<String> {
+    let tag: String = tag.trim().to_lowercase().chars().take(MAX_TAG_CHARS).collect();
+    (!tag.is_empty()).then_some(tag)
+}
+
+pub fn load_tags(conn: &Connection, conversation_id: &str) -> rusqlite::Result<Vec<String>> {
+    let mut stmt = conn.prepare("SELECT tag FROM conversation_tags WHERE conversation_id = ?1 ORDER BY tag")?;
+    let tags = stmt.query_map(params![conversation_id], |row| row.get(0))?;
+    tags.collect()
+}
+
+fn folder_exists(conn: &Connection, folder_id: &str) -> rusqlite::Result<bool> {
+    Ok(conn
+        .query_row("SELECT 1 FROM folders WHERE id = ?1", params![folder_id], |_| Ok(()))
+        .optional()?
+        .is_some())
+}
+
+fn clean_folder_name(name: &str) -> Result<String, String> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err("Folder name cannot be empty".to_string());
+    }
+    Ok(name.to_string())
+}
+
+impl HistoryStore {
+    pub fn create_folder(&self, name: &str) -> Result<Folder, String> {
+        let folder = Folder { id: new_id(), name: clean_folder_name(name)?, created_at: Utc::now(), conversation_count: 0 };
+        self.with_conn(|conn| {
+            conn.execute(
+                "INSERT INTO folders (id, name, created_at) VALUES (?1, ?2, ?3)",
+                params![folder.id, folder.name, folder.created_at],
+            )
+        })?;
+        Ok(folder)
+    }
+
+    pub fn rename_folder(&self, id: &str, name: &str) -> Result<(), String> {
+        let name = clean_folder_name(name)?;
+        let changed = self.with_conn(|conn| conn.execute("UPDATE folders SET name = ?1 WHERE id = ?2", params![name, id]))?;
+        if changed == 0 {
+            return Err(format!("Folder '{}' not found", id));
+        }
+        Ok(())
+    }
+
+    /// 删除文件夹，其中的对话保留并回到未分类
+    pub fn delete_folder(&self, id: &str) -> Result<(), String> {
+        let changed = self.with_conn(|conn| conn.execute("DELETE FROM folders WHERE id = ?1", params![id]))?;
+        if changed == 0 {
+            return Err(format!("Folder '{}' not found", id));
+        }
+        Ok(())
+    }
+
+    pub fn list_folders(&self) -> Result<Vec<Folder>, String> {
+        self.with_conn(|conn| {
+            let mut stmt = conn.prepare(
+                "SELECT f.id, f.name, f.created_at,
+                        (SELECT COUNT(*) FROM conversations c WHERE c.folder_id = f.id AND c.archived = 0)
+                 FROM folders f ORDER BY f.name COLLATE NOCASE",
+            )?;
+            let folders = stmt.query_map([], |row| {
+                Ok(Folder { id: row.get(0)?, name: row.get(1)?, created_at: row.get(2)?, conversation_count: row.get(3)? })
+            })?;
+            folders.collect()
+        })
+    }
+
+    /// 所有已使用的标签及对应的对话数
+    pub fn list_tags(&self) -> Result<Vec<TagCount>, String> {
+        self.with_conn(|conn| {
+            let mut stmt =
+                conn.prepare("SELECT tag, COUNT(*) FROM conversation_tags GROUP BY tag ORDER BY tag")?;
+            let tags = stmt.query_map([], |row| Ok(TagCount { tag: row.get(0)?, conversation_count: row.get(1)? }))?;
+            tags.collect()
+        })
+    }
+
+    /// 把对话移动到文件夹，`folder_id` 为 None 时移出文件夹。返回实际更新的对话数。
+    pub fn move_conversations(&self, ids: &[String], folder_id: Option<&str>) -> Result<u32, String> {
+        let result = self.with_conn(|conn| {
+            let tx = conn.transaction()?;
+            if let Some(folder_id) = folder_id {
+                if !folder_exists(&tx, folder_id)? {
+                    return Ok(None);
+                }
+            }
+            let mut changed = 0;
+            for id in ids {
+                changed += tx.execute("UPDATE conversations SET folder_id = ?1 WHERE id = ?2", params![folder_id, id])?;
+            }
+            tx.commit()?;
+            Ok(Some(changed as u32))
+        })?;
+        result.ok_or_else(|| format!("Folder '{}' not found", folder_id.unwrap_or_default()))
+    }
+
+    /// 为多个对话添加和移除标签
+    pub fn tag_conversations(&self, ids: &[String], add: &[String], remove: &[String]) -> Result<(), String> {
+        let add: Vec<String> = add.iter().filter_map(|t| normalize_tag(t)).collect();
+        let remove: Vec<String> = remove.iter().filter_map(|t| normalize_tag(t)).collect();
+        self.with_conn(|conn| {
+            let tx = conn.transaction()?;
+            {
+                let mut insert = tx.prepare(
+                    "INSERT OR IGNORE INTO conversation_tags (conversation_id, tag)
+                     SELECT id, ?2 FROM conversations WHERE id = ?1",
+                )?;
+                let mut delete = tx.prepare("DELETE FROM conversation_tags WHERE conversation_id = ?1 AND tag = ?2")?;
+                for id in ids {
+                    for tag in &remove {
+                        delete.execute(params![id, tag])?;
+                    }
+                    for tag in &add {
+                        insert.execute(params![id, tag])?;
+                    }
+                }
+            }
+            tx.commit()
+        })
+    }
+
+    /// 批量设置置顶标记，返回实际更新的对话数
+    pub fn set_conversations_pinned(&self, ids: &[String], pinned: bool) -> Result<u32, String> {
+        self.update_flag("UPDATE conversations SET pinned = ?1 WHERE id = ?2", ids, pinned)
+    }
+
+    /// 批量归档或取消归档，返回实际更新的对话数
+    pub fn set_conversations_archived(&self, ids: &[String], archived: bool) -> Result<u32, String> {
+        self.update_flag("UPDATE conversations SET archived = ?1 WHERE id = ?2", ids, archived)
+    }
+
+    fn update_flag(&self, sql: &str, ids: &[String], value: bool) -> Result<u32, String> {
+        self.with_conn(|conn| {
+            let tx = conn.transaction()?;
+            let mut changed = 0;
+            {
+                let mut stmt = tx.prepare(sql)?;
+                for id in ids {
+                    changed += stmt.execute(params![value, id])?;
+                }
+            }
+            tx.commit()?;
+            Ok(changed as u32)
+        })
+    }
+
+    /// 批量删除对话，返回实际删除的对话数
+    pub fn delete_conversations(&self, ids: &[String]) -> Result<u32, String> {
+        self.with_conn(|conn| {
+            let tx = conn.transaction()?;
+            let mut changed = 0;
+            {
+                let mut stmt = tx.prepare("DELETE FROM conversations WHERE id = ?1")?;
+                for id in ids {
+                    changed += stmt.execute(params![id])?;
+                }
+            }
+            tx.commit()?;
+            Ok(changed as u32)
+        })
+    }
+}
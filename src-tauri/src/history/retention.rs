@@ -0,0 +1,53 @@
+// --- History Retention ---
+//
+// 按保留期限和最大对话数删除旧对话。置顶的对话排在最前，并且可以设置为永不删除。
+
+use chrono::{Duration, Utc};
+use rusqlite::params;
+
+use super::HistoryStore;
+
+/// 保留策略，None 表示不限制
+#[derive(Debug, Clone)]
+pub struct RetentionPolicy {
+    /// 删除超过该天数未更新的对话
+    pub max_age_days: Option<u32>,
+    /// 最多保留的对话数（按置顶、最近更新排序）
+    pub max_conversations: Option<u32>,
+    pub keep_pinned: bool,
+}
+
+impl RetentionPolicy {
+    pub fn is_unlimited(&self) -> bool {
+        self.max_age_days.is_none() && self.max_conversations.is_none()
+    }
+}
+
+impl HistoryStore {
+    /// 应用保留策略，返回删除的对话数
+    pub fn enforce_retention(&self, policy: &RetentionPolicy) -> Result<u32, String> {
+        let cutoff = policy.max_age_days.map(|days| Utc::now() - Duration::days(days as i64));
+        self.with_conn(|conn| {
+            let tx = conn.transaction()?;
+            let mut deleted = 0;
+            if let Some(cutoff) = cutoff {
+                deleted += tx.execute(
+                    "DELETE FROM conversations WHERE updated_at < ?1 AND (?2 = 0 OR pinned = 0)",
+                    params![cutoff, policy.keep_pinned],
+                )?;
+            }
+            if let Some(max_conversations) = policy.max_conversations {
+                deleted += tx.execute(
+                    "DELETE FROM conversations
+                     WHERE id IN (
+                         SELECT id FROM conversations ORDER BY pinned DESC, updated_at DESC LIMIT -1 OFFSET ?1
+                     )
+                     AND (?2 = 0 OR pinned = 0)",
+                    params![max_conversations, policy.keep_pinned],
+                )?;
+            }
+            tx.commit()?;
+            Ok(deleted as u32)
+        })
+    }
+}
@@ -0,0 +1,290 @@
+// --- History Search ---
+//
+// 基于 SQLite FTS5 的全文索引。unicode61 分词器会把连续的中日韩文字当作一个词，
+// 因此写入索引前在每个 CJK 字符两侧插入零宽空格，使其逐字成词；
+// 查询时把 CJK 词转换为逐字短语，从而实现任意子串匹配。
+
+use chrono::{DateTime, Utc};
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+use super::message_text;
+use crate::Attachment;
+
+/// 分隔 CJK 字符用的零宽空格，unicode61 分词器将其视为分隔符
+const SEGMENT_SEPARATOR: char = '\u{200B}';
+const MAX_RESULTS: u32 = 100;
+/// 摘要中包含的最大词数
+const SNIPPET_TOKENS: u32 = 16;
+
+#[derive(Deserialize, Debug, Clone, Default, TS)]
+#[ts(export)]
+pub struct SearchQuery {
+    pub query: String,
+    #[serde(default)]
+    pub from: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub to: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub model: Option<String>,
+    #[serde(default)]
+    pub role: Option<String>,
+    #[serde(default)]
+    pub limit: Option<u32>,
+    #[serde(default)]
+    pub offset: Option<u32>,
+}
+
+#[derive(Serialize, Debug, Clone, TS)]
+#[ts(export)]
+pub struct SearchHit {
+    pub conversation_id: String,
+    pub conversation_title: String,
+    pub message_id: String,
+    pub role: String,
+    pub model: Option<String>,
+    /// 命中片段，匹配部分用 `<mark>` 标出
+    pub snippet: String,
+    /// 相关度，越大越相关
+    pub score: f64,
+    pub created_at: DateTime<Utc>,
+}
+
+/// 判断是否为需要逐字分词的中日韩字符
+fn is_cjk(c: char) -> bool {
+    matches!(c,
+        '\u{3040}'..='\u{30FF}'   // 平假名、片假名
+        | '\u{3400}'..='\u{4DBF}' // CJK 扩展 A
+        | '\u{4E00}'..='\u{9FFF}' // CJK 统一汉字
+        | '\u{AC00}'..='\u{D7AF}' // 韩文音节
+        | '\u{F900}'..='\u{FAFF}' // CJK 兼容汉字
+        | '\u{FF66}'..='\u{FF9F}' // 半角片假名
+        | '\u{20000}'..='\u{2FA1F}' // CJK 扩展 B 及以后
+    )
+}
+
+/// 在每个 CJK 字符两侧插入分隔符
+fn segment(text: &str) -> String {
+    let mut segmented = String::with_capacity(text.len() * 2);
+    for c in text.chars() {
+        if is_cjk(c) {
+            segmented.push(SEGMENT_SEPARATOR);
+            segmented.push(c);
+            segmented.push(SEGMENT_SEPARATOR);
+        } else {
+            segmented.push(c);
+        }
+    }
+    segmented
+}
+
+/// 生成消息的索引文本：正文、文本附件的内容以及所有附件的文件名
+fn search_text(content: &serde_json::Value, attachments: &[Attachment]) -> String {
+    let mut sections = vec![message_text(content)];
+    for attachment in attachments {
+        sections.push(attachment.name.clone());
+        if attachment.attachment_type != "image" {
+            sections.push(attachment.content.clone());
+        }
+    }
+    segment(&sections.join("\n"))
+}
+
+pub fn index_message(
+    conn: &Connection,
+    message_id: &str,
+    content: &serde_json::Value,
+    attachments: &[Attachment],
+) -> rusqlite::Result<()> {
+    conn.execute(
+        "INSERT INTO message_fts (message_id, body) VALUES (?1, ?2)",
+        params![message_id, search_text(content, attachments)],
+    )?;
+    Ok(())
+}
+
+/// 根据现有消息重建全文索引
+pub fn rebuild_index(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute("DELETE FROM message_fts", [])?;
+    let mut stmt = conn.prepare("SELECT id, content FROM messages")?;
+    let messages = stmt
+        .query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, serde_json::Value>(1)?)))?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    let mut attachment_stmt = conn.prepare(
+        "SELECT name, attachment_type, content FROM attachments WHERE message_id = ?1 ORDER BY position",
+    )?;
+    for (id, content) in messages {
+        let attachments = attachment_stmt
+            .query_map(params![id], |row| {
+                Ok(Attachment {
+                    name: row.get(0)?,
+                    attachment_type: row.get(1)?,
+                    content: row.get(2)?,
+                    preview_url: None,
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        index_message(conn, &id, &content, &attachments)?;
+    }
+    Ok(())
+}
+
+/// 把用户输入转换为 FTS5 查询：每个词作为短语（非 CJK 词按前缀匹配），词之间为 AND。
+/// unicode61 只把字母和数字当作词的一部分，只含标点或符号的词会成为空短语，
+/// 加上 `*` 后是语法错误，因此直接丢弃；没有剩下任何词时不查询。
+fn build_match_query(query: &str) -> Option<String> {
+    let terms: Vec<String> = query
+        .split_whitespace()
+        .filter(|term| term.chars().any(char::is_alphanumeric))
+        .map(|term| {
+            let escaped = segment(term).replace('"', "\"\"");
+            if term.chars().any(is_cjk) {
+                format!("\"{}\"", escaped)
+            } else {
+                format!("\"{}\"*", escaped)
+            }
+        })
+        .collect();
+    (!terms.is_empty()).then(|| terms.join(" "))
+}
+
+pub fn search(conn: &Connection, query: &SearchQuery) -> rusqlite::Result<Vec<SearchHit>> {
+    let Some(match_query) = build_match_query(&query.query) else {
+        return Ok(Vec::new());
+    };
+    let limit = query.limit.unwrap_or(50).clamp(1, MAX_RESULTS);
+    let offset = query.offset.unwrap_or(0);
+
+    let mut stmt = conn.prepare(
+        "SELECT m.conversation_id, c.title, m.id, m.role, m.model, m.created_at,
+                snippet(message_fts, 1, '<mark>', '</mark>', '…', ?8),
+                bm25(message_fts) AS rank
+         FROM message_fts
+         JOIN messages m ON m.id = message_fts.message_id
+         JOIN conversations c ON c.id = m.conversation_id
+         WHERE message_fts MATCH ?1
+           AND (?2 IS NULL OR m.created_at >= ?2)
+           AND (?3 IS NULL OR m.created_at <= ?3)
+           AND (?4 IS NULL OR m.model = ?4)
+           AND (?5 IS NULL OR m.role = ?5)
+         ORDER BY rank
+         LIMIT ?6 OFFSET ?7",
+    )?;
+    let hits = stmt
+        .query_map(
+            params![match_query, query.from, query.to, query.model, query.role, limit, offset, SNIPPET_TOKENS],
+            |row| {
+                let snippet: String = row.get(6)?;
+                let rank: f64 = row.get(7)?;
+                Ok(SearchHit {
+                    conversation_id: row.get(0)?,
+                    conversation_title: row.get(1)?,
+                    message_id: row.get(2)?,
+                    role: row.get(3)?,
+                    model: row.get(4)?,
+                    snippet: snippet.replace(SEGMENT_SEPARATOR, ""),
+                    // bm25 越小越相关，取反后越大越相关
+                    score: -rank,
+                    created_at: row.get(5)?,
+                })
+            },
+        )?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    Ok(hits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 与历史数据库相同的 FTS5 表，只包含给定的文本
+    fn index(texts: &[&str]) -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE VIRTUAL TABLE message_fts USING fts5(
+                message_id UNINDEXED,
+                body,
+                tokenize = 'unicode61 remove_diacritics 2'
+            );",
+        )
+        .unwrap();
+        for (index, text) in texts.iter().enumerate() {
+            let content = serde_json::Value::String(text.to_string());
+            index_message(&conn, &index.to_string(), &content, &[]).unwrap();
+        }
+        conn
+    }
+
+    fn matches(conn: &Connection, query: &str) -> Vec<String> {
+        let query = build_match_query(query).unwrap();
+        let mut stmt =
+            conn.prepare("SELECT message_id FROM message_fts WHERE message_fts MATCH ?1 ORDER BY message_id").unwrap();
+        stmt.query_map(params![query], |row| row.get(0)).unwrap().collect::<rusqlite::Result<_>>().unwrap()
+    }
+
+    #[test]
+    fn segment_splits_cjk_characters_only() {
+        assert_eq!(segment("hello world"), "hello world");
+        assert_eq!(segment("中文"), "\u{200B}中\u{200B}\u{200B}文\u{200B}");
+        assert_eq!(segment("Rust编程"), "Rust\u{200B}编\u{200B}\u{200B}程\u{200B}");
+        assert_eq!(segment("かな한글"), "\u{200B}か\u{200B}\u{200B}な\u{200B}\u{200B}한\u{200B}\u{200B}글\u{200B}");
+    }
+
+    #[test]
+    fn terms_become_prefix_phrases() {
+        assert_eq!(build_match_query("rust async").as_deref(), Some("\"rust\"* \"async\"*"));
+        assert_eq!(build_match_query("  rust  ").as_deref(), Some("\"rust\"*"));
+    }
+
+    #[test]
+    fn fts_operators_are_quoted() {
+        assert_eq!(
+            build_match_query("a AND b NEAR c*").as_deref(),
+            Some("\"a\"* \"AND\"* \"b\"* \"NEAR\"* \"c*\"*")
+        );
+        assert_eq!(build_match_query("NOT (x OR y)").as_deref(), Some("\"NOT\"* \"(x\"* \"OR\"* \"y)\"*"));
+        assert_eq!(build_match_query("col:value").as_deref(), Some("\"col:value\"*"));
+    }
+
+    #[test]
+    fn quotes_are_escaped() {
+        assert_eq!(build_match_query("say \"hi\"").as_deref(), Some("\"say\"* \"\"\"hi\"\"\"*"));
+        assert_eq!(build_match_query("it's").as_deref(), Some("\"it's\"*"));
+    }
+
+    #[test]
+    fn punctuation_only_terms_are_dropped() {
+        assert_eq!(build_match_query("rust *** ?").as_deref(), Some("\"rust\"*"));
+        assert_eq!(build_match_query("\" -- ..."), None);
+        assert_eq!(build_match_query(""), None);
+        assert_eq!(build_match_query("   "), None);
+    }
+
+    #[test]
+    fn cjk_terms_become_character_phrases() {
+        assert_eq!(build_match_query("编程").as_deref(), Some("\"\u{200B}编\u{200B}\u{200B}程\u{200B}\""));
+        assert_eq!(
+            build_match_query("Rust编程 入门").as_deref(),
+            Some("\"Rust\u{200B}编\u{200B}\u{200B}程\u{200B}\" \"\u{200B}入\u{200B}\u{200B}门\u{200B}\"")
+        );
+    }
+
+    #[test]
+    fn queries_are_valid_fts5() {
+        let conn = index(&[
+            "学习Rust编程入门",
+            "Rust async AND NEAR operators",
+            "say \"hi\" to everyone",
+            "中文和English混合",
+        ]);
+        assert_eq!(matches(&conn, "编程"), vec!["0"]);
+        assert_eq!(matches(&conn, "Rust编程"), vec!["0"]);
+        assert_eq!(matches(&conn, "rust"), vec!["0", "1"]);
+        assert_eq!(matches(&conn, "asy NEAR"), vec!["1"]);
+        assert_eq!(matches(&conn, "a AND b"), Vec::<String>::new());
+        assert_eq!(matches(&conn, "\"hi\" ***"), vec!["2"]);
+        assert_eq!(matches(&conn, "文和 engl"), vec!["3"]);
+    }
+}
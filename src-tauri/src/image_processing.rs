@@ -0,0 +1,202 @@
+// --- Image Preprocessing ---
+//
+// 图片附件在发送前于后端重新处理：按各 API 的尺寸上限缩放、在字节预算内重新编码，
+// 并丢弃包括 EXIF（GPS 位置等）在内的全部元数据。带透明通道的图片在服务商支持时编码为无损 WebP
+// 以保留透明度，超出预算或服务商不支持时与其它图片一样编码为 JPEG。
+
+use std::io::Cursor;
+
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::webp::WebPEncoder;
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageDecoder, ImageReader, RgbImage};
+use serde::Serialize;
+use ts_rs::TS;
+
+use crate::providers::ProviderKind;
+
+/// 单张图片的处理上限
+#[derive(Debug, Clone, Copy)]
+pub struct ImageLimits {
+    pub max_dimension: u32,
+    pub max_bytes: usize,
+    /// 服务商接受 WebP 图片
+    pub webp: bool,
+}
+
+/// 各服务商的图片尺寸和大小上限
+pub fn limits_for(provider: ProviderKind) -> ImageLimits {
+    match provider {
+        ProviderKind::Mock => ImageLimits { max_dimension: 1024, max_bytes: 1024 * 1024, webp: true },
+        // OpenAI 会把长边缩放到 2048 像素以内，更大的图片只会浪费带宽
+        ProviderKind::OpenAI => ImageLimits { max_dimension: 2048, max_bytes: 4 * 1024 * 1024, webp: true },
+        // Gemini 把大于 3072 像素的图片缩小；整个请求的内联数据不能超过 20MB
+        ProviderKind::Gemini => ImageLimits { max_dimension: 3072, max_bytes: 4 * 1024 * 1024, webp: true },
+        // OpenRouter 可能转发给 Anthropic：单张图片（base64 后）不超过 5MB，长边超过 1568 像素会被缩小
+        ProviderKind::OpenRouter => ImageLimits { max_dimension: 1568, max_bytes: 3 * 1024 * 1024, webp: true },
+        // 本地的视觉模型以 1344 像素以内的切片处理图片，不受网络带宽限制；图片只按 JPEG/PNG 解码
+        ProviderKind::Ollama => ImageLimits { max_dimension: 1344, max_bytes: 8 * 1024 * 1024, webp: false },
+        ProviderKind::Cloudflare | ProviderKind::Compatible => {
+            ImageLimits { max_dimension: 2048, max_bytes: 4 * 1024 * 1024, webp: false }
+        }
+    }
+}
+
+/// 处理结果，返回给前端用于显示最终大小
+#[derive(Serialize, Debug, Clone, TS)]
+#[ts(export)]
+pub struct ProcessedImage {
+    pub data_url: String,
+    pub width: u32,
+    pub height: u32,
+    pub original_bytes: u32,
+    pub final_bytes: u32,
+}
+
+/// JPEG 质量逐级降低，直到满足字节预算
+const JPEG_QUALITIES: [u8; 5] = [85, 75, 65, 55, 45];
+/// 最低质量仍超出预算时，每轮缩小的比例和最多轮数
+const DOWNSCALE_FACTOR: f32 = 0.75;
+const MAX_DOWNSCALE_ROUNDS: usize = 4;
+
+fn decode_data_url(data_url: &str) -> Result<Vec<u8>, String> {
+    let (header, data) = data_url
+        .split_once(',')
+        .ok_or_else(|| "Image is not a valid data URL".to_string())?;
+    if !header.starts_with("data:image/") || !header.ends_with(";base64") {
+        return Err("Image must be a base64 encoded image data URL".to_string());
+    }
+    STANDARD.decode(data.trim()).map_err(|e| format!("Invalid base64 image data: {}", e))
+}
+
+/// 判断是否为不含任何元数据段（APP1-APP15、注释）的 JPEG
+fn is_clean_jpeg(bytes: &[u8]) -> bool {
+    if bytes.len() < 4 || bytes[0] != 0xFF || bytes[1] != 0xD8 {
+        return false;
+    }
+    let mut pos = 2;
+    while pos + 4 <= bytes.len() {
+        if bytes[pos] != 0xFF {
+            return false;
+        }
+        let marker = bytes[pos + 1];
+        // 到达扫描数据，之后不再有元数据段
+        if marker == 0xDA {
+            return true;
+        }
+        if (0xE1..=0xEF).contains(&marker) || marker == 0xFE {
+            return false;
+        }
+        let length = u16::from_be_bytes([bytes[pos + 2], bytes[pos + 3]]) as usize;
+        pos += 2 + length;
+    }
+    false
+}
+
+/// 将带透明通道的图片铺在白色背景上，JPEG 不支持透明
+fn flatten_to_rgb(image: &DynamicImage) -> RgbImage {
+    if !image.color().has_alpha() {
+        return image.to_rgb8();
+    }
+    let rgba = image.to_rgba8();
+    RgbImage::from_fn(rgba.width(), rgba.height(), |x, y| {
+        let [r, g, b, a] = rgba.get_pixel(x, y).0;
+        let blend = |c: u8| ((c as u32 * a as u32 + 255 * (255 - a as u32)) / 255) as u8;
+        image::Rgb([blend(r), blend(g), blend(b)])
+    })
+}
+
+/// 无损 WebP，保留透明通道
+fn encode_webp(image: &DynamicImage) -> Result<Vec<u8>, String> {
+    let rgba = image.to_rgba8();
+    let mut buffer = Vec::new();
+    WebPEncoder::new_lossless(&mut buffer)
+        .encode(rgba.as_raw(), rgba.width(), rgba.height(), image::ExtendedColorType::Rgba8)
+        .map_err(|e| format!("Failed to encode image: {}", e))?;
+    Ok(buffer)
+}
+
+fn encode_jpeg(image: &RgbImage, quality: u8) -> Result<Vec<u8>, String> {
+    let mut buffer = Vec::new();
+    JpegEncoder::new_with_quality(&mut buffer, quality)
+        .encode(image.as_raw(), image.width(), image.height(), image::ExtendedColorType::Rgb8)
+        .map_err(|e| format!("Failed to encode image: {}", e))?;
+    Ok(buffer)
+}
+
+/// 解码、缩放并重新编码图片，结果不含任何元数据
+pub fn process_data_url(data_url: &str, limits: ImageLimits) -> Result<ProcessedImage, String> {
+    let bytes = decode_data_url(data_url)?;
+    let original_bytes = bytes.len() as u32;
+
+    let reader = ImageReader::new(Cursor::new(&bytes))
+        .with_guessed_format()
+        .map_err(|e| format!("Unrecognized image format: {}", e))?;
+
+    // 已经处理过的图片（不含元数据、尺寸和大小都在限制内）原样返回，避免重复压缩
+    if is_clean_jpeg(&bytes) && bytes.len() <= limits.max_bytes {
+        if let Ok((width, height)) = reader.into_dimensions() {
+            if width.max(height) <= limits.max_dimension {
+                return Ok(ProcessedImage {
+                    data_url: data_url.to_string(),
+                    width,
+                    height,
+                    original_bytes,
+                    final_bytes: original_bytes,
+                });
+            }
+        }
+    }
+
+    let mut decoder = ImageReader::new(Cursor::new(&bytes))
+        .with_guessed_format()
+        .map_err(|e| format!("Unrecognized image format: {}", e))?
+        .into_decoder()
+        .map_err(|e| format!("Failed to decode image: {}", e))?;
+    // 丢弃 EXIF 之前先应用其中的方向信息，否则手机照片可能会被旋转
+    let orientation = decoder.orientation().unwrap_or(image::metadata::Orientation::NoTransforms);
+    let mut image = DynamicImage::from_decoder(decoder).map_err(|e| format!("Failed to decode image: {}", e))?;
+    image.apply_orientation(orientation);
+
+    if image.width().max(image.height()) > limits.max_dimension {
+        image = image.resize(limits.max_dimension, limits.max_dimension, FilterType::Lanczos3);
+    }
+
+    if limits.webp && image.color().has_alpha() {
+        let encoded = encode_webp(&image)?;
+        if encoded.len() <= limits.max_bytes {
+            return Ok(ProcessedImage {
+                data_url: format!("data:image/webp;base64,{}", STANDARD.encode(&encoded)),
+                width: image.width(),
+                height: image.height(),
+                original_bytes,
+                final_bytes: encoded.len() as u32,
+            });
+        }
+    }
+
+    let mut rgb = flatten_to_rgb(&image);
+    for _ in 0..=MAX_DOWNSCALE_ROUNDS {
+        for quality in JPEG_QUALITIES {
+            let encoded = encode_jpeg(&rgb, quality)?;
+            if encoded.len() <= limits.max_bytes {
+                return Ok(ProcessedImage {
+                    data_url: format!("data:image/jpeg;base64,{}", STANDARD.encode(&encoded)),
+                    width: rgb.width(),
+                    height: rgb.height(),
+                    original_bytes,
+                    final_bytes: encoded.len() as u32,
+                });
+            }
+        }
+        let width = ((rgb.width() as f32) * DOWNSCALE_FACTOR).max(1.0) as u32;
+        let height = ((rgb.height() as f32) * DOWNSCALE_FACTOR).max(1.0) as u32;
+        rgb = image::imageops::resize(&rgb, width, height, FilterType::Lanczos3);
+    }
+
+    Err(format!(
+        "Image could not be compressed below {}KB",
+        limits.max_bytes / 1024
+    ))
+}
@@ -1,4 +1,4 @@
-use tauri::{Manager, AppHandle};
+use tauri::{Manager, AppHandle, State};
 use std::str::FromStr;
 use std::fs;
 use std::time::{Duration, Instant};
@@ -7,12 +7,28 @@ use serde::{Deserialize, Serialize};
 use tauri_plugin_global_shortcut::{GlobalShortcutExt, Shortcut, ShortcutEvent};
 use tauri_plugin_opener::OpenerExt;
 use url::Url;
-use regex::Regex;
 use std::io::Write;
 use std::env;
-use tauri_plugin_store::StoreBuilder;
 use ts_rs::TS;
 
+mod api_errors;
+mod attachments;
+mod backup;
+mod compaction;
+mod display;
+mod export;
+mod history;
+mod image_processing;
+mod net_policy;
+mod privacy;
+mod prompts;
+mod providers;
+mod mock;
+mod recorder;
+mod settings;
+mod summaries;
+mod vault;
+
 
 
 #[tauri::command]
@@ -39,79 +55,25 @@ fn register_shortcut(app: AppHandle, shortcut: String) -> Result<(), String> {
 struct InputValidator;
 
 impl InputValidator {
-    /// 验证URL是否安全
-    fn validate_url(url: &str) -> Result<(), String> {
+    /// 验证URL是否安全（协议、IP 字面量和本地主机名）。DNS 解析后的检查见 `net_policy::vet_endpoint`。
+    fn validate_url(url: &str, allowlist: &[String]) -> Result<(), String> {
         let parsed = Url::parse(url).map_err(|e| format!("Invalid URL: {}", e))?;
-        
-        // 检查协议
-        if parsed.scheme() != "https" && parsed.scheme() != "http" {
-            return Err("URL must use http or https protocol".to_string());
-        }
-        
-        // 检查是否为本地地址
-        if let Some(host) = parsed.host_str() {
-            if host == "localhost" || host == "127.0.0.1" || host == "::1" {
-                return Err("Localhost URLs are not allowed for security reasons".to_string());
-            }
-            
-            // 检查是否为私有网络地址
-            if host.starts_with("192.168.") || 
-               host.starts_with("10.") || 
-               host.starts_with("172.") ||
-               host.ends_with(".local") {
-                return Err("Private network URLs are not allowed".to_string());
-            }
-        }
-        
-        Ok(())
-    }
-    
-    /// 清理和验证系统提示
-    fn sanitize_system_prompt(prompt: &str) -> String {
-        let mut sanitized = prompt.to_string();
-        
-        // 移除潜在的恶意内容
-        let dangerous_patterns = [
-            (r"<script[^>]*>.*?</script>", ""),
-            (r"javascript:", ""),
-            (r"on\w+=", ""),
-            (r"eval\(.*\)", ""),
-            (r"exec\(.*\)", ""),
-        ];
-        
-        for (pattern, replacement) in dangerous_patterns.iter() {
-            if let Ok(re) = Regex::new(pattern) {
-                sanitized = re.replace_all(&sanitized, *replacement).to_string();
-            }
-        }
-        
-        // 限制长度
-        if sanitized.len() > 10000 {
-            sanitized.truncate(10000);
-            sanitized.push_str("... [truncated]");
-        }
-        
-        sanitized
+        net_policy::check_url(&parsed, allowlist)
     }
     
-    /// 验证模型名称
-    fn validate_model_name(model: &str) -> Result<(), String> {
-        if model.is_empty() {
-            return Err("Model name cannot be empty".to_string());
+    /// 按字符边界截断文本，并附加明确的截断标记。`max_chars` 为 0 时不截断。
+    fn truncate_chars(text: &str, max_chars: usize) -> (String, bool) {
+        if max_chars == 0 {
+            return (text.to_string(), false);
         }
-        
-        // 只允许字母、数字、连字符、下划线和点
-        let valid_pattern = Regex::new(r"^[a-zA-Z0-9._-]+$").unwrap();
-        if !valid_pattern.is_match(model) {
-            return Err("Model name contains invalid characters".to_string());
-        }
-        
-        // 限制长度
-        if model.len() > 100 {
-            return Err("Model name is too long".to_string());
+        match text.char_indices().nth(max_chars) {
+            Some((byte_index, _)) => {
+                let mut truncated = text[..byte_index].to_string();
+                truncated.push_str(&format!("\n\n[truncated: response exceeded {} characters]", max_chars));
+                (truncated, true)
+            }
+            None => (text.to_string(), false),
         }
-        
-        Ok(())
     }
     
     /// 验证文件扩展名
@@ -145,8 +107,9 @@ impl SecurityLogger {
         let timestamp = chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string();
         let log_entry = format!("[{}] [{}] {}\n", timestamp, level, event);
         
-        // 写入安全日志文件（放在应用程序配置目录中）
-        if let Ok(config_dir) = app.path().app_config_dir() {
+        // 写入安全日志文件（放在应用程序配置目录中），隐身模式下不写入
+        let config_dir = app.path().app_config_dir().ok().filter(|_| !privacy::is_incognito());
+        if let Some(config_dir) = config_dir {
             let log_path = config_dir.join("security.log");
             if let Ok(mut file) = fs::OpenOptions::new()
                 .create(true)
@@ -187,87 +150,168 @@ fn open_config_directory(app: AppHandle) -> Result<(), String> {
 }
 
 #[tauri::command]
-fn get_settings(app: AppHandle) -> Result<serde_json::Value, String> {
-    let config_dir = app.path().app_config_dir().map_err(|e| e.to_string())?;
-    let path = config_dir.join("settings.dat");
-    let store = match StoreBuilder::new(app.app_handle(), path).build() {
-        Ok(store) => store,
-        Err(e) => return Err(e.to_string()),
-    };
-    let _ = store.reload();
+fn get_settings(service: State<'_, settings::SettingsService>) -> Result<settings::LoadedSettings, String> {
+    let mut settings = service.effective();
+    let env_locked = service.env_overrides().locked_fields(&settings);
 
-    let mut settings = serde_json::Map::new();
-    
-    // 读取所有设置值
-    if let Some(api_key) = store.get("api_key") {
-        settings.insert("api_key".to_string(), api_key.clone());
-    }
-    if let Some(api_url) = store.get("api_url") {
-        settings.insert("api_url".to_string(), api_url.clone());
-    }
-    if let Some(model_name) = store.get("model_name") {
-        settings.insert("model_name".to_string(), model_name.clone());
-    }
-    if let Some(shortcut) = store.get("shortcut") {
-        settings.insert("shortcut".to_string(), shortcut.clone());
-    }
-    if let Some(system_prompt) = store.get("system_prompt") {
-        settings.insert("system_prompt".to_string(), system_prompt.clone());
-    }
-    if let Some(api_type) = store.get("api_type") {
-        settings.insert("api_type".to_string(), api_type.clone());
-    }
-    if let Some(clear_chat_shortcut) = store.get("clear_chat_shortcut") {
-        settings.insert("clear_chat_shortcut".to_string(), clear_chat_shortcut.clone());
+    // 已解锁时返回明文密钥；未解锁时保留密文，前端只显示占位符
+    for profile in &mut settings.profiles {
+        if let Ok(plain) = vault::reveal(&profile.api_key) {
+            profile.api_key = plain;
+        }
     }
-    if let Some(borderless_shortcut) = store.get("borderless_shortcut") {
-        settings.insert("borderless_shortcut".to_string(), borderless_shortcut.clone());
+    // 环境变量提供的密钥不发送给前端，`env_locked` 已说明密钥由环境变量提供。
+    // 保存时被覆盖的字段恢复为 settings.dat 中的值，这里清空不影响保存。
+    if env_locked.iter().any(|field| field == "api_key") {
+        let active = settings.current_profile().name;
+        if let Some(profile) = settings.profiles.iter_mut().find(|profile| profile.name == active) {
+            profile.api_key.clear();
+        }
     }
 
-    Ok(serde_json::Value::Object(settings))
+    Ok(settings::LoadedSettings { settings, env_locked })
 }
 
 #[tauri::command]
-fn set_settings(app: AppHandle, settings: serde_json::Value) -> Result<(), String> {
-    let config_dir = app.path().app_config_dir().map_err(|e| e.to_string())?;
-    let path = config_dir.join("settings.dat");
-    let store = match StoreBuilder::new(app.app_handle(), path).build() {
-        Ok(store) => store,
-        Err(e) => return Err(e.to_string()),
+fn set_settings(app: AppHandle, settings: settings::Settings) -> Result<(), settings::SettingsError> {
+    save_settings(&app, settings)
+}
+
+/// 只修改 `patch` 中的字段。字段名未知或类型不对时返回对应字段的错误。
+#[tauri::command]
+fn update_settings(
+    app: AppHandle,
+    patch: serde_json::Map<String, serde_json::Value>,
+) -> Result<(), settings::SettingsError> {
+    let service = app.state::<settings::SettingsService>();
+    let mut merged = match serde_json::to_value(service.get()) {
+        Ok(serde_json::Value::Object(map)) => map,
+        _ => return Err("Failed to read the current settings".to_string().into()),
     };
-    let _ = store.reload();
 
-    // 保存所有设置值
-    if let Some(api_key) = settings.get("api_key") {
-        store.set("api_key", api_key.clone());
-    }
-    if let Some(api_url) = settings.get("api_url") {
-        store.set("api_url", api_url.clone());
-    }
-    if let Some(model_name) = settings.get("model_name") {
-        store.set("model_name", model_name.clone());
-    }
-    if let Some(shortcut) = settings.get("shortcut") {
-        store.set("shortcut", shortcut.clone());
-        let _ = register_shortcut(app.clone(), shortcut.as_str().unwrap().to_string());
-    }
-    if let Some(system_prompt) = settings.get("system_prompt") {
-        store.set("system_prompt", system_prompt.clone());
-    }
-    if let Some(api_type) = settings.get("api_type") {
-        store.set("api_type", api_type.clone());
+    let mut errors = std::collections::BTreeMap::new();
+    for (key, value) in patch {
+        let Some(current) = merged.get(&key).cloned() else {
+            errors.insert(key, "Unknown setting".to_string());
+            continue;
+        };
+        merged.insert(key.clone(), value);
+        if let Err(e) = serde_json::from_value::<settings::Settings>(serde_json::Value::Object(merged.clone())) {
+            merged.insert(key.clone(), current);
+            errors.insert(key, format!("Invalid value: {}", e));
+        }
     }
-    if let Some(clear_chat_shortcut) = settings.get("clear_chat_shortcut") {
-        store.set("clear_chat_shortcut", clear_chat_shortcut.clone());
+    if !errors.is_empty() {
+        return Err(settings::SettingsError::Invalid { fields: errors });
     }
-    if let Some(borderless_shortcut) = settings.get("borderless_shortcut") {
-        store.set("borderless_shortcut", borderless_shortcut.clone());
+
+    let settings = serde_json::from_value(serde_json::Value::Object(merged)).map_err(|e| e.to_string())?;
+    save_settings(&app, settings)
+}
+
+/// 校验并保存完整的设置，成功后通过 `settings-changed` 事件通知所有窗口
+fn save_settings(app: &AppHandle, mut settings: settings::Settings) -> Result<(), settings::SettingsError> {
+    let service = app.state::<settings::SettingsService>();
+    let stored = service.get();
+
+    // 按实际生效的值校验；环境变量覆盖的字段保存 settings.dat 中原来的值
+    let overrides = service.env_overrides();
+    overrides.apply(&mut settings);
+    settings::validate(&settings)?;
+    overrides.restore_stored(&mut settings, &stored);
+
+    // 新快捷键无法注册（例如已被其它程序占用）时恢复原来的快捷键，不保存设置
+    let previous_shortcut = stored.shortcut;
+    if previous_shortcut != settings.shortcut {
+        if let Err(e) = register_shortcut(app.clone(), settings.shortcut.clone()) {
+            let _ = register_shortcut(app.clone(), previous_shortcut);
+            return Err(settings::SettingsError::field(
+                "shortcut",
+                format!("The shortcut could not be registered: {}", e),
+            ));
+        }
     }
 
-    store.save().map_err(|e| e.to_string())?;
+    service.update(app, &settings)?;
     Ok(())
 }
 
+#[tauri::command]
+fn list_prompts(library: State<'_, prompts::PromptLibrary>) -> Vec<prompts::Prompt> {
+    library.list()
+}
+
+#[tauri::command]
+fn create_prompt(library: State<'_, prompts::PromptLibrary>, draft: prompts::PromptDraft) -> Result<prompts::Prompt, String> {
+    library.create(draft)
+}
+
+#[tauri::command]
+fn update_prompt(
+    library: State<'_, prompts::PromptLibrary>,
+    id: String,
+    draft: prompts::PromptDraft,
+) -> Result<prompts::Prompt, String> {
+    library.update(&id, draft)
+}
+
+#[tauri::command]
+fn delete_prompt(library: State<'_, prompts::PromptLibrary>, id: String) -> Result<(), String> {
+    library.delete(&id)
+}
+
+/// 导出提示库，`ids` 为空时导出全部提示。用户取消时返回 None，否则返回导出的提示数。
+#[tauri::command]
+async fn export_prompts(app: AppHandle, ids: Option<Vec<String>>) -> Result<Option<u32>, String> {
+    use tauri_plugin_dialog::DialogExt;
+
+    tokio::task::spawn_blocking(move || {
+        let picked = app
+            .dialog()
+            .file()
+            .set_file_name(prompts::PROMPTS_FILE)
+            .add_filter("JSON", &["json"])
+            .blocking_save_file();
+        let Some(path) = picked else {
+            return Ok(None);
+        };
+        let path = path.into_path().map_err(|e| e.to_string())?;
+        let library = app.state::<prompts::PromptLibrary>();
+        library.export(&ids.unwrap_or_default(), &path).map(Some)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+/// 导入导出的提示库。未指定路径时弹出文件选择对话框，用户取消时返回 None。
+#[tauri::command]
+async fn import_prompts(app: AppHandle, path: Option<String>) -> Result<Option<prompts::PromptImportReport>, String> {
+    use tauri_plugin_dialog::DialogExt;
+
+    tokio::task::spawn_blocking(move || {
+        let path = match path {
+            Some(path) => std::path::PathBuf::from(path),
+            None => {
+                let picked = app
+                    .dialog()
+                    .file()
+                    .add_filter("JSON", &["json"])
+                    .blocking_pick_file();
+                match picked {
+                    Some(picked) => picked.into_path().map_err(|e| e.to_string())?,
+                    None => return Ok(None),
+                }
+            }
+        };
+        let data = std::fs::read_to_string(&path)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        let library = app.state::<prompts::PromptLibrary>();
+        library.import(&data).map(Some)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
 #[tauri::command]
 fn extract_text(app: AppHandle, bytes: Vec<u8>, file_name: String) -> Result<String, String> {
     // 文件扩展名验证
@@ -350,9 +394,9 @@ struct ConversationMessage {
     // Content can be a simple string or a more complex structure (e.g., for images)
     #[ts(type = "any")]
     content: serde_json::Value,
-    // Attachment is now part of the message from the frontend
+    // Attachments (images and extracted documents) sent along with the message
     #[serde(default)]
-    attachment: Option<Attachment>,
+    attachments: Vec<Attachment>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, TS)]
@@ -399,7 +443,7 @@ struct ResponseMessage {
     content: String,
 }
 
-#[derive(Debug, Serialize, TS)]
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
 #[ts(export)]
 #[serde(tag = "type", content = "message")]
 pub enum ApiError {
@@ -410,244 +454,1068 @@ pub enum ApiError {
     RateLimitExceeded,
     ApiResponseError(String),
     InternalError(String),
+    InvalidAttachment(String),
+    Timeout {
+        timeout_secs: Option<u32>,
+    },
+    ContextLengthExceeded {
+        message: String,
+        max_tokens: Option<u32>,
+        requested_tokens: Option<u32>,
+    },
+    ContentFiltered {
+        message: String,
+        category: Option<String>,
+    },
+    InsufficientQuota {
+        message: String,
+    },
+    ModelNotFound {
+        model: String,
+        message: String,
+    },
+    Overloaded {
+        message: String,
+        retry_after_secs: Option<u32>,
+    },
+    Cancelled,
+    /// 已启用静态加密但尚未解锁，无法读取 API 密钥
+    VaultLocked,
+    /// 服务商拒绝了请求（400），例如模型不支持某个参数
+    BadRequest {
+        message: String,
+        param: Option<String>,
+    },
+    /// 能解析出错误信封、但不属于以上任何类别的错误
+    ProviderError {
+        status: u16,
+        error_type: Option<String>,
+        code: Option<String>,
+        message: String,
+        param: Option<String>,
+    },
 }
 
-#[tauri::command]
-async fn ask_ai(app: AppHandle, messages: Vec<ConversationMessage>) -> Result<String, ApiError> {
-    let config_dir = app.path().app_config_dir().map_err(|e| ApiError::InternalError(e.to_string()))?;
-    let path = config_dir.join("settings.dat");
-    let store = match StoreBuilder::new(app.app_handle(), path).build() {
-        Ok(store) => store,
-        Err(e) => return Err(ApiError::InternalError(e.to_string())),
-    };
-    let _ = store.reload();
+/// 回答的默认最大字符数，超出部分会被明确截断
+const DEFAULT_MAX_RESPONSE_CHARS: usize = 100_000;
 
-    let api_key = store.get("api_key").map(|v| v.to_string()).unwrap_or_default();
-    
-    // 清理 API 密钥 - 移除可能的引号和多余空格
-    let api_key = api_key.trim().to_string();
-    let api_key = api_key.trim_matches('"').trim_matches('\'').to_string();
-        let api_type = store.get("api_type").map(|v| v.to_string()).unwrap_or("openai".to_string());
-    let api_type = api_type.trim().to_string();
-    let api_type = api_type.trim_matches('\"').trim_matches('\'').to_string();
-
-    let api_url = if api_type == "openai" {
-        "https://api.openai.com/v1/chat/completions".to_string()
+/// 请求超时时间（秒）
+const REQUEST_TIMEOUT_SECS: u32 = 30;
+
+/// 用于取消用户发起的请求（ask_ai、手动压缩和重放），后台生成标题和摘要的请求不受影响
+static CANCEL_REQUESTS: tokio::sync::Notify = tokio::sync::Notify::const_new();
+
+/// 一组命名的 API 连接配置，保存在设置的 `profiles` 列表中，`active_profile` 指定当前使用的配置。
+#[derive(Serialize, Deserialize, Debug, Clone, TS)]
+#[ts(export)]
+struct ApiProfile {
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    api_type: String,
+    #[serde(default)]
+    api_url: String,
+    #[serde(default)]
+    api_key: String,
+    #[serde(default)]
+    model_name: String,
+}
+
+/// 实际请求的 API 地址：OpenAI 类型使用固定地址，其它类型移除引号和多余空格并补全协议前缀。
+/// 未设置地址时返回空字符串。
+fn effective_api_url(api_type: &str, api_url: &str) -> String {
+    if api_type == "openai" {
+        return "https://api.openai.com/v1/chat/completions".to_string();
+    }
+    let api_url = api_url.trim().trim_matches('"').trim();
+    if api_url.is_empty() || api_url.starts_with("http://") || api_url.starts_with("https://") {
+        api_url.to_string()
     } else {
-        store.get("api_url").map(|v| v.to_string()).unwrap_or_default()
-    };
-    
+        format!("https://{}", api_url)
+    }
+}
+
+/// 校验并规范化 API 配置（补全 URL、验证模型名称和密钥）
+fn resolve_profile(app: &AppHandle, mut profile: ApiProfile, allowlist: &[String]) -> Result<ApiProfile, ApiError> {
+    // mock 类型不访问网络，api_url 仅用于指定 fixture 文件
+    if profile.api_type == "mock" {
+        return Ok(profile);
+    }
+
     // 验证 API URL
-    if api_url.trim().is_empty() {
-        SecurityLogger::log_security_violation(&app, "API URL is empty");
+    let api_url = effective_api_url(&profile.api_type, &profile.api_url);
+    if api_url.is_empty() {
+        SecurityLogger::log_security_violation(app, "API URL is empty");
         return Err(ApiError::InvalidApiUrl("API URL cannot be empty. Please set it in the settings.".to_string()));
     }
-    
-    // 确保 URL 是有效的 - 移除可能的引号和多余空格
-    let api_url = api_url.trim().to_string();
-    let api_url = api_url.trim_matches('"').to_string();
-    
-    // 确保 URL 有协议前缀
-    let api_url = if !api_url.starts_with("http://") && !api_url.starts_with("https://") {
-        format!("https://{}", api_url)
-    } else {
-        api_url
-    };
-    
-    if let Err(e) = InputValidator::validate_url(&api_url) {
-        SecurityLogger::log_security_violation(&app, &format!("Invalid API URL: {}", e));
+
+    if let Err(e) = InputValidator::validate_url(&api_url, allowlist) {
+        SecurityLogger::log_security_violation(app, &format!("Invalid API URL: {}", e));
         return Err(ApiError::InvalidApiUrl(e));
     }
-    
-    // 清理模型名称 - 移除可能的引号和多余空格
-    let model_name = store.get("model_name").map(|v| v.to_string()).unwrap_or("gpt-4o-mini".to_string());
-    let model_name = model_name.trim().to_string();
-    let model_name = model_name.trim_matches('"').trim_matches('\'').to_string();
 
-    if let Err(e) = InputValidator::validate_model_name(&model_name) {
-        SecurityLogger::log_security_violation(&app, &format!("Invalid model name: {}", e));
+    if let Err(e) = providers::validate_model_name(&profile.api_type, &api_url, &profile.model_name) {
+        SecurityLogger::log_security_violation(app, &format!("Invalid model name: {}", e));
         return Err(ApiError::InvalidModelName(e));
     }
-    
-    // 清理系统提示 - 移除可能的引号和多余空格
-    let system_prompt = store.get("system_prompt").map(|v| v.to_string()).unwrap_or("You are a helpful assistant.".to_string());
-    let system_prompt = system_prompt.trim().to_string();
-    let system_prompt = system_prompt.trim_matches('"').trim_matches('\'').to_string();
+
+    // 启用加密时 API 密钥以密文保存，需要先解锁
+    profile.api_key = vault::reveal(&profile.api_key).map_err(|e| match e {
+        vault::VaultError::Locked => ApiError::VaultLocked,
+        other => ApiError::InternalError(other.to_string()),
+    })?;
 
     // API密钥验证
-    if api_key.is_empty() || api_key == "your_api_key_here" {
-        SecurityLogger::log_security_violation(&app, "API key not set");
+    if profile.api_key.is_empty() || profile.api_key == "your_api_key_here" {
+        SecurityLogger::log_security_violation(app, "API key not set");
         return Err(ApiError::InvalidApiKey);
     }
 
-    // --- Build Messages for API ---
-    let mut messages_to_send = Vec::new();
+    profile.api_url = api_url;
+    Ok(profile)
+}
 
-    // 1. Add System Prompt
-    if !system_prompt.is_empty() {
-        messages_to_send.push(serde_json::json!({
-            "role": "system",
-            "content": system_prompt.clone()
-        }));
+/// 从响应文本中提取回答内容，兼容 OpenAI 及各种兼容网关的格式
+fn parse_chat_response(response_text: &str) -> Option<String> {
+    // 首先尝试解析为标准的 OpenAI 格式
+    if let Ok(openai_response) = serde_json::from_str::<OpenAIChatResponse>(response_text) {
+        if let Some(choice) = openai_response.choices.first() {
+            return Some(choice.message.content.clone());
+        }
     }
 
-    // 2. Process User and Assistant Messages
-    for message in messages {
-        let role = message.role;
-        let mut final_content = message.content;
-
-        // If there's an attachment and it's an image, create a multi-part content
-        if let Some(attachment) = message.attachment {
-            if attachment.attachment_type == "image" {
-                let text_content = final_content.as_str().unwrap_or("").to_string();
-                
-                final_content = serde_json::json!([
-                    {
-                        "type": "text",
-                        "text": InputValidator::sanitize_system_prompt(&text_content)
-                    },
-                    {
-                        "type": "image_url",
-                        "image_url": {
-                            "url": attachment.content // This is the base64 data URL
-                        }
-                    }
-                ]);
-            }
-        } else {
-            // For messages without image attachments, just sanitize the text content
-            if let Some(text) = final_content.as_str() {
-                final_content = serde_json::Value::String(InputValidator::sanitize_system_prompt(text));
+    // 尝试解析为通用格式
+    if let Ok(generic_response) = serde_json::from_str::<GenericChatResponse>(response_text) {
+        if let Some(choice) = generic_response.choices.and_then(|mut choices| choices.pop()) {
+            return Some(choice.message.content);
+        }
+        if let Some(message) = generic_response.message {
+            return Some(message.content);
+        }
+        if let Some(content) = generic_response.content {
+            return Some(content);
+        }
+    }
+
+    // 如果无法解析为标准格式，尝试手动解析 JSON 查找常见字段
+    if let Ok(json_value) = serde_json::from_str::<serde_json::Value>(response_text) {
+        let candidates = [
+            &json_value["choices"][0]["message"]["content"],
+            &json_value["message"]["content"],
+            &json_value["content"],
+            &json_value["result"],
+        ];
+        for candidate in candidates {
+            if let Some(content) = candidate.as_str() {
+                return Some(content.to_string());
             }
         }
+    }
 
-        messages_to_send.push(serde_json::json!({
-            "role": role,
-            "content": final_content
-        }));
+    None
+}
+
+/// 按设置截断回答，截断时记录日志
+fn apply_response_limit(app: &AppHandle, answer: String, max_chars: usize) -> String {
+    let (answer, truncated) = InputValidator::truncate_chars(&answer, max_chars);
+    if truncated {
+        SecurityLogger::log_security_event_with_file(
+            app,
+            &format!("API response truncated to {} characters", max_chars),
+            "INFO",
+        );
+    }
+    answer
+}
+
+/// 单次请求的附加选项
+#[derive(Debug, Clone, Default)]
+struct RequestOptions {
+    /// 是否记录本次请求（开发者模式）
+    capture_enabled: bool,
+    /// 重放时记录原始捕获的 id
+    replay_of: Option<String>,
+    /// 允许访问的内部网关
+    endpoint_allowlist: Vec<String>,
+    /// 收到通知时取消本次请求。用户发起的请求使用 `CANCEL_REQUESTS`，为 None 时不能取消
+    cancel: Option<&'static tokio::sync::Notify>,
+}
+
+/// 执行 `request`，`cancel` 收到通知时放弃并返回 `ApiError::Cancelled`
+async fn cancellable<T>(
+    app: &AppHandle,
+    cancel: Option<&tokio::sync::Notify>,
+    request: impl std::future::Future<Output = Result<T, ApiError>>,
+) -> Result<T, ApiError> {
+    let Some(cancel) = cancel else {
+        return request.await;
+    };
+    tokio::select! {
+        result = request => result,
+        _ = cancel.notified() => {
+            SecurityLogger::log_security_event_with_file(app, "API request cancelled by user", "INFO");
+            Err(ApiError::Cancelled)
+        }
     }
+}
 
+/// 发送已构建好的消息列表。开发者模式下会记录本次请求与响应。
+async fn send_chat_request(
+    app: &AppHandle,
+    profile: &ApiProfile,
+    messages_to_send: Vec<serde_json::Value>,
+    options: RequestOptions,
+) -> Result<String, ApiError> {
+    if profile.api_type == "mock" {
+        return cancellable(app, options.cancel, mock::respond(app, &profile.api_url, &messages_to_send)).await;
+    }
 
     // 记录API请求
-    SecurityLogger::log_api_request(&app, &api_url, &model_name);
+    SecurityLogger::log_api_request(app, &profile.api_url, &profile.model_name);
+
+    // 解析并检查目标地址，连接固定使用检查过的地址，防止 DNS 重绑定
+    let endpoint = net_policy::vet_endpoint(&profile.api_url, &options.endpoint_allowlist)
+        .await
+        .map_err(|e| {
+            SecurityLogger::log_security_violation(app, &format!("Blocked API endpoint: {}", e));
+            ApiError::InvalidApiUrl(e)
+        })?;
+
+    // 只允许同一主机内的重定向，避免被重定向到内网地址
+    let redirect_host = endpoint.host.clone();
+    let redirect_policy = reqwest::redirect::Policy::custom(move |attempt| {
+        if attempt.previous().len() >= 5 {
+            attempt.error("too many redirects")
+        } else if attempt.url().host_str() == Some(redirect_host.as_str()) {
+            attempt.follow()
+        } else {
+            attempt.error("redirects to a different host are not allowed")
+        }
+    });
+
+    let mut client_builder = Client::builder()
+        .timeout(Duration::from_secs(REQUEST_TIMEOUT_SECS as u64)) // 设置超时
+        .redirect(redirect_policy)
+        // 代理会自行解析主机名，绕过上面检查并固定的地址
+        .no_proxy();
+    if let Some(addr) = endpoint.pinned_addr {
+        client_builder = client_builder.resolve(&endpoint.host, addr);
+    }
 
-    let client = Client::builder()
-        .timeout(Duration::from_secs(30)) // 设置超时
+    let client = client_builder
         .build()
         .map_err(|e| {
-            SecurityLogger::log_error(&app, &format!("Failed to create HTTP client: {}", e));
+            SecurityLogger::log_error(app, &format!("Failed to create HTTP client: {}", e));
             ApiError::InternalError(format!("Failed to create HTTP client: {}", e))
         })?;
 
     let request_body = AIRequest {
-        model: model_name.clone(),
+        model: profile.model_name.clone(),
         messages: messages_to_send,
         stream: false,
     };
 
-    let mut request = client.post(&api_url);
-    
-    // 设置 Content-Type 头
-    request = request.header("Content-Type", "application/json");
-    
-    // 根据 API 类型设置认证方式
-    if api_type == "openai" {
-        request = request.bearer_auth(&api_key);
+    let headers = [
+        ("Content-Type", "application/json".to_string()),
+        ("Authorization", format!("Bearer {}", profile.api_key)),
+    ];
+
+    let mut capture = if options.capture_enabled {
+        let body = serde_json::to_value(&request_body).unwrap_or(serde_json::Value::Null);
+        Some(recorder::Capture {
+            id: recorder::new_capture_id(),
+            timestamp: chrono::Local::now().to_rfc3339(),
+            api_type: profile.api_type.clone(),
+            model_name: profile.model_name.clone(),
+            replay_of: options.replay_of,
+            request: recorder::CapturedRequest {
+                url: profile.api_url.clone(),
+                headers: recorder::redact_headers(&headers, &profile.api_key),
+                body: recorder::redact_json(&body, &profile.api_key),
+            },
+            response: None,
+            error: None,
+            duration_ms: 0,
+        })
     } else {
-        // 对于兼容 API，直接设置 Authorization 头
-        request = request.header("Authorization", format!("Bearer {}", api_key));
+        None
+    };
+
+    let started = Instant::now();
+    let mut request = client.post(&profile.api_url);
+    for (name, value) in &headers {
+        request = request.header(*name, value);
     }
-    
-    let res = request
-        .json(&request_body)
-        .send()
-        .await
-        .map_err(|e| {
-            SecurityLogger::log_error(&app, &format!("API request failed: {}", e));
-            ApiError::NetworkError(e.to_string())
-        })?;
 
-    if res.status().is_success() {
-        let response_text = res.text().await.map_err(|e| {
-            SecurityLogger::log_error(&app, &format!("Failed to read response: {}", e));
-            ApiError::ApiResponseError(format!("Failed to read response: {}", e))
-        })?;
-        
-        // 首先尝试解析为标准的 OpenAI 格式
-        if let Ok(openai_response) = serde_json::from_str::<OpenAIChatResponse>(&response_text) {
-            if let Some(choice) = openai_response.choices.get(0) {
-                let content = InputValidator::sanitize_system_prompt(&choice.message.content);
-                return Ok(content.trim().to_string());
-            }
-        }
-        
-        // 尝试解析为通用格式
-        if let Ok(generic_response) = serde_json::from_str::<GenericChatResponse>(&response_text) {
-            if let Some(choice) = generic_response.choices.and_then(|mut choices| choices.pop()) {
-                let content = InputValidator::sanitize_system_prompt(&choice.message.content);
-                return Ok(content.trim().to_string());
+    let exchange = async {
+        let res = request.json(&request_body).send().await?;
+        let status = res.status();
+        let retry_after_secs = res
+            .headers()
+            .get("retry-after")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.trim().parse::<u32>().ok());
+        let text = res.text().await?;
+        Ok::<_, reqwest::Error>((status, retry_after_secs, text))
+    };
+
+    let result = cancellable(app, options.cancel, async {
+        exchange.await.map_err(|e| {
+            SecurityLogger::log_error(app, &format!("API request failed: {}", e));
+            if e.is_timeout() {
+                ApiError::Timeout { timeout_secs: Some(REQUEST_TIMEOUT_SECS) }
+            } else {
+                ApiError::NetworkError(e.to_string())
             }
-            if let Some(message) = generic_response.message {
-                let content = InputValidator::sanitize_system_prompt(&message.content);
-                return Ok(content.trim().to_string());
+        })
+    })
+    .await;
+
+    let (status, retry_after_secs, response_text) = match result {
+        Ok(exchange) => exchange,
+        Err(error) => {
+            if let Some(capture) = capture.as_mut() {
+                capture.error = Some(recorder::redact_text(&format!("{:?}", error), &profile.api_key));
+                capture.duration_ms = started.elapsed().as_millis() as u64;
+                recorder::save_capture(app, capture);
             }
-            if let Some(content) = generic_response.content {
-                let sanitized_content = InputValidator::sanitize_system_prompt(&content);
-                return Ok(sanitized_content.trim().to_string());
+            return Err(error);
+        }
+    };
+
+    // 记录的捕获 id，用于在错误信息中引用
+    let capture_id = capture.as_mut().map(|capture| {
+        capture.response = Some(recorder::CapturedResponse {
+            status: status.as_u16(),
+            body: recorder::redact_text(&response_text, &profile.api_key),
+        });
+        capture.duration_ms = started.elapsed().as_millis() as u64;
+        capture.id.clone()
+    });
+
+    let outcome = if status.is_success() {
+        match parse_chat_response(&response_text) {
+            Some(content) => Ok(content.trim().to_string()),
+            None => {
+                // 部分网关在 200 响应中返回错误信封或内容过滤标记
+                if let Some(error) = api_errors::content_filter_from_success(&response_text) {
+                    SecurityLogger::log_error(app, "API response was blocked by content filter");
+                    Err(error)
+                } else if api_errors::parse_envelope(&response_text).is_some() {
+                    SecurityLogger::log_error(app, "API returned an error envelope with a success status");
+                    Err(api_errors::classify(status.as_u16(), &response_text, retry_after_secs, &profile.model_name))
+                } else {
+                    // 如果无法解析为标准格式，返回原始响应用于调试
+                    SecurityLogger::log_error(app, &format!("Unable to parse API response. URL: {}, Model: {}", profile.api_url, profile.model_name));
+                    let detail = match &capture_id {
+                        Some(id) => format!("The full exchange was saved as capture {}.", id),
+                        None => format!("Raw response: {}", response_text),
+                    };
+                    Err(ApiError::ApiResponseError(format!("Unable to parse API response. Please check if the API endpoint and model name are correct. {}", detail)))
+                }
             }
         }
-        
-        // 如果无法解析为标准格式，尝试手动解析 JSON 查找常见字段
-        if let Ok(json_value) = serde_json::from_str::<serde_json::Value>(&response_text) {
-            // 尝试从 choices[0].message.content 获取
-            if let Some(content) = json_value["choices"][0]["message"]["content"].as_str() {
-                let sanitized_content = InputValidator::sanitize_system_prompt(content);
-                return Ok(sanitized_content.trim().to_string());
+    } else {
+        // 提供更详细的错误信息
+        let error = api_errors::classify(status.as_u16(), &response_text, retry_after_secs, &profile.model_name);
+        match &error {
+            ApiError::InvalidApiKey => SecurityLogger::log_security_violation(app, "API authentication failed"),
+            ApiError::RateLimitExceeded => SecurityLogger::log_security_event_with_file(app, "Rate limit exceeded", "WARNING"),
+            _ => SecurityLogger::log_error(app, &format!("API request failed with status: {}", status)),
+        }
+        Err(error)
+    };
+
+    if let Some(capture) = capture.as_mut() {
+        if let Err(e) = &outcome {
+            capture.error = Some(recorder::redact_text(&format!("{:?}", e), &profile.api_key));
+        }
+        recorder::save_capture(app, capture);
+    }
+
+    outcome
+}
+
+#[tauri::command]
+async fn ask_ai(
+    app: AppHandle,
+    history: State<'_, history::HistoryStore>,
+    messages: Vec<ConversationMessage>,
+    conversation_id: Option<String>,
+    locale: Option<String>,
+) -> Result<String, ApiError> {
+    let settings = settings::Settings::load(&app).map_err(ApiError::InternalError)?;
+    let endpoint_allowlist = settings.endpoint_allowlist.clone();
+    let profile = resolve_profile(&app, settings.current_profile(), &endpoint_allowlist)?;
+    // 隐身模式下不捕获请求，也不读写历史（包括压缩记录）
+    let incognito = privacy::is_incognito();
+    let options = RequestOptions {
+        capture_enabled: !incognito && settings.capture_requests,
+        replay_of: None,
+        endpoint_allowlist: endpoint_allowlist.clone(),
+        cancel: Some(&CANCEL_REQUESTS),
+    };
+    let conversation_id = conversation_id.filter(|_| !incognito);
+
+    // 系统提示模板中的 {{input}} 为本次发送的用户消息
+    let input = messages
+        .iter()
+        .rev()
+        .find(|message| message.role == "user")
+        .map(|message| history::message_text(&message.content))
+        .unwrap_or_default();
+    let locale = locale.unwrap_or_else(|| "en".to_string());
+    let mut system_prompt = prompts::render_system_prompt(&app, &settings, &input, &locale);
+
+    // 已保存的对话从历史中重建上下文，接近上下文窗口时先压缩较早的轮次
+    let messages = match conversation_id {
+        Some(conversation_id) => {
+            let compaction_settings = compaction::CompactionSettings::load(&settings);
+            let reserved_tokens = compaction::estimate_text_tokens(&system_prompt);
+            let compacted = compaction::compact_if_needed(
+                &app,
+                &history,
+                &conversation_id,
+                settings.summary_profile(&profile),
+                &compaction_settings,
+                Some(reserved_tokens),
+                endpoint_allowlist,
+            )
+            .await;
+            // 压缩失败时仍然发送完整的上下文，由服务端决定是否超出限制
+            if let Err(e) = compacted {
+                SecurityLogger::log_error(&app, &format!("Context compaction failed: {:?}", e));
             }
-            // 尝试从 message.content 获取
-            if let Some(content) = json_value["message"]["content"].as_str() {
-                let sanitized_content = InputValidator::sanitize_system_prompt(content);
-                return Ok(sanitized_content.trim().to_string());
+            let context = history.context(&conversation_id).map_err(ApiError::InternalError)?;
+            // 压缩的摘要并入开头的系统提示
+            if let Some(summary) = &context.summary {
+                let summary = history::summary_prompt(summary);
+                system_prompt = if system_prompt.is_empty() {
+                    summary
+                } else {
+                    format!("{}\n\n{}", system_prompt, summary)
+                };
             }
-            // 尝试从 content 直接获取
-            if let Some(content) = json_value["content"].as_str() {
-                let sanitized_content = InputValidator::sanitize_system_prompt(content);
-                return Ok(sanitized_content.trim().to_string());
+            context.entries.into_iter().map(|entry| entry.message).collect()
+        }
+        None => messages,
+    };
+
+    // --- Build Messages for API ---
+    let mut messages_to_send = Vec::new();
+
+    // 1. Add System Prompt
+    if !system_prompt.is_empty() {
+        messages_to_send.push(serde_json::json!({
+            "role": "system",
+            "content": system_prompt.clone()
+        }));
+    }
+
+    // 2. Process User and Assistant Messages
+    for message in &messages {
+        if let Err(e) = attachments::validate_limits(&message.attachments) {
+            SecurityLogger::log_security_violation(&app, &format!("Attachment limit exceeded: {}", e));
+            return Err(ApiError::InvalidAttachment(e));
+        }
+    }
+
+    // 图片解码和重新编码比较耗时，放到阻塞线程池中执行
+    let provider = providers::ProviderKind::detect(&profile.api_type, &profile.api_url);
+    let limits = image_processing::limits_for(provider);
+    let format = attachments::ContentFormat::detect(provider, &profile.api_url);
+    let conversation = tokio::task::spawn_blocking(move || {
+        messages
+            .into_iter()
+            .map(|message| {
+                attachments::build_message(&message.role, message.content, &message.attachments, limits, format)
+            })
+            .collect::<Result<Vec<_>, String>>()
+    })
+    .await
+    .map_err(|e| ApiError::InternalError(e.to_string()))?
+    .map_err(|e| {
+        SecurityLogger::log_error(&app, &format!("Attachment processing failed: {}", e));
+        ApiError::InvalidAttachment(e)
+    })?;
+    messages_to_send.extend(conversation);
+
+    let answer = send_chat_request(&app, &profile, messages_to_send, options).await?;
+    Ok(apply_response_limit(&app, answer, settings.max_response_chars as usize))
+}
+
+/// 清理前端渲染出的 HTML，在显示模型回答之前调用
+#[tauri::command]
+fn sanitize_html(html: String) -> String {
+    display::sanitize_html(&html)
+}
+
+/// 取消所有进行中的 ask_ai 请求，被取消的请求返回 `ApiError::Cancelled`
+#[tauri::command]
+fn cancel_ask_ai() {
+    CANCEL_REQUESTS.notify_waiters();
+}
+
+/// 预处理图片附件（缩放、压缩、去除元数据），返回最终的数据和大小供界面显示
+#[tauri::command]
+async fn preprocess_image(app: AppHandle, data_url: String) -> Result<image_processing::ProcessedImage, String> {
+    let profile = settings::Settings::load(&app)?.current_profile();
+    let limits = image_processing::limits_for(providers::ProviderKind::detect(&profile.api_type, &profile.api_url));
+    tokio::task::spawn_blocking(move || image_processing::process_data_url(&data_url, limits))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+// --- Conversation History Commands ---
+
+#[tauri::command]
+async fn create_conversation(
+    history: State<'_, history::HistoryStore>,
+    title: Option<String>,
+    profile: Option<String>,
+) -> Result<history::Conversation, String> {
+    privacy::ensure_history_allowed()?;
+    history.create_conversation(title, profile)
+}
+
+/// 追加消息到对话。助手消息未指定模型时，记录当前设置中的模型
+#[tauri::command]
+async fn append_message(
+    app: AppHandle,
+    history: State<'_, history::HistoryStore>,
+    conversation_id: String,
+    message: ConversationMessage,
+    model: Option<String>,
+    locale: Option<String>,
+) -> Result<history::StoredMessage, String> {
+    privacy::ensure_history_allowed()?;
+    let is_assistant = message.role == "assistant";
+    let model = match model {
+        Some(model) => Some(model),
+        None if message.role == "assistant" => Some(settings::Settings::load(&app)?.current_profile().model_name),
+        None => None,
+    };
+    let stored = history.append_message(&conversation_id, message, model)?;
+    // 每轮回答保存后在后台生成标题和更新摘要
+    if is_assistant {
+        summaries::spawn_refresh(app, conversation_id, locale);
+    }
+    Ok(stored)
+}
+
+#[tauri::command]
+async fn list_conversations(
+    history: State<'_, history::HistoryStore>,
+    offset: Option<u32>,
+    limit: Option<u32>,
+    filter: Option<history::ConversationFilter>,
+) -> Result<history::ConversationPage, String> {
+    history.list_conversations(&filter.unwrap_or_default(), offset.unwrap_or(0), limit.unwrap_or(50))
+}
+
+#[tauri::command]
+async fn create_folder(history: State<'_, history::HistoryStore>, name: String) -> Result<history::Folder, String> {
+    history.create_folder(&name)
+}
+
+#[tauri::command]
+async fn rename_folder(history: State<'_, history::HistoryStore>, id: String, name: String) -> Result<(), String> {
+    history.rename_folder(&id, &name)
+}
+
+#[tauri::command]
+async fn delete_folder(history: State<'_, history::HistoryStore>, id: String) -> Result<(), String> {
+    history.delete_folder(&id)
+}
+
+#[tauri::command]
+async fn list_folders(history: State<'_, history::HistoryStore>) -> Result<Vec<history::Folder>, String> {
+    history.list_folders()
+}
+
+#[tauri::command]
+async fn list_tags(history: State<'_, history::HistoryStore>) -> Result<Vec<history::TagCount>, String> {
+    history.list_tags()
+}
+
+/// 把对话移动到文件夹，`folder_id` 为空时移出文件夹
+#[tauri::command]
+async fn move_conversations(
+    history: State<'_, history::HistoryStore>,
+    ids: Vec<String>,
+    folder_id: Option<String>,
+) -> Result<u32, String> {
+    history.move_conversations(&ids, folder_id.as_deref())
+}
+
+#[tauri::command]
+async fn tag_conversations(
+    history: State<'_, history::HistoryStore>,
+    ids: Vec<String>,
+    add: Option<Vec<String>>,
+    remove: Option<Vec<String>>,
+) -> Result<(), String> {
+    history.tag_conversations(&ids, &add.unwrap_or_default(), &remove.unwrap_or_default())
+}
+
+#[tauri::command]
+async fn pin_conversations(history: State<'_, history::HistoryStore>, ids: Vec<String>, pinned: bool) -> Result<u32, String> {
+    history.set_conversations_pinned(&ids, pinned)
+}
+
+#[tauri::command]
+async fn archive_conversations(
+    history: State<'_, history::HistoryStore>,
+    ids: Vec<String>,
+    archived: bool,
+) -> Result<u32, String> {
+    history.set_conversations_archived(&ids, archived)
+}
+
+#[tauri::command]
+async fn delete_conversations(history: State<'_, history::HistoryStore>, ids: Vec<String>) -> Result<u32, String> {
+    history.delete_conversations(&ids)
+}
+
+#[tauri::command]
+async fn load_conversation(history: State<'_, history::HistoryStore>, id: String) -> Result<history::Conversation, String> {
+    history.load_conversation(&id)
+}
+
+#[tauri::command]
+async fn rename_conversation(history: State<'_, history::HistoryStore>, id: String, title: String) -> Result<(), String> {
+    history.rename_conversation(&id, &title)
+}
+
+/// 尚未保存到历史的对话（无痕模式或保存失败）按窗口中的消息导出，标题取第一条用户消息的首行
+fn unsaved_conversation(app: &AppHandle, messages: Vec<ConversationMessage>) -> Result<history::Conversation, String> {
+    let now = chrono::Utc::now();
+    let title = messages
+        .iter()
+        .find(|message| message.role == "user")
+        .map(|message| {
+            let text = history::message_text(&message.content);
+            text.lines().next().unwrap_or_default().chars().take(60).collect::<String>()
+        })
+        .filter(|title| !title.trim().is_empty())
+        .unwrap_or_else(|| history::DEFAULT_TITLE.to_string());
+    let messages = messages
+        .into_iter()
+        .enumerate()
+        .map(|(index, message)| history::StoredMessage {
+            id: index.to_string(),
+            role: message.role,
+            content: message.content,
+            attachments: message.attachments,
+            model: None,
+            created_at: now,
+            parent_id: index.checked_sub(1).map(|parent| parent.to_string()),
+            sibling_ids: vec![index.to_string()],
+            pinned: false,
+        })
+        .collect();
+    Ok(history::Conversation {
+        id: String::new(),
+        title,
+        profile: settings::Settings::load(app)?.active_profile,
+        created_at: now,
+        updated_at: now,
+        summary: None,
+        folder_id: None,
+        tags: Vec::new(),
+        pinned: false,
+        archived: false,
+        active_leaf_id: None,
+        messages,
+    })
+}
+
+/// 导出对话（当前激活分支）。没有 `conversation_id` 时导出 `messages`。
+/// 未指定格式时由保存对话框中选择的扩展名决定，默认 Markdown。
+/// 用户取消对话框时返回 None，否则返回写入的文件路径。
+#[tauri::command]
+async fn export_conversation(
+    app: AppHandle,
+    history: State<'_, history::HistoryStore>,
+    conversation_id: Option<String>,
+    messages: Option<Vec<ConversationMessage>>,
+    format: Option<export::ExportFormat>,
+) -> Result<Option<String>, String> {
+    use tauri_plugin_dialog::DialogExt;
+
+    let conversation = match (conversation_id, messages) {
+        (Some(id), _) => history.load_conversation(&id)?,
+        (None, Some(messages)) if !messages.is_empty() => unsaved_conversation(&app, messages)?,
+        (None, _) => return Err("There is nothing to export yet".to_string()),
+    };
+    let default_format = format.unwrap_or(export::ExportFormat::Markdown);
+    let file_name = export::default_file_name(&conversation, default_format);
+
+    tokio::task::spawn_blocking(move || {
+        let mut dialog = app.dialog().file().set_file_name(file_name);
+        let formats: Vec<export::ExportFormat> = match format {
+            Some(format) => vec![format],
+            None => export::ExportFormat::ALL.to_vec(),
+        };
+        for format in formats {
+            dialog = dialog.add_filter(format.filter_name(), &[format.extension()]);
+        }
+        let Some(path) = dialog.blocking_save_file() else {
+            return Ok(None);
+        };
+        let mut path = path.into_path().map_err(|e| e.to_string())?;
+
+        let format = match (format, export::ExportFormat::from_path(&path)) {
+            (Some(format), _) | (None, Some(format)) => format,
+            (None, None) => default_format,
+        };
+        if export::ExportFormat::from_path(&path) != Some(format) {
+            let mut file_name = path.file_name().unwrap_or_default().to_os_string();
+            file_name.push(format!(".{}", format.extension()));
+            path.set_file_name(file_name);
+        }
+
+        export::export_conversation(&conversation, format, &path)?;
+        Ok(Some(path.to_string_lossy().into_owned()))
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+/// 从 ChatGPT、Claude 或本应用的导出文件导入对话。未指定路径时弹出文件选择对话框，
+/// 用户取消时返回 None。
+#[tauri::command]
+async fn import_conversations(app: AppHandle, path: Option<String>) -> Result<Option<history::ImportReport>, String> {
+    use tauri_plugin_dialog::DialogExt;
+
+    tokio::task::spawn_blocking(move || {
+        let path = match path {
+            Some(path) => std::path::PathBuf::from(path),
+            None => {
+                let picked = app
+                    .dialog()
+                    .file()
+                    .add_filter("JSON", &["json"])
+                    .blocking_pick_file();
+                match picked {
+                    Some(picked) => picked.into_path().map_err(|e| e.to_string())?,
+                    None => return Ok(None),
+                }
             }
-            // 尝试从 result 获取
-            if let Some(content) = json_value["result"].as_str() {
-                let sanitized_content = InputValidator::sanitize_system_prompt(content);
-                return Ok(sanitized_content.trim().to_string());
+        };
+        let data = std::fs::read_to_string(&path)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        let history = app.state::<history::HistoryStore>();
+        history.import_export(&data).map(Some)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+/// 立即执行历史保留策略，返回删除的对话数
+#[tauri::command]
+fn apply_retention(app: AppHandle) -> Result<u32, String> {
+    privacy::enforce_retention(&app)
+}
+
+/// 创建包含设置和历史的备份文件。用户在保存对话框中取消时返回 None。
+#[tauri::command]
+async fn create_backup(app: AppHandle, include_secrets: Option<bool>) -> Result<Option<backup::BackupManifest>, String> {
+    use tauri_plugin_dialog::DialogExt;
+
+    tokio::task::spawn_blocking(move || {
+        let picked = app
+            .dialog()
+            .file()
+            .set_file_name(backup::default_file_name())
+            .add_filter("AI Window Backup", &[backup::BACKUP_EXTENSION])
+            .blocking_save_file();
+        let Some(path) = picked else {
+            return Ok(None);
+        };
+        let path = path.into_path().map_err(|e| e.to_string())?;
+        backup::create_backup(&app, &path, include_secrets.unwrap_or(false)).map(Some)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+/// 校验并恢复备份。未指定路径时弹出文件选择对话框，用户取消时返回 None。
+/// 加密的备份需要提供创建备份时的主密码。
+#[tauri::command]
+async fn restore_backup(
+    app: AppHandle,
+    path: Option<String>,
+    mode: backup::RestoreMode,
+    passphrase: Option<String>,
+) -> Result<Option<backup::RestoreReport>, String> {
+    use tauri_plugin_dialog::DialogExt;
+
+    tokio::task::spawn_blocking(move || {
+        let path = match path {
+            Some(path) => std::path::PathBuf::from(path),
+            None => {
+                let picked = app
+                    .dialog()
+                    .file()
+                    .add_filter("AI Window Backup", &[backup::BACKUP_EXTENSION])
+                    .blocking_pick_file();
+                match picked {
+                    Some(picked) => picked.into_path().map_err(|e| e.to_string())?,
+                    None => return Ok(None),
+                }
             }
+        };
+        backup::restore_backup(&app, &path, mode, passphrase.as_deref()).map(Some)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+/// 手动压缩对话上下文，忽略自动压缩的阈值
+#[tauri::command]
+async fn compact_conversation(
+    app: AppHandle,
+    history: State<'_, history::HistoryStore>,
+    conversation_id: String,
+) -> Result<Option<history::Compaction>, ApiError> {
+    let settings = settings::Settings::load(&app).map_err(ApiError::InternalError)?;
+    let endpoint_allowlist = settings.endpoint_allowlist.clone();
+    let profile = settings.summary_profile(&settings.current_profile());
+    let compaction_settings = compaction::CompactionSettings::load(&settings);
+    compaction::compact_if_needed(&app, &history, &conversation_id, profile, &compaction_settings, None, endpoint_allowlist)
+        .await
+}
+
+#[tauri::command]
+async fn undo_compaction(
+    history: State<'_, history::HistoryStore>,
+    conversation_id: String,
+    compaction_id: String,
+) -> Result<history::Compaction, String> {
+    history.undo_compaction(&conversation_id, &compaction_id)
+}
+
+#[tauri::command]
+async fn list_compactions(
+    history: State<'_, history::HistoryStore>,
+    conversation_id: String,
+) -> Result<Vec<history::Compaction>, String> {
+    history.list_compactions(&conversation_id)
+}
+
+/// 固定的消息在压缩上下文时始终原样保留
+#[tauri::command]
+async fn pin_message(
+    history: State<'_, history::HistoryStore>,
+    conversation_id: String,
+    message_id: String,
+    pinned: bool,
+) -> Result<(), String> {
+    history.set_pinned(&conversation_id, &message_id, pinned)
+}
+
+#[tauri::command]
+fn vault_status(app: AppHandle) -> Result<vault::VaultStatus, String> {
+    let config_dir = app.path().app_config_dir().map_err(|e| e.to_string())?;
+    Ok(vault::status(&config_dir))
+}
+
+/// 启用静态加密：生成数据密钥、加密历史数据库和设置中的 API 密钥。
+/// Argon2 派生密钥较慢，所有步骤都在阻塞线程池中执行。
+#[tauri::command]
+async fn enable_encryption(app: AppHandle, passphrase: String) -> Result<(), String> {
+    tokio::task::spawn_blocking(move || {
+        let config_dir = app.path().app_config_dir().map_err(|e| e.to_string())?;
+        if vault::is_enabled(&config_dir) {
+            return Err("Encryption is already enabled".to_string());
         }
-        
-        // 如果无法解析为标准格式，返回原始响应用于调试
-        SecurityLogger::log_error(&app, &format!("Unable to parse API response. URL: {}, Model: {}", api_url, model_name));
-        Err(ApiError::ApiResponseError(format!("Unable to parse API response. Please check if the API endpoint and model name are correct. Raw response: {}", response_text)))
-    } else {
-        let status = res.status();
-        let error_body = res.text().await.map_err(|e| {
-            SecurityLogger::log_error(&app, &format!("Failed to read error response: {}", e));
-            ApiError::ApiResponseError(format!("Failed to read error response: {}", e))
+        let history = app.state::<history::HistoryStore>();
+        let new_key = vault::prepare_new_key(&passphrase)?;
+        let database_key = new_key.database_key();
+
+        // 先导出加密的数据库副本，写入 vault.json 之后再替换原文件
+        history.prepare_reencrypt(Some(&database_key))?;
+        vault::commit_new_key(&config_dir, new_key)?;
+        history.commit_reencrypt(Some(&database_key))?;
+
+        let service = app.state::<settings::SettingsService>();
+        vault::map_secrets(&service.store(), vault::seal_secret)?;
+        service.commit(&app)?;
+        settings::map_backup_secrets(&config_dir, vault::seal_secret)?;
+
+        SecurityLogger::log_security_event_with_file(&app, "Encryption at rest enabled", "INFO");
+        Ok(())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+async fn unlock_vault(app: AppHandle, passphrase: String) -> Result<(), String> {
+    tokio::task::spawn_blocking(move || {
+        let config_dir = app.path().app_config_dir().map_err(|e| e.to_string())?;
+        let database_key = vault::unlock(&config_dir, &passphrase).map_err(|e| {
+            if e == vault::VaultError::WrongPassphrase {
+                SecurityLogger::log_security_violation(&app, "Failed vault unlock attempt");
+            }
+            e.to_string()
         })?;
-        
-        // 提供更详细的错误信息
-        let error_message = if status.as_u16() == 401 {
-            SecurityLogger::log_security_violation(&app, "API authentication failed");
-            ApiError::InvalidApiKey
-        } else if status.as_u16() == 404 {
-            SecurityLogger::log_error(&app, "API endpoint not found");
-            ApiError::InvalidApiUrl("API endpoint not found. Please check the URL.".to_string())
-        } else if status.as_u16() == 429 {
-            SecurityLogger::log_security_event_with_file(&app, "Rate limit exceeded", "WARNING");
-            ApiError::RateLimitExceeded
-        } else {
-            SecurityLogger::log_error(&app, &format!("API request failed with status: {}", status));
-            ApiError::ApiResponseError(format!("API request failed with status {}: {}", status, error_body))
-        };
-        
-        Err(error_message)
+        app.state::<history::HistoryStore>().unlock(Some(&database_key))?;
+        // 完成启动时因尚未解锁而推迟的设置迁移
+        match settings::migrate(&app) {
+            Ok(0) => {}
+            Ok(_) => app.state::<settings::SettingsService>().commit(&app)?,
+            Err(e) => SecurityLogger::log_error(&app, &format!("Settings migration failed: {}", e)),
+        }
+        Ok(())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+/// 从内存中清除数据密钥并关闭加密的历史数据库
+#[tauri::command]
+fn lock_vault(app: AppHandle, history: State<'_, history::HistoryStore>) -> Result<(), String> {
+    let config_dir = app.path().app_config_dir().map_err(|e| e.to_string())?;
+    if !vault::is_enabled(&config_dir) {
+        return Err("Encryption is not enabled".to_string());
     }
+    vault::lock();
+    history.lock();
+    Ok(())
+}
+
+/// 更换主密码，同时更换数据密钥并重新加密历史数据库和设置中的 API 密钥
+#[tauri::command]
+async fn change_passphrase(app: AppHandle, current_passphrase: String, new_passphrase: String) -> Result<(), String> {
+    tokio::task::spawn_blocking(move || {
+        let config_dir = app.path().app_config_dir().map_err(|e| e.to_string())?;
+        let history = app.state::<history::HistoryStore>();
+        let database_key = vault::unlock(&config_dir, &current_passphrase)?;
+        if !history.is_open() {
+            history.unlock(Some(&database_key))?;
+        }
+        let previous = vault::current_data_key()?;
+        let new_key = vault::prepare_new_key(&new_passphrase)?;
+        let next = new_key.data_key();
+        let new_database_key = new_key.database_key();
+
+        // vault.json 是提交点：先保存用新密钥加密的设置和备份，最后才写入 vault.json
+        history.prepare_reencrypt(Some(&new_database_key))?;
+        let service = app.state::<settings::SettingsService>();
+        vault::map_secrets(&service.store(), |value| vault::reseal_secret(value, &previous, &next))?;
+        service.commit(&app)?;
+        settings::map_backup_secrets(&config_dir, |value| vault::reseal_secret(value, &previous, &next))?;
+
+        if let Err(e) = vault::commit_new_key(&config_dir, new_key) {
+            // vault.json 仍是旧密钥，把设置和备份改回旧密钥加密
+            vault::map_secrets(&service.store(), |value| vault::reseal_secret(value, &next, &previous))?;
+            service.commit(&app)?;
+            settings::map_backup_secrets(&config_dir, |value| vault::reseal_secret(value, &next, &previous))?;
+            return Err(e.to_string());
+        }
+        history.commit_reencrypt(Some(&new_database_key))?;
+
+        SecurityLogger::log_security_event_with_file(&app, "Master passphrase and data key changed", "INFO");
+        Ok(())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+/// 编辑过去的消息或重新生成回答：在 `message_id` 旁边创建一个新的兄弟分支
+#[tauri::command]
+async fn fork_message(
+    history: State<'_, history::HistoryStore>,
+    conversation_id: String,
+    message_id: String,
+    message: ConversationMessage,
+    model: Option<String>,
+) -> Result<history::StoredMessage, String> {
+    privacy::ensure_history_allowed()?;
+    history.fork_message(&conversation_id, &message_id, message, model)
+}
+
+#[tauri::command]
+async fn switch_branch(
+    history: State<'_, history::HistoryStore>,
+    conversation_id: String,
+    message_id: String,
+) -> Result<history::Conversation, String> {
+    history.switch_branch(&conversation_id, &message_id)
+}
+
+/// 返回 ask_ai 需要的线性消息列表，`leaf_id` 为空时使用当前激活分支
+#[tauri::command]
+async fn get_conversation_path(
+    history: State<'_, history::HistoryStore>,
+    conversation_id: String,
+    leaf_id: Option<String>,
+) -> Result<Vec<ConversationMessage>, String> {
+    history.conversation_path(&conversation_id, leaf_id.as_deref())
+}
+
+#[tauri::command]
+async fn search_history(
+    history: State<'_, history::HistoryStore>,
+    query: history::SearchQuery,
+) -> Result<Vec<history::SearchHit>, String> {
+    history.search(&query)
+}
+
+#[tauri::command]
+async fn delete_conversation(history: State<'_, history::HistoryStore>, id: String) -> Result<(), String> {
+    history.delete_conversation(&id)
+}
+
+// --- Developer Capture Commands ---
+
+#[tauri::command]
+fn list_captures(app: AppHandle) -> Result<Vec<recorder::CaptureSummary>, String> {
+    recorder::list_captures(&app)
+}
+
+#[tauri::command]
+fn clear_captures(app: AppHandle) -> Result<(), String> {
+    recorder::clear_captures(&app)
+}
+
+/// 将已记录的请求重新发送到另一个 API 配置，`profile_name` 为空时使用当前配置
+#[tauri::command]
+async fn replay_capture(app: AppHandle, capture_id: String, profile_name: Option<String>) -> Result<String, ApiError> {
+    let capture = recorder::load_capture(&app, &capture_id).map_err(ApiError::InternalError)?;
+
+    let settings = settings::Settings::load(&app).map_err(ApiError::InternalError)?;
+    let profile = match profile_name.as_deref() {
+        Some(name) if !name.is_empty() => settings
+            .named_profile(name)
+            .ok_or_else(|| ApiError::InternalError(format!("Profile '{}' not found", name)))?,
+        _ => settings.current_profile(),
+    };
+    let endpoint_allowlist = settings.endpoint_allowlist.clone();
+    let profile = resolve_profile(&app, profile, &endpoint_allowlist)?;
+
+    let messages_to_send = capture.request.body["messages"]
+        .as_array()
+        .cloned()
+        .ok_or_else(|| ApiError::InternalError(format!("Capture '{}' has no messages to replay", capture_id)))?;
+
+    SecurityLogger::log_security_event_with_file(
+        &app,
+        &format!("Replaying capture {} against profile '{}'", capture_id, profile.name),
+        "INFO",
+    );
+
+    // 与 ask_ai 相同：关闭捕获或处于隐身模式时不记录结果
+    let options = RequestOptions {
+        capture_enabled: settings.capture_requests && !privacy::is_incognito(),
+        replay_of: Some(capture_id),
+        endpoint_allowlist,
+        cancel: Some(&CANCEL_REQUESTS),
+    };
+    let answer = send_chat_request(&app, &profile, messages_to_send, options).await?;
+    Ok(apply_response_limit(&app, answer, settings.max_response_chars as usize))
 }
 // --- Window and App Setup ---
 
@@ -721,6 +1589,33 @@ pub fn run() {
             #[cfg(debug_assertions)]
             export_ts_bindings();
 
+            // 先把旧版本的设置升级到当前结构。迁移失败时保留原来的设置，按默认值补全缺失的字段。
+            match settings::migrate(app.handle()) {
+                Ok(0) => {}
+                Ok(applied) => SecurityLogger::log_security_event_with_file(
+                    app.handle(),
+                    &format!("Applied {} settings migrations", applied),
+                    "INFO",
+                ),
+                Err(e) => SecurityLogger::log_error(app.handle(), &format!("Settings migration failed: {}", e)),
+            }
+            app.manage(settings::SettingsService::new(app.handle())?);
+            app.manage(prompts::PromptLibrary::open(app.handle())?);
+            settings::spawn_watcher(app.handle().clone());
+
+            // 打开对话历史数据库
+            let data_dir = app.path().app_data_dir()?;
+            let history = history::HistoryStore::new(&data_dir.join(history::DATABASE_FILE));
+            // 启用加密时数据库在用户解锁后才打开
+            if !vault::is_enabled(&app.path().app_config_dir()?) {
+                history.unlock(None)?;
+            }
+            app.manage(history);
+
+            // 隐身模式需要在第一次请求之前生效
+            privacy::sync_incognito(&settings::Settings::load(app.handle())?);
+            privacy::spawn_retention_task(app.handle().clone());
+
             // 延迟安全检查，先让窗口显示
             let app_handle = app.handle().clone();
             
@@ -732,9 +1627,9 @@ pub fn run() {
             // 延迟加载设置和注册快捷键
             let app_handle_clone = app.handle().clone();
             std::thread::spawn(move || {
-                if let Ok(settings) = get_settings(app_handle_clone.clone()) {
-                    if let Some(shortcut) = settings.get("shortcut") {
-                        let _ = register_shortcut(app_handle_clone, shortcut.as_str().unwrap().to_string());
+                if let Ok(settings) = settings::Settings::load(&app_handle_clone) {
+                    if let Err(e) = register_shortcut(app_handle_clone.clone(), settings.shortcut) {
+                        SecurityLogger::log_error(&app_handle_clone, &format!("Failed to register the global shortcut: {}", e));
                     }
                 }
             });
@@ -754,11 +1649,66 @@ pub fn run() {
             extract_text,
             get_settings,
             set_settings,
+            update_settings,
+            list_prompts,
+            create_prompt,
+            update_prompt,
+            delete_prompt,
+            export_prompts,
+            import_prompts,
             register_shortcut,
-            set_decorations
+            set_decorations,
+            cancel_ask_ai,
+            preprocess_image,
+            sanitize_html,
+            create_conversation,
+            append_message,
+            list_conversations,
+            load_conversation,
+            rename_conversation,
+            delete_conversation,
+            search_history,
+            fork_message,
+            switch_branch,
+            get_conversation_path,
+            export_conversation,
+            import_conversations,
+            apply_retention,
+            create_backup,
+            restore_backup,
+            compact_conversation,
+            undo_compaction,
+            list_compactions,
+            pin_message,
+            create_folder,
+            rename_folder,
+            delete_folder,
+            list_folders,
+            list_tags,
+            move_conversations,
+            tag_conversations,
+            pin_conversations,
+            archive_conversations,
+            delete_conversations,
+            vault_status,
+            enable_encryption,
+            unlock_vault,
+            lock_vault,
+            change_passphrase,
+            list_captures,
+            clear_captures,
+            replay_capture
         ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while running tauri application")
+        .run(|app, event| {
+            // 退出前保存尚未写入磁盘的设置
+            if let tauri::RunEvent::Exit = event {
+                if let Err(e) = app.state::<settings::SettingsService>().flush() {
+                    SecurityLogger::log_error(app, &format!("Failed to save settings: {}", e));
+                }
+            }
+        });
 }
 
 #[cfg(debug_assertions)]
@@ -767,6 +1717,34 @@ fn export_ts_bindings() {
         &ApiError::export_to_string().unwrap(),
         &ConversationMessage::export_to_string().unwrap(),
         &Attachment::export_to_string().unwrap(),
+        &ApiProfile::export_to_string().unwrap(),
+        &image_processing::ProcessedImage::export_to_string().unwrap(),
+        &recorder::CaptureSummary::export_to_string().unwrap(),
+        &history::StoredMessage::export_to_string().unwrap(),
+        &history::Conversation::export_to_string().unwrap(),
+        &history::ConversationSummary::export_to_string().unwrap(),
+        &history::ConversationPage::export_to_string().unwrap(),
+        &history::SearchQuery::export_to_string().unwrap(),
+        &history::SearchHit::export_to_string().unwrap(),
+        &export::ExportFormat::export_to_string().unwrap(),
+        &history::ImportStatus::export_to_string().unwrap(),
+        &history::ImportOutcome::export_to_string().unwrap(),
+        &history::ImportReport::export_to_string().unwrap(),
+        &history::Compaction::export_to_string().unwrap(),
+        &history::Folder::export_to_string().unwrap(),
+        &history::TagCount::export_to_string().unwrap(),
+        &history::ConversationFilter::export_to_string().unwrap(),
+        &vault::VaultStatus::export_to_string().unwrap(),
+        &backup::BackupFile::export_to_string().unwrap(),
+        &backup::BackupManifest::export_to_string().unwrap(),
+        &backup::RestoreMode::export_to_string().unwrap(),
+        &backup::RestoreReport::export_to_string().unwrap(),
+        &settings::Settings::export_to_string().unwrap(),
+        &settings::SettingsError::export_to_string().unwrap(),
+        &settings::LoadedSettings::export_to_string().unwrap(),
+        &prompts::Prompt::export_to_string().unwrap(),
+        &prompts::PromptDraft::export_to_string().unwrap(),
+        &prompts::PromptImportReport::export_to_string().unwrap(),
     ].join("\n\n");
 
     if let Ok(mut file) = std::fs::File::create("../src/lib/bindings.ts") {
@@ -0,0 +1,154 @@
+// --- Mock Provider ---
+//
+// `mock` 类型的 API 不访问网络：默认回显用户输入，也可以从 fixture 文件中读取
+// 预设回答、模拟延迟、分块流式输出以及各种 ApiError，便于离线演示和前端测试。
+
+use std::path::PathBuf;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Manager};
+
+use crate::ApiError;
+
+/// 未指定 fixture 路径时，在配置目录中查找的默认文件名
+const DEFAULT_FIXTURE: &str = "mock_fixture.json";
+/// 流式输出事件名
+pub const STREAM_EVENT: &str = "ai-stream-chunk";
+/// 以此前缀开头的用户消息会直接触发对应的错误，无需 fixture 文件
+const ERROR_DIRECTIVE: &str = "/mock-error ";
+
+#[derive(Deserialize, Debug, Clone, Default)]
+struct MockFixture {
+    #[serde(default)]
+    rules: Vec<MockRule>,
+}
+
+/// 一条预设规则。`match` 为空时匹配任何输入；`reply` 和 `error` 都为空时回显输入。
+#[derive(Deserialize, Debug, Clone, Default)]
+struct MockRule {
+    #[serde(rename = "match", default)]
+    pattern: Option<String>,
+    #[serde(default)]
+    reply: Option<String>,
+    #[serde(default)]
+    error: Option<ApiError>,
+    #[serde(default)]
+    delay_ms: u64,
+    /// 大于 0 时按该字符数分块发送流式事件
+    #[serde(default)]
+    chunk_size: usize,
+    #[serde(default)]
+    chunk_delay_ms: u64,
+}
+
+#[derive(Serialize, Clone)]
+struct StreamChunk {
+    chunk: String,
+    done: bool,
+}
+
+/// 解析 fixture 路径：`api_url` 可以是绝对路径，也可以是相对配置目录的文件名
+fn fixture_path(app: &AppHandle, api_url: &str) -> Option<PathBuf> {
+    let config_dir = app.path().app_config_dir().ok()?;
+    let name = api_url.trim().trim_start_matches("mock://");
+    if name.is_empty() {
+        return Some(config_dir.join(DEFAULT_FIXTURE));
+    }
+    let path = PathBuf::from(name);
+    if path.is_absolute() {
+        Some(path)
+    } else {
+        Some(config_dir.join(path))
+    }
+}
+
+fn load_fixture(app: &AppHandle, api_url: &str) -> Result<MockFixture, ApiError> {
+    let path = match fixture_path(app, api_url) {
+        Some(path) if path.exists() => path,
+        _ => return Ok(MockFixture::default()),
+    };
+    let json = std::fs::read_to_string(&path)
+        .map_err(|e| ApiError::InternalError(format!("Failed to read mock fixture: {}", e)))?;
+    serde_json::from_str(&json)
+        .map_err(|e| ApiError::InternalError(format!("Invalid mock fixture {}: {}", path.display(), e)))
+}
+
+/// 取出最后一条用户消息的文本内容（兼容多段内容格式）
+fn last_user_text(messages: &[serde_json::Value]) -> String {
+    let Some(message) = messages.iter().rev().find(|m| m["role"] == "user") else {
+        return String::new();
+    };
+    match &message["content"] {
+        serde_json::Value::String(text) => text.clone(),
+        serde_json::Value::Array(parts) => parts
+            .iter()
+            .filter_map(|p| p["text"].as_str())
+            .collect::<Vec<_>>()
+            .join("\n"),
+        _ => String::new(),
+    }
+}
+
+/// 将 `/mock-error RateLimitExceeded` 这样的指令转换为对应的错误
+fn error_from_directive(text: &str) -> Option<ApiError> {
+    let rest = text.trim().strip_prefix(ERROR_DIRECTIVE)?.trim();
+    let (variant, message) = match rest.split_once(' ') {
+        Some((variant, message)) => (variant, message.trim()),
+        None => (rest, "Simulated error"),
+    };
+    // 依次尝试字符串消息、无内容以及结构化字段三种形式
+    let fields = serde_json::json!({ "message": message, "model": "mock", "status": 500 });
+    [
+        serde_json::json!({ "type": variant, "message": message }),
+        serde_json::json!({ "type": variant }),
+        serde_json::json!({ "type": variant, "message": fields }),
+    ]
+    .into_iter()
+    .find_map(|value| serde_json::from_value(value).ok())
+}
+
+async fn emit_chunks(app: &AppHandle, reply: &str, chunk_size: usize, chunk_delay_ms: u64) {
+    let chars: Vec<char> = reply.chars().collect();
+    for chunk in chars.chunks(chunk_size) {
+        let _ = app.emit(STREAM_EVENT, StreamChunk { chunk: chunk.iter().collect(), done: false });
+        if chunk_delay_ms > 0 {
+            tokio::time::sleep(Duration::from_millis(chunk_delay_ms)).await;
+        }
+    }
+    let _ = app.emit(STREAM_EVENT, StreamChunk { chunk: String::new(), done: true });
+}
+
+/// 根据 fixture 规则生成模拟回答
+pub async fn respond(app: &AppHandle, api_url: &str, messages: &[serde_json::Value]) -> Result<String, ApiError> {
+    let input = last_user_text(messages);
+
+    if let Some(error) = error_from_directive(&input) {
+        return Err(error);
+    }
+
+    let fixture = load_fixture(app, api_url)?;
+    let rule = fixture
+        .rules
+        .into_iter()
+        .find(|rule| match &rule.pattern {
+            Some(pattern) => input.contains(pattern.as_str()),
+            None => true,
+        })
+        .unwrap_or_default();
+
+    if rule.delay_ms > 0 {
+        tokio::time::sleep(Duration::from_millis(rule.delay_ms)).await;
+    }
+
+    if let Some(error) = rule.error {
+        return Err(error);
+    }
+
+    let reply = rule.reply.unwrap_or_else(|| input.clone());
+    if rule.chunk_size > 0 {
+        emit_chunks(app, &reply, rule.chunk_size, rule.chunk_delay_ms).await;
+    }
+
+    Ok(reply)
+}
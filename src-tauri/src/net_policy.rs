@@ -0,0 +1,288 @@
+// --- Endpoint Network Policy ---
+//
+// 防止 API 地址被用来访问本机或内网服务（SSRF）。主机名会先解析，所有解析结果都必须是
+// 公网地址；通过检查的地址会被固定给本次连接使用，避免检查之后 DNS 结果被替换。
+// 用户可以通过 `endpoint_allowlist` 设置显式放行受信任的内部网关。
+
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::str::FromStr;
+
+use ipnet::IpNet;
+use url::{Host, Url};
+
+/// 不允许访问的地址段及原因
+const BLOCKED_RANGES: &[(&str, &str)] = &[
+    ("0.0.0.0/8", "unspecified"),
+    ("10.0.0.0/8", "private"),
+    ("100.64.0.0/10", "carrier-grade NAT"),
+    ("127.0.0.0/8", "loopback"),
+    ("169.254.0.0/16", "link-local"),
+    ("172.16.0.0/12", "private"),
+    ("192.0.0.0/24", "IETF protocol assignment"),
+    ("192.0.2.0/24", "documentation"),
+    ("192.168.0.0/16", "private"),
+    ("198.18.0.0/15", "benchmarking"),
+    ("198.51.100.0/24", "documentation"),
+    ("203.0.113.0/24", "documentation"),
+    ("224.0.0.0/4", "multicast"),
+    ("240.0.0.0/4", "reserved"),
+    ("::/128", "unspecified"),
+    ("::1/128", "loopback"),
+    ("2001:db8::/32", "documentation"),
+    ("fc00::/7", "unique local"),
+    ("fe80::/10", "link-local"),
+    ("ff00::/8", "multicast"),
+];
+
+/// 已通过检查、可以固定给连接使用的地址
+#[derive(Debug, Clone)]
+pub struct VettedEndpoint {
+    pub host: String,
+    /// 主机名解析得到的地址；主机本身是 IP 字面量时为 None
+    pub pinned_addr: Option<SocketAddr>,
+}
+
+/// 提取 IPv6 中内嵌的 IPv4 地址：IPv4-mapped `::ffff:a.b.c.d`、IPv4-compatible `::a.b.c.d`、
+/// NAT64 `64:ff9b::/96`、6to4 `2002::/16` 以及 Teredo `2001::/32`（客户端地址按位取反保存）
+fn embedded_ipv4(ip: IpAddr) -> IpAddr {
+    let IpAddr::V6(v6) = ip else {
+        return ip;
+    };
+    if let Some(v4) = v6.to_ipv4_mapped() {
+        return IpAddr::V4(v4);
+    }
+    let segments = v6.segments();
+    let [.., a, b, c, d] = v6.octets();
+    let v4 = match segments {
+        // `::` 和 `::1` 本身已在禁止列表中
+        [0, 0, 0, 0, 0, 0, 0, 0 | 1] => return ip,
+        [0, 0, 0, 0, 0, 0, ..] | [0x64, 0xff9b, 0, 0, 0, 0, ..] => Ipv4Addr::new(a, b, c, d),
+        [0x2002, high, low, ..] => Ipv4Addr::from((u32::from(high) << 16) | u32::from(low)),
+        [0x2001, 0, ..] => Ipv4Addr::new(!a, !b, !c, !d),
+        _ => return ip,
+    };
+    IpAddr::V4(v4)
+}
+
+/// 判断地址是否属于禁止访问的地址段，返回原因
+pub fn blocked_reason(ip: IpAddr) -> Option<&'static str> {
+    let ip = embedded_ipv4(ip);
+    if let IpAddr::V4(v4) = ip {
+        if v4.is_broadcast() {
+            return Some("broadcast");
+        }
+    }
+    BLOCKED_RANGES.iter().find_map(|(cidr, reason)| {
+        let net = IpNet::from_str(cidr).expect("valid built-in CIDR");
+        net.contains(&ip).then_some(*reason)
+    })
+}
+
+/// 检查允许列表项的格式：主机名、`*.` 通配符、IP 地址或 CIDR 地址段（不含协议和路径）
+pub fn check_allowlist_entry(entry: &str) -> Result<(), String> {
+    let entry = entry.trim();
+    if IpNet::from_str(entry).is_ok() || IpAddr::from_str(entry).is_ok() {
+        return Ok(());
+    }
+    let name = entry.strip_prefix("*.").unwrap_or(entry);
+    let valid = !name.is_empty()
+        && name.split('.').all(|label| {
+            !label.is_empty() && label.len() <= 63 && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        });
+    if !valid {
+        return Err(format!(
+            "'{}' is not a host name, '*.' wildcard, IP address or CIDR range",
+            entry
+        ));
+    }
+    Ok(())
+}
+
+/// 检查主机名或地址是否在用户允许列表中。
+/// 列表项可以是完整主机名、`*.example.com` 通配符或 CIDR 地址段。
+fn is_allowlisted(host: &str, ip: Option<IpAddr>, allowlist: &[String]) -> bool {
+    let host = host.trim_end_matches('.').to_ascii_lowercase();
+    allowlist.iter().any(|entry| {
+        let entry = entry.trim().to_ascii_lowercase();
+        if entry.is_empty() {
+            return false;
+        }
+        if let Ok(net) = IpNet::from_str(&entry) {
+            return ip.map(|ip| net.contains(&embedded_ipv4(ip)) || net.contains(&ip)).unwrap_or(false);
+        }
+        if let Ok(entry_ip) = IpAddr::from_str(&entry) {
+            return ip == Some(entry_ip);
+        }
+        match entry.strip_prefix("*.") {
+            Some(suffix) => host.ends_with(&format!(".{}", suffix)),
+            None => host == entry,
+        }
+    })
+}
+
+/// 不进行 DNS 解析的静态检查：协议、IP 字面量以及明显的本地主机名
+pub fn check_url(url: &Url, allowlist: &[String]) -> Result<(), String> {
+    if url.scheme() != "https" && url.scheme() != "http" {
+        return Err("URL must use http or https protocol".to_string());
+    }
+
+    match url.host() {
+        None => Err("URL must include a host".to_string()),
+        Some(Host::Ipv4(v4)) => check_ip(&v4.to_string(), IpAddr::V4(v4), allowlist),
+        Some(Host::Ipv6(v6)) => check_ip(&v6.to_string(), IpAddr::V6(v6), allowlist),
+        Some(Host::Domain(domain)) => {
+            let name = domain.trim_end_matches('.').to_ascii_lowercase();
+            let is_local_name = name == "localhost"
+                || name.ends_with(".localhost")
+                || name.ends_with(".local")
+                || name.ends_with(".internal");
+            if is_local_name && !is_allowlisted(&name, None, allowlist) {
+                return Err("Localhost and local network host names are not allowed. Add the host to the endpoint allowlist to use it.".to_string());
+            }
+            Ok(())
+        }
+    }
+}
+
+fn check_ip(host: &str, ip: IpAddr, allowlist: &[String]) -> Result<(), String> {
+    match blocked_reason(ip) {
+        Some(reason) if !is_allowlisted(host, Some(ip), allowlist) => Err(format!(
+            "Address {} is a {} address and is not allowed. Add it to the endpoint allowlist to use it.",
+            ip, reason
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// 解析主机名并检查所有解析结果，返回用于固定连接的地址
+pub async fn vet_endpoint(url: &str, allowlist: &[String]) -> Result<VettedEndpoint, String> {
+    let url = Url::parse(url).map_err(|e| format!("Invalid URL: {}", e))?;
+    check_url(&url, allowlist)?;
+
+    let host = url.host_str().unwrap_or_default().to_string();
+    let domain = match url.host() {
+        Some(Host::Domain(domain)) => domain.to_string(),
+        // IP 字面量已在 check_url 中检查过，无需解析
+        _ => return Ok(VettedEndpoint { host, pinned_addr: None }),
+    };
+
+    let port = url.port_or_known_default().unwrap_or(443);
+    let addrs: Vec<SocketAddr> = tokio::net::lookup_host((domain.as_str(), port))
+        .await
+        .map_err(|e| format!("Failed to resolve {}: {}", domain, e))?
+        .collect();
+    if addrs.is_empty() {
+        return Err(format!("{} did not resolve to any address", domain));
+    }
+
+    // 任何一个解析结果指向内网都拒绝，防止 DNS 轮询绕过检查
+    for addr in &addrs {
+        check_ip(&domain, addr.ip(), allowlist)
+            .map_err(|e| format!("{} resolves to a blocked address. {}", domain, e))?;
+    }
+
+    Ok(VettedEndpoint { host: domain, pinned_addr: Some(addrs[0]) })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(url: &str, allowlist: &[&str]) -> Result<(), String> {
+        let allowlist: Vec<String> = allowlist.iter().map(|entry| entry.to_string()).collect();
+        check_url(&Url::parse(url).unwrap(), &allowlist)
+    }
+
+    fn reason(ip: &str) -> Option<&'static str> {
+        blocked_reason(IpAddr::from_str(ip).unwrap())
+    }
+
+    #[test]
+    fn public_endpoints_are_allowed() {
+        assert!(check("https://api.openai.com/v1/chat/completions", &[]).is_ok());
+        assert!(check("https://8.8.8.8/v1", &[]).is_ok());
+        assert!(check("https://[2606:4700::1111]/v1", &[]).is_ok());
+    }
+
+    #[test]
+    fn non_http_schemes_are_rejected() {
+        assert!(check("file:///etc/passwd", &[]).is_err());
+        assert!(check("ftp://example.com/", &[]).is_err());
+    }
+
+    #[test]
+    fn local_and_private_endpoints_are_rejected() {
+        for url in [
+            "http://localhost:11434/api/chat",
+            "http://ollama.localhost/",
+            "http://printer.local/",
+            "http://metadata.google.internal/",
+            "http://127.0.0.1/",
+            "http://10.1.2.3/",
+            "http://169.254.169.254/latest/meta-data",
+            "http://192.168.1.1/",
+            "http://[::1]/",
+            "http://[fd00::1]/",
+            "http://[::ffff:127.0.0.1]/",
+        ] {
+            assert!(check(url, &[]).is_err(), "{} should be blocked", url);
+        }
+    }
+
+    #[test]
+    fn allowlist_overrides_blocking() {
+        assert!(check("http://localhost:11434/", &["localhost"]).is_ok());
+        assert!(check("http://gateway.corp.internal/", &["*.corp.internal"]).is_ok());
+        assert!(check("http://corp.internal/", &["*.corp.internal"]).is_err());
+        assert!(check("http://10.1.2.3/", &["10.0.0.0/8"]).is_ok());
+        assert!(check("http://10.1.2.3/", &["10.1.2.3"]).is_ok());
+        assert!(check("http://10.1.2.4/", &["10.1.2.3"]).is_err());
+        assert!(check("http://[::ffff:10.1.2.3]/", &["10.0.0.0/8"]).is_ok());
+    }
+
+    #[test]
+    fn embedded_ipv4_addresses_are_unwrapped() {
+        assert_eq!(reason("::ffff:192.168.0.1"), Some("private"));
+        assert_eq!(reason("::127.0.0.1"), Some("loopback"));
+        assert_eq!(reason("64:ff9b::a00:1"), Some("private"));
+        assert_eq!(reason("2002:c0a8:101::1"), Some("private"));
+        assert_eq!(reason("2002:7f00:1::"), Some("loopback"));
+        // RFC 4380 中的示例：客户端地址 192.0.2.45
+        assert_eq!(reason("2001:0:4136:e378:8000:63bf:3fff:fdd2"), Some("documentation"));
+        assert_eq!(reason("2001:0:4136:e378:8000:63bf:80ff:fffe"), Some("loopback"));
+        assert_eq!(reason("2002:808:808::1"), None);
+        assert_eq!(reason("::"), Some("unspecified"));
+        assert_eq!(reason("::1"), Some("loopback"));
+    }
+
+    #[test]
+    fn special_purpose_ranges_are_blocked() {
+        assert_eq!(reason("192.0.2.1"), Some("documentation"));
+        assert_eq!(reason("198.51.100.7"), Some("documentation"));
+        assert_eq!(reason("203.0.113.200"), Some("documentation"));
+        assert_eq!(reason("100.64.0.1"), Some("carrier-grade NAT"));
+        assert_eq!(reason("255.255.255.255"), Some("broadcast"));
+        assert_eq!(reason("1.1.1.1"), None);
+    }
+
+    #[test]
+    fn allowlist_entries_are_validated() {
+        for entry in ["api.example.com", "*.example.com", "10.0.0.1", "10.0.0.0/8", "fd00::/8"] {
+            assert!(check_allowlist_entry(entry).is_ok(), "{} should be accepted", entry);
+        }
+        for entry in ["https://example.com", "example.com/path", "*", "bad host", ""] {
+            assert!(check_allowlist_entry(entry).is_err(), "{} should be rejected", entry);
+        }
+    }
+
+    #[test]
+    fn allowlist_matching() {
+        let allowlist = vec!["*.example.com".to_string(), "172.16.0.0/12".to_string()];
+        assert!(is_allowlisted("api.example.com", None, &allowlist));
+        assert!(is_allowlisted("API.Example.com.", None, &allowlist));
+        assert!(!is_allowlisted("example.com", None, &allowlist));
+        assert!(!is_allowlisted("evilexample.com", None, &allowlist));
+        assert!(is_allowlisted("gateway", IpAddr::from_str("172.20.1.1").ok(), &allowlist));
+        assert!(!is_allowlisted("gateway", IpAddr::from_str("172.32.0.1").ok(), &allowlist));
+    }
+}
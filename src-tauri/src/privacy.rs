@@ -0,0 +1,84 @@
+// --- Retention & Incognito Mode ---
+//
+// 保留策略由后台任务定期执行：删除超过保留天数的对话、超出最大数量的较旧对话，置顶的对话默认不删除。
+// 隐身模式下对话不写入历史，请求不被捕获记录，也不写入 security.log；
+// 开关保存在设置中，同时缓存在内存里供日志等无法读取设置的地方判断。
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use serde::Serialize;
+use tauri::{AppHandle, Emitter, Manager};
+
+use crate::settings::Settings;
+use crate::{history, SecurityLogger};
+
+/// 保留策略删除了对话后通知前端刷新历史列表
+pub const HISTORY_PRUNED_EVENT: &str = "history-pruned";
+/// 后台执行保留策略的间隔
+const RETENTION_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+static INCOGNITO: AtomicBool = AtomicBool::new(false);
+
+#[derive(Serialize, Clone)]
+struct HistoryPruned {
+    deleted: u32,
+}
+
+pub fn is_incognito() -> bool {
+    INCOGNITO.load(Ordering::Relaxed)
+}
+
+/// 用设置中的隐身模式开关更新缓存，设置保存后调用
+pub fn sync_incognito(settings: &Settings) {
+    INCOGNITO.store(settings.incognito, Ordering::Relaxed);
+}
+
+/// 隐身模式下拒绝写入历史
+pub fn ensure_history_allowed() -> Result<(), String> {
+    if is_incognito() {
+        return Err("History is disabled in incognito mode".to_string());
+    }
+    Ok(())
+}
+
+pub fn retention_policy(settings: &Settings) -> history::RetentionPolicy {
+    history::RetentionPolicy {
+        max_age_days: Some(settings.retention_days).filter(|&days| days > 0),
+        max_conversations: Some(settings.retention_max_conversations).filter(|&count| count > 0),
+        keep_pinned: settings.retention_keep_pinned,
+    }
+}
+
+/// 立即执行保留策略，返回删除的对话数。历史未解锁时跳过。
+pub fn enforce_retention(app: &AppHandle) -> Result<u32, String> {
+    let policy = retention_policy(&Settings::load(app)?);
+    let history = app.state::<history::HistoryStore>();
+    if policy.is_unlimited() || !history.is_open() {
+        return Ok(0);
+    }
+
+    let deleted = history.enforce_retention(&policy)?;
+    if deleted > 0 {
+        SecurityLogger::log_security_event_with_file(
+            app,
+            &format!("Retention policy deleted {} conversations", deleted),
+            "INFO",
+        );
+        let _ = app.emit(HISTORY_PRUNED_EVENT, HistoryPruned { deleted });
+    }
+    Ok(deleted)
+}
+
+/// 启动时执行一次保留策略，之后定期执行
+pub fn spawn_retention_task(app: AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        let mut interval = tokio::time::interval(RETENTION_INTERVAL);
+        loop {
+            interval.tick().await;
+            if let Err(e) = enforce_retention(&app) {
+                SecurityLogger::log_error(&app, &format!("Failed to apply the retention policy: {}", e));
+            }
+        }
+    });
+}
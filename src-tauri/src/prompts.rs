@@ -0,0 +1,375 @@
+// --- Prompt Library ---
+//
+// 命名的系统提示，带说明和标签，保存在配置目录的 prompts.json 中。设置中的 `system_prompt_preset`
+// 是所选提示的 id，值为 `custom` 时使用设置中的 `system_prompt`。
+// 提示内容是 minijinja 模板，可以引用 `{{date}}`、`{{clipboard}}`、`{{locale}}` 和 `{{input}}`，
+// 每次请求前渲染。提示库可以导出为 JSON 文件分享给其他人，导入时按 id 合并，较新的版本优先。
+
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use chrono::{DateTime, Local, Utc};
+use minijinja::{context, Environment, UndefinedBehavior};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager};
+use tauri_plugin_clipboard_manager::ClipboardExt;
+use ts_rs::TS;
+
+use crate::settings::Settings;
+use crate::{history, SecurityLogger};
+
+/// 提示库文件名（位于应用配置目录）
+pub const PROMPTS_FILE: &str = "prompts.json";
+/// `system_prompt_preset` 为该值时使用设置中的 `system_prompt`
+pub const CUSTOM_PROMPT: &str = "custom";
+/// 导出文件的格式标识和版本
+const EXPORT_FORMAT: &str = "ai-window-prompts";
+const EXPORT_VERSION: u32 = 1;
+/// 模板中可以使用的变量
+const VARIABLES: &[&str] = &["date", "clipboard", "locale", "input"];
+
+#[derive(Serialize, Deserialize, Debug, Clone, TS)]
+#[ts(export)]
+pub struct Prompt {
+    pub id: String,
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// 提示模板
+    pub content: String,
+    #[serde(default = "Utc::now")]
+    pub updated_at: DateTime<Utc>,
+}
+
+/// 新建或修改提示时由前端提交的字段
+#[derive(Deserialize, Debug, Clone, TS)]
+#[ts(export)]
+pub struct PromptDraft {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    pub content: String,
+}
+
+#[derive(Serialize, Debug, Clone, TS)]
+#[ts(export)]
+pub struct PromptImportReport {
+    pub added: u32,
+    pub updated: u32,
+    /// 本地已有相同或更新版本而未导入的提示
+    pub skipped: u32,
+}
+
+/// prompts.json 和导出文件的结构
+#[derive(Serialize, Deserialize)]
+struct PromptFile {
+    format: String,
+    version: u32,
+    prompts: Vec<Prompt>,
+}
+
+impl PromptFile {
+    fn new(prompts: Vec<Prompt>) -> Self {
+        PromptFile { format: EXPORT_FORMAT.to_string(), version: EXPORT_VERSION, prompts }
+    }
+
+    fn parse(data: &str) -> Result<Vec<Prompt>, String> {
+        let file: PromptFile = serde_json::from_str(data).map_err(|e| format!("Invalid prompt library: {}", e))?;
+        if file.format != EXPORT_FORMAT {
+            return Err(format!("'{}' is not an AI Window prompt library", file.format));
+        }
+        if file.version > EXPORT_VERSION {
+            return Err(format!(
+                "The prompt library was exported by a newer version of AI Window (version {})",
+                file.version
+            ));
+        }
+        Ok(file.prompts)
+    }
+}
+
+/// 首次启动时提供的提示，id 与旧版本设置中的预设名相同
+fn builtin_prompts() -> Vec<Prompt> {
+    let prompt = |id: &str, name: &str, description: &str, content: &str| Prompt {
+        id: id.to_string(),
+        name: name.to_string(),
+        description: description.to_string(),
+        tags: Vec::new(),
+        content: content.to_string(),
+        updated_at: Utc::now(),
+    };
+    vec![
+        prompt(
+            "default",
+            "Default",
+            "A helpful assistant that answers questions concisely.",
+            "You are a helpful assistant.",
+        ),
+        prompt(
+            "minimal",
+            "Minimal",
+            "Concise, signal-focused responses",
+            "Your function is to distill every query to its absolute essence. Provide the single most critical piece of information as a declarative statement. Maximum signal, zero noise. Your response should rarely exceed one sentence.",
+        ),
+    ]
+}
+
+fn environment() -> Environment<'static> {
+    let mut env = Environment::new();
+    env.set_undefined_behavior(UndefinedBehavior::Strict);
+    env
+}
+
+/// 模板引用的变量
+fn template_variables(content: &str) -> Result<HashSet<String>, String> {
+    let env = environment();
+    let template = env.template_from_str(content).map_err(|e| format!("Invalid template: {}", e))?;
+    Ok(template.undeclared_variables(false))
+}
+
+/// 检查并规范化提交的字段：名称不能为空，模板必须能解析且只使用支持的变量
+fn clean_draft(draft: PromptDraft) -> Result<PromptDraft, String> {
+    let name = draft.name.trim().to_string();
+    if name.is_empty() {
+        return Err("Prompt name cannot be empty".to_string());
+    }
+    let mut unknown: Vec<String> = template_variables(&draft.content)?
+        .into_iter()
+        .filter(|variable| !VARIABLES.contains(&variable.as_str()))
+        .collect();
+    if !unknown.is_empty() {
+        unknown.sort();
+        return Err(format!(
+            "Unknown template variables: {}. Available variables: {}",
+            unknown.join(", "),
+            VARIABLES.join(", ")
+        ));
+    }
+
+    let mut tags: Vec<String> = draft.tags.iter().filter_map(|tag| history::normalize_tag(tag)).collect();
+    tags.sort();
+    tags.dedup();
+    Ok(PromptDraft { name, description: draft.description.trim().to_string(), tags, content: draft.content })
+}
+
+pub struct PromptLibrary {
+    path: PathBuf,
+    prompts: Mutex<Vec<Prompt>>,
+}
+
+impl PromptLibrary {
+    /// 读取提示库。文件不存在时使用内置提示；文件损坏时另存为 `prompts.json.bak` 后使用内置提示。
+    pub fn open(app: &AppHandle) -> Result<Self, String> {
+        let path = app.path().app_config_dir().map_err(|e| e.to_string())?.join(PROMPTS_FILE);
+        let prompts = match std::fs::read_to_string(&path) {
+            Ok(data) => match PromptFile::parse(&data) {
+                Ok(prompts) => prompts,
+                Err(e) => {
+                    let _ = std::fs::rename(&path, path.with_extension("json.bak"));
+                    SecurityLogger::log_error(app, &format!("Failed to load the prompt library: {}", e));
+                    builtin_prompts()
+                }
+            },
+            Err(_) => builtin_prompts(),
+        };
+        Ok(PromptLibrary { path, prompts: Mutex::new(prompts) })
+    }
+
+    pub fn list(&self) -> Vec<Prompt> {
+        self.prompts.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    pub fn get(&self, id: &str) -> Option<Prompt> {
+        self.list().into_iter().find(|prompt| prompt.id == id)
+    }
+
+    pub fn create(&self, draft: PromptDraft) -> Result<Prompt, String> {
+        let draft = clean_draft(draft)?;
+        let prompt = Prompt {
+            id: uuid::Uuid::new_v4().to_string(),
+            name: draft.name,
+            description: draft.description,
+            tags: draft.tags,
+            content: draft.content,
+            updated_at: Utc::now(),
+        };
+        self.modify(|prompts| {
+            prompts.push(prompt.clone());
+            Ok(())
+        })?;
+        Ok(prompt)
+    }
+
+    pub fn update(&self, id: &str, draft: PromptDraft) -> Result<Prompt, String> {
+        let draft = clean_draft(draft)?;
+        self.modify(|prompts| {
+            let prompt = prompts
+                .iter_mut()
+                .find(|prompt| prompt.id == id)
+                .ok_or_else(|| format!("Prompt '{}' not found", id))?;
+            prompt.name = draft.name;
+            prompt.description = draft.description;
+            prompt.tags = draft.tags;
+            prompt.content = draft.content;
+            prompt.updated_at = Utc::now();
+            Ok(prompt.clone())
+        })
+    }
+
+    pub fn delete(&self, id: &str) -> Result<(), String> {
+        self.modify(|prompts| {
+            let before = prompts.len();
+            prompts.retain(|prompt| prompt.id != id);
+            if prompts.len() == before {
+                return Err(format!("Prompt '{}' not found", id));
+            }
+            Ok(())
+        })
+    }
+
+    /// 导出提示库，`ids` 为空时导出全部提示。返回导出的提示数。
+    pub fn export(&self, ids: &[String], path: &Path) -> Result<u32, String> {
+        let prompts: Vec<Prompt> =
+            self.list().into_iter().filter(|prompt| ids.is_empty() || ids.contains(&prompt.id)).collect();
+        let count = prompts.len() as u32;
+        let data = serde_json::to_vec_pretty(&PromptFile::new(prompts)).map_err(|e| e.to_string())?;
+        write_atomically(path, &data)?;
+        Ok(count)
+    }
+
+    /// 导入导出文件。id 相同的提示只在导入的版本更新时替换本地版本。
+    pub fn import(&self, data: &str) -> Result<PromptImportReport, String> {
+        self.merge(read_prompts(data)?)
+    }
+
+    /// 按 id 合并提示，id 相同时保留较新的版本
+    pub fn merge(&self, imported: Vec<Prompt>) -> Result<PromptImportReport, String> {
+        self.modify(|prompts| {
+            let mut report = PromptImportReport { added: 0, updated: 0, skipped: 0 };
+            for prompt in imported {
+                match prompts.iter_mut().find(|existing| existing.id == prompt.id) {
+                    Some(existing) if existing.updated_at < prompt.updated_at => {
+                        *existing = prompt;
+                        report.updated += 1;
+                    }
+                    Some(_) => report.skipped += 1,
+                    None => {
+                        prompts.push(prompt);
+                        report.added += 1;
+                    }
+                }
+            }
+            Ok(report)
+        })
+    }
+
+    /// 用 `prompts` 替换整个提示库（恢复备份）
+    pub fn replace_all(&self, prompts: Vec<Prompt>) -> Result<(), String> {
+        self.modify(|current| {
+            *current = prompts;
+            Ok(())
+        })
+    }
+
+    /// 整个提示库的 JSON，格式与导出文件相同
+    pub fn to_json(&self) -> Result<Vec<u8>, String> {
+        serde_json::to_vec_pretty(&PromptFile::new(self.list())).map_err(|e| e.to_string())
+    }
+
+    /// 修改提示库并保存。保存失败时内存中的提示库保持不变。
+    fn modify<T>(&self, f: impl FnOnce(&mut Vec<Prompt>) -> Result<T, String>) -> Result<T, String> {
+        let mut prompts = self.prompts.lock().unwrap_or_else(|e| e.into_inner());
+        let mut updated = prompts.clone();
+        let result = f(&mut updated)?;
+        let data = serde_json::to_vec_pretty(&PromptFile::new(updated.clone())).map_err(|e| e.to_string())?;
+        write_atomically(&self.path, &data)?;
+        *prompts = updated;
+        Ok(result)
+    }
+}
+
+/// 读取导出文件中的提示并逐个检查，任何一个不合法时整个文件都不导入
+pub fn read_prompts(data: &str) -> Result<Vec<Prompt>, String> {
+    let mut imported = Vec::new();
+    for prompt in PromptFile::parse(data)? {
+        let draft = PromptDraft {
+            name: prompt.name,
+            description: prompt.description,
+            tags: prompt.tags,
+            content: prompt.content,
+        };
+        let draft = clean_draft(draft).map_err(|e| format!("Prompt '{}' cannot be imported: {}", prompt.id, e))?;
+        imported.push(Prompt {
+            id: if prompt.id.trim().is_empty() { uuid::Uuid::new_v4().to_string() } else { prompt.id },
+            name: draft.name,
+            description: draft.description,
+            tags: draft.tags,
+            content: draft.content,
+            updated_at: prompt.updated_at,
+        });
+    }
+    Ok(imported)
+}
+
+fn write_atomically(path: &Path, bytes: &[u8]) -> Result<(), String> {
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| "Prompt library path must include a file name".to_string())?
+        .to_string_lossy();
+    let temp_path: PathBuf = path.with_file_name(format!(".{}.tmp", file_name));
+
+    let result = (|| {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut file = File::create(&temp_path)?;
+        file.write_all(bytes)?;
+        file.sync_all()?;
+        std::fs::rename(&temp_path, path)
+    })();
+    if let Err(e) = result {
+        let _ = std::fs::remove_file(&temp_path);
+        return Err(format!("Failed to write {}: {}", path.display(), e));
+    }
+    Ok(())
+}
+
+/// 本次请求使用的系统提示：所选提示或自定义提示渲染后的文本。
+/// 渲染失败时记录错误并使用未渲染的模板，不影响请求本身。
+pub fn render_system_prompt(app: &AppHandle, settings: &Settings, input: &str, locale: &str) -> String {
+    let library = app.state::<PromptLibrary>();
+    let template = match settings.system_prompt_preset.as_str() {
+        CUSTOM_PROMPT => settings.system_prompt.clone(),
+        id => library.get(id).map(|prompt| prompt.content).unwrap_or_else(|| settings.system_prompt.clone()),
+    };
+
+    let rendered = template_variables(&template).and_then(|variables| {
+        // 只有模板引用剪贴板时才读取
+        let clipboard = if variables.contains("clipboard") {
+            app.clipboard().read_text().unwrap_or_default()
+        } else {
+            String::new()
+        };
+        let ctx = context! {
+            date => Local::now().format("%Y-%m-%d").to_string(),
+            clipboard => clipboard,
+            locale => locale,
+            input => input,
+        };
+        environment().render_str(&template, ctx).map_err(|e| format!("Failed to render the system prompt: {}", e))
+    });
+    match rendered {
+        Ok(rendered) => rendered.trim().to_string(),
+        Err(e) => {
+            SecurityLogger::log_error(app, &e);
+            template.trim().to_string()
+        }
+    }
+}
@@ -0,0 +1,215 @@
+// --- Providers ---
+//
+// 不同服务商的模型 id 格式差异很大（OpenRouter 的 `org/model`、Ollama 的 `name:tag`、
+// Gemini 的 `models/...`、Cloudflare 的 `@cf/...`），因此模型名称按服务商各自的语法校验。
+// 所有服务商都共用的底线检查：不允许控制字符、空白和路径穿越。
+
+use regex::Regex;
+use url::Url;
+
+/// 模型名称的最大长度
+const MAX_MODEL_NAME_LEN: usize = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProviderKind {
+    OpenAI,
+    OpenRouter,
+    Ollama,
+    Gemini,
+    Cloudflare,
+    /// 其它 OpenAI 兼容网关
+    Compatible,
+    Mock,
+}
+
+impl ProviderKind {
+    /// 根据 API 类型和地址判断服务商。OpenAI 兼容类型会按地址识别常见的服务商。
+    pub fn detect(api_type: &str, api_url: &str) -> Self {
+        match api_type {
+            "openai" => return ProviderKind::OpenAI,
+            "mock" => return ProviderKind::Mock,
+            "openrouter" => return ProviderKind::OpenRouter,
+            "ollama" => return ProviderKind::Ollama,
+            "gemini" => return ProviderKind::Gemini,
+            "cloudflare" => return ProviderKind::Cloudflare,
+            _ => {}
+        }
+
+        let Ok(url) = Url::parse(api_url) else {
+            return ProviderKind::Compatible;
+        };
+        let host = url.host_str().unwrap_or_default().to_ascii_lowercase();
+        if host == "openrouter.ai" || host.ends_with(".openrouter.ai") {
+            ProviderKind::OpenRouter
+        } else if host == "generativelanguage.googleapis.com" {
+            ProviderKind::Gemini
+        } else if host == "api.cloudflare.com" || host == "gateway.ai.cloudflare.com" {
+            ProviderKind::Cloudflare
+        } else if url.port() == Some(11434) {
+            // Ollama 的默认端口
+            ProviderKind::Ollama
+        } else {
+            ProviderKind::Compatible
+        }
+    }
+
+    /// 该服务商的模型 id 语法及示例（用于错误提示）
+    fn grammar(self) -> (&'static str, &'static str) {
+        match self {
+            // 包含微调模型，例如 ft:gpt-4o-mini:org:name:id
+            ProviderKind::OpenAI => (r"^[A-Za-z0-9][A-Za-z0-9._:-]*$", "gpt-4o-mini"),
+            ProviderKind::OpenRouter => (
+                r"^[A-Za-z0-9][A-Za-z0-9._-]*/[A-Za-z0-9][A-Za-z0-9._-]*(:[A-Za-z0-9._-]+)?$",
+                "meta-llama/Llama-3-70b",
+            ),
+            ProviderKind::Ollama => (
+                r"^[A-Za-z0-9][A-Za-z0-9._-]*(/[A-Za-z0-9][A-Za-z0-9._-]*)*(:[A-Za-z0-9._-]+)?$",
+                "llama3:8b",
+            ),
+            ProviderKind::Gemini => (r"^((models|tunedModels)/)?[a-z0-9][a-z0-9.-]*$", "models/gemini-pro"),
+            ProviderKind::Cloudflare => (
+                r"^@(cf|hf)/[A-Za-z0-9._-]+(/[A-Za-z0-9._-]+)*$",
+                "@cf/meta/llama-3-8b-instruct",
+            ),
+            ProviderKind::Compatible => (
+                r"^@?[A-Za-z0-9][A-Za-z0-9._:@+-]*(/[A-Za-z0-9][A-Za-z0-9._:@+-]*)*$",
+                "vendor/model-name",
+            ),
+            ProviderKind::Mock => (r"^.+$", "mock"),
+        }
+    }
+}
+
+/// 所有服务商共用的检查：长度、控制字符、空白和路径穿越
+fn check_common(model: &str) -> Result<(), String> {
+    if model.is_empty() {
+        return Err("Model name cannot be empty".to_string());
+    }
+    if model.len() > MAX_MODEL_NAME_LEN {
+        return Err("Model name is too long".to_string());
+    }
+    if model.chars().any(|c| c.is_control() || c.is_whitespace()) {
+        return Err("Model name must not contain control characters or whitespace".to_string());
+    }
+    // 模型名称可能被拼接进请求路径（例如 Gemini、Cloudflare），禁止任何形式的路径穿越
+    if model.contains('\\')
+        || model.contains('%')
+        || model.contains('?')
+        || model.contains('#')
+        || model.starts_with('/')
+        || model.split('/').any(|segment| segment == ".." || segment == ".")
+    {
+        return Err("Model name contains invalid path characters".to_string());
+    }
+    Ok(())
+}
+
+/// 按服务商语法验证模型名称
+pub fn validate_model_name(api_type: &str, api_url: &str, model: &str) -> Result<(), String> {
+    check_common(model)?;
+
+    let provider = ProviderKind::detect(api_type, api_url);
+    let (pattern, example) = provider.grammar();
+    let grammar = Regex::new(pattern).expect("valid model name grammar");
+    if !grammar.is_match(model) {
+        return Err(format!(
+            "Model name '{}' is not valid for {:?} (expected something like '{}')",
+            model, provider, example
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALL: &[ProviderKind] = &[
+        ProviderKind::OpenAI,
+        ProviderKind::OpenRouter,
+        ProviderKind::Ollama,
+        ProviderKind::Gemini,
+        ProviderKind::Cloudflare,
+        ProviderKind::Compatible,
+        ProviderKind::Mock,
+    ];
+
+    fn accepts(provider: ProviderKind, model: &str) -> bool {
+        let (pattern, _) = provider.grammar();
+        check_common(model).is_ok() && Regex::new(pattern).unwrap().is_match(model)
+    }
+
+    #[test]
+    fn detects_provider_from_api_type() {
+        assert_eq!(ProviderKind::detect("openai", "https://openrouter.ai/api/v1"), ProviderKind::OpenAI);
+        assert_eq!(ProviderKind::detect("mock", ""), ProviderKind::Mock);
+        assert_eq!(ProviderKind::detect("openrouter", ""), ProviderKind::OpenRouter);
+        assert_eq!(ProviderKind::detect("ollama", ""), ProviderKind::Ollama);
+        assert_eq!(ProviderKind::detect("gemini", ""), ProviderKind::Gemini);
+        assert_eq!(ProviderKind::detect("cloudflare", ""), ProviderKind::Cloudflare);
+    }
+
+    #[test]
+    fn detects_compatible_providers_from_url() {
+        let detect = |url| ProviderKind::detect("openai-compatible", url);
+        assert_eq!(detect("https://openrouter.ai/api/v1/chat/completions"), ProviderKind::OpenRouter);
+        assert_eq!(detect("https://eu.openrouter.ai/api/v1"), ProviderKind::OpenRouter);
+        assert_eq!(detect("https://generativelanguage.googleapis.com/v1beta/openai"), ProviderKind::Gemini);
+        assert_eq!(detect("https://api.cloudflare.com/client/v4/accounts/x/ai/v1"), ProviderKind::Cloudflare);
+        assert_eq!(detect("https://gateway.ai.cloudflare.com/v1/x/y/openai"), ProviderKind::Cloudflare);
+        assert_eq!(detect("http://localhost:11434/v1/chat/completions"), ProviderKind::Ollama);
+        assert_eq!(detect("https://notopenrouter.ai/v1"), ProviderKind::Compatible);
+        assert_eq!(detect("https://gateway.example.com/v1"), ProviderKind::Compatible);
+        assert_eq!(detect("not a url"), ProviderKind::Compatible);
+    }
+
+    #[test]
+    fn grammar_examples_are_valid() {
+        for &provider in ALL {
+            let (pattern, example) = provider.grammar();
+            assert!(Regex::new(pattern).is_ok(), "{:?}", provider);
+            assert!(accepts(provider, example), "{:?} rejects its example {}", provider, example);
+        }
+    }
+
+    #[test]
+    fn grammars_accept_provider_model_ids() {
+        assert!(accepts(ProviderKind::OpenAI, "gpt-4o-mini"));
+        assert!(accepts(ProviderKind::OpenAI, "ft:gpt-4o-mini:org:name:abc123"));
+        assert!(accepts(ProviderKind::OpenRouter, "meta-llama/Llama-3-70b"));
+        assert!(accepts(ProviderKind::OpenRouter, "mistralai/mistral-7b-instruct:free"));
+        assert!(accepts(ProviderKind::Ollama, "llama3:8b"));
+        assert!(accepts(ProviderKind::Ollama, "library/qwen2.5"));
+        assert!(accepts(ProviderKind::Gemini, "models/gemini-pro"));
+        assert!(accepts(ProviderKind::Gemini, "gemini-1.5-flash"));
+        assert!(accepts(ProviderKind::Cloudflare, "@cf/meta/llama-3-8b-instruct"));
+        assert!(accepts(ProviderKind::Compatible, "@cf/meta/llama"));
+        assert!(accepts(ProviderKind::Compatible, "vendor/model:tag"));
+    }
+
+    #[test]
+    fn grammars_reject_other_provider_formats() {
+        assert!(!accepts(ProviderKind::OpenAI, "meta-llama/Llama-3-70b"));
+        assert!(!accepts(ProviderKind::OpenRouter, "gpt-4o-mini"));
+        assert!(!accepts(ProviderKind::Gemini, "models/Gemini-Pro"));
+        assert!(!accepts(ProviderKind::Cloudflare, "meta/llama"));
+    }
+
+    #[test]
+    fn common_checks_apply_to_every_provider() {
+        for &provider in ALL {
+            for model in ["", "a b", "model\u{0}", "../etc/passwd", "org/../model", "/model", "a\\b", "a%2e", "a?b"] {
+                assert!(!accepts(provider, model), "{:?} accepts {:?}", provider, model);
+            }
+            assert!(!accepts(provider, &"a".repeat(MAX_MODEL_NAME_LEN + 1)));
+        }
+    }
+
+    #[test]
+    fn validate_model_name_uses_the_detected_grammar() {
+        let openrouter = "https://openrouter.ai/api/v1";
+        assert!(validate_model_name("openai-compatible", openrouter, "meta-llama/Llama-3-70b").is_ok());
+        let error = validate_model_name("openai-compatible", openrouter, "gpt-4o").unwrap_err();
+        assert!(error.contains("OpenRouter") && error.contains("meta-llama/Llama-3-70b"));
+    }
+}
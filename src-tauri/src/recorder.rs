@@ -0,0 +1,208 @@
+// --- Request Recorder ---
+//
+// 开发者模式下记录 ask_ai 的每一次请求与响应，便于排查兼容网关返回的异常数据。
+// 每次交互保存为 captures 目录下的一个 JSON 文件，API 密钥在写入前会被脱敏。
+
+use std::fs;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager};
+use ts_rs::TS;
+
+use crate::SecurityLogger;
+
+/// 捕获文件所在的子目录（位于应用配置目录下）
+const CAPTURE_DIR: &str = "captures";
+/// 最多保留的捕获文件数量，超出后删除最旧的文件
+const MAX_CAPTURES: usize = 200;
+const REDACTED: &str = "[REDACTED]";
+
+static CAPTURE_COUNTER: AtomicU32 = AtomicU32::new(0);
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CapturedRequest {
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+    pub body: serde_json::Value,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CapturedResponse {
+    pub status: u16,
+    pub body: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Capture {
+    pub id: String,
+    pub timestamp: String,
+    pub api_type: String,
+    pub model_name: String,
+    /// 如果这是一次重放，记录原始捕获的 id
+    #[serde(default)]
+    pub replay_of: Option<String>,
+    pub request: CapturedRequest,
+    #[serde(default)]
+    pub response: Option<CapturedResponse>,
+    #[serde(default)]
+    pub error: Option<String>,
+    pub duration_ms: u64,
+}
+
+#[derive(Serialize, Debug, Clone, TS)]
+#[ts(export)]
+pub struct CaptureSummary {
+    pub id: String,
+    pub timestamp: String,
+    pub api_type: String,
+    pub model_name: String,
+    pub url: String,
+    pub status: Option<u16>,
+    pub error: Option<String>,
+    pub replay_of: Option<String>,
+}
+
+impl From<&Capture> for CaptureSummary {
+    fn from(capture: &Capture) -> Self {
+        CaptureSummary {
+            id: capture.id.clone(),
+            timestamp: capture.timestamp.clone(),
+            api_type: capture.api_type.clone(),
+            model_name: capture.model_name.clone(),
+            url: capture.request.url.clone(),
+            status: capture.response.as_ref().map(|r| r.status),
+            error: capture.error.clone(),
+            replay_of: capture.replay_of.clone(),
+        }
+    }
+}
+
+/// 生成新的捕获 id，按时间排序即为文件名排序
+pub fn new_capture_id() -> String {
+    let counter = CAPTURE_COUNTER.fetch_add(1, Ordering::Relaxed) % 10000;
+    format!("{}-{:04}", chrono::Local::now().format("%Y%m%d-%H%M%S%.3f"), counter)
+}
+
+fn capture_dir(app: &AppHandle) -> Result<PathBuf, String> {
+    let config_dir = app.path().app_config_dir().map_err(|e| e.to_string())?;
+    Ok(config_dir.join(CAPTURE_DIR))
+}
+
+/// 捕获 id 只允许由时间戳字符组成，防止通过 id 访问目录外的文件
+fn capture_path(app: &AppHandle, id: &str) -> Result<PathBuf, String> {
+    if id.is_empty() || !id.chars().all(|c| c.is_ascii_digit() || c == '-' || c == '.') {
+        return Err("Invalid capture id".to_string());
+    }
+    Ok(capture_dir(app)?.join(format!("{}.json", id)))
+}
+
+/// 脱敏请求头中的认证信息
+pub fn redact_headers(headers: &[(&str, String)], secret: &str) -> Vec<(String, String)> {
+    headers
+        .iter()
+        .map(|(name, value)| {
+            let lower = name.to_ascii_lowercase();
+            let value = if lower == "authorization" {
+                format!("Bearer {}", REDACTED)
+            } else if lower == "x-api-key" || lower == "api-key" {
+                REDACTED.to_string()
+            } else {
+                redact_text(value, secret)
+            };
+            (name.to_string(), value)
+        })
+        .collect()
+}
+
+/// 将文本中出现的密钥替换为占位符
+pub fn redact_text(text: &str, secret: &str) -> String {
+    if secret.is_empty() {
+        text.to_string()
+    } else {
+        text.replace(secret, REDACTED)
+    }
+}
+
+/// 递归脱敏 JSON 中所有字符串值
+pub fn redact_json(value: &serde_json::Value, secret: &str) -> serde_json::Value {
+    match value {
+        serde_json::Value::String(s) => serde_json::Value::String(redact_text(s, secret)),
+        serde_json::Value::Array(items) => {
+            serde_json::Value::Array(items.iter().map(|v| redact_json(v, secret)).collect())
+        }
+        serde_json::Value::Object(map) => serde_json::Value::Object(
+            map.iter()
+                .map(|(k, v)| (k.clone(), redact_json(v, secret)))
+                .collect(),
+        ),
+        other => other.clone(),
+    }
+}
+
+/// 写入一条捕获记录并轮换旧文件。记录失败不影响请求本身。
+pub fn save_capture(app: &AppHandle, capture: &Capture) {
+    let result = (|| -> Result<(), String> {
+        let dir = capture_dir(app)?;
+        fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+        let json = serde_json::to_string_pretty(capture).map_err(|e| e.to_string())?;
+        fs::write(capture_path(app, &capture.id)?, json).map_err(|e| e.to_string())?;
+        rotate_captures(&dir);
+        Ok(())
+    })();
+
+    if let Err(e) = result {
+        SecurityLogger::log_error(app, &format!("Failed to save request capture: {}", e));
+    }
+}
+
+fn sorted_capture_files(dir: &PathBuf) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok().map(|e| e.path()))
+                .filter(|path| path.extension().and_then(|e| e.to_str()) == Some("json"))
+                .collect()
+        })
+        .unwrap_or_default();
+    files.sort();
+    files
+}
+
+fn rotate_captures(dir: &PathBuf) {
+    let files = sorted_capture_files(dir);
+    if files.len() > MAX_CAPTURES {
+        for path in &files[..files.len() - MAX_CAPTURES] {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+pub fn load_capture(app: &AppHandle, id: &str) -> Result<Capture, String> {
+    let path = capture_path(app, id)?;
+    let json = fs::read_to_string(&path).map_err(|e| format!("Capture '{}' not found: {}", id, e))?;
+    serde_json::from_str(&json).map_err(|e| format!("Capture '{}' is corrupted: {}", id, e))
+}
+
+/// 按时间倒序列出所有捕获
+pub fn list_captures(app: &AppHandle) -> Result<Vec<CaptureSummary>, String> {
+    let dir = capture_dir(app)?;
+    let mut summaries = Vec::new();
+    for path in sorted_capture_files(&dir).iter().rev() {
+        if let Ok(json) = fs::read_to_string(path) {
+            if let Ok(capture) = serde_json::from_str::<Capture>(&json) {
+                summaries.push(CaptureSummary::from(&capture));
+            }
+        }
+    }
+    Ok(summaries)
+}
+
+pub fn clear_captures(app: &AppHandle) -> Result<(), String> {
+    let dir = capture_dir(app)?;
+    for path in sorted_capture_files(&dir) {
+        fs::remove_file(&path).map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
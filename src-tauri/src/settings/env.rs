@@ -0,0 +1,276 @@
+// --- Environment Overrides ---
+//
+// 受管理的电脑上由 IT 通过环境变量下发 API 配置。变量按列表顺序查找，每个变量先查进程环境变量，
+// 再查配置目录中的 `.env`，两者都优先于 settings.dat。
+// 覆盖只作用于当前使用的配置档案，不会写回 settings.dat；设置页把这些字段显示为锁定。
+// `AI_WINDOW_API_KEY` 适用于任何 API 类型。`OPENAI_API_KEY` 常为其它工具而设置，只用于直接访问
+// api.openai.com 的 OpenAI 类型配置档案；API 地址来自覆盖或指向其它网关时不使用，以免把 OpenAI 密钥发给第三方。
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use serde::Serialize;
+use ts_rs::TS;
+
+use super::Settings;
+use crate::ApiProfile;
+
+/// 配置目录中可选的环境变量文件
+pub const ENV_FILE: &str = ".env";
+
+const API_KEY_VARS: &[&str] = &["AI_WINDOW_API_KEY"];
+const OPENAI_API_KEY_VARS: &[&str] = &["OPENAI_API_KEY"];
+/// `OPENAI_API_KEY` 只发送给这个主机
+const OPENAI_API_HOST: &str = "api.openai.com";
+const API_URL_VARS: &[&str] = &["AI_WINDOW_API_URL"];
+const MODEL_VARS: &[&str] = &["AI_WINDOW_MODEL"];
+
+/// 返回给设置页的设置，附带由环境变量提供、不能在界面中修改的字段
+#[derive(Serialize, Debug, Clone, TS)]
+#[ts(export)]
+pub struct LoadedSettings {
+    #[serde(flatten)]
+    #[ts(flatten)]
+    pub settings: Settings,
+    /// 当前配置档案中被环境变量覆盖的字段名
+    pub env_locked: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct EnvOverrides {
+    api_key: Option<String>,
+    openai_api_key: Option<String>,
+    api_url: Option<String>,
+    model_name: Option<String>,
+}
+
+impl EnvOverrides {
+    /// 读取进程环境变量和 `path` 处的 `.env`
+    pub fn from_file(path: &Path) -> Self {
+        let process_env = std::env::vars_os()
+            .filter_map(|(key, value)| Some((key.into_string().ok()?, value.into_string().ok()?)))
+            .collect();
+        Self::from_sources(&process_env, &read_env_file(path))
+    }
+
+    fn from_sources(process_env: &HashMap<String, String>, env_file: &HashMap<String, String>) -> Self {
+        let lookup = |vars: &[&str]| {
+            vars.iter().find_map(|var| {
+                let value = |source: &HashMap<String, String>| {
+                    source.get(*var).map(|value| value.trim().to_string()).filter(|value| !value.is_empty())
+                };
+                value(process_env).or_else(|| value(env_file))
+            })
+        };
+        EnvOverrides {
+            api_key: lookup(API_KEY_VARS),
+            openai_api_key: lookup(OPENAI_API_KEY_VARS),
+            api_url: lookup(API_URL_VARS),
+            model_name: lookup(MODEL_VARS),
+        }
+    }
+
+    /// 适用于该配置档案的密钥覆盖
+    fn api_key_for(&self, profile: &ApiProfile) -> Option<&String> {
+        self.api_key.as_ref().or_else(|| self.openai_api_key.as_ref().filter(|_| uses_openai_directly(self, profile)))
+    }
+
+    /// `settings` 当前配置档案中被覆盖的字段名。覆盖 API 地址时 API 类型也固定为 OpenAI 兼容。
+    pub fn locked_fields(&self, settings: &Settings) -> Vec<String> {
+        let mut fields = Vec::new();
+        if self.api_url.is_some() {
+            fields.push("api_type".to_string());
+            fields.push("api_url".to_string());
+        }
+        if self.api_key_for(&settings.current_profile()).is_some() {
+            fields.push("api_key".to_string());
+        }
+        if self.model_name.is_some() {
+            fields.push("model_name".to_string());
+        }
+        fields
+    }
+
+    /// 把覆盖值应用到当前配置档案
+    pub fn apply(&self, settings: &mut Settings) {
+        let Some(profile) = active_profile_mut(settings) else {
+            return;
+        };
+        // 密钥按覆盖之前的 API 类型和地址选择
+        if let Some(api_key) = self.api_key_for(profile).cloned() {
+            profile.api_key = api_key;
+        }
+        if let Some(api_url) = &self.api_url {
+            if profile.api_type == "openai" {
+                profile.api_type = "openai-compatible".to_string();
+            }
+            profile.api_url = api_url.clone();
+        }
+        if let Some(model_name) = &self.model_name {
+            profile.model_name = model_name.clone();
+        }
+    }
+
+    /// 保存前把被覆盖的字段恢复为 settings.dat 中的值，避免把环境变量中的值（尤其是密钥）写入磁盘
+    pub fn restore_stored(&self, settings: &mut Settings, stored: &Settings) {
+        let locked = self.locked_fields(settings);
+        if locked.is_empty() {
+            return;
+        }
+        let Some(profile) = active_profile_mut(settings) else {
+            return;
+        };
+        let stored_profile = stored.named_profile(&profile.name).unwrap_or_else(|| ApiProfile {
+            name: profile.name.clone(),
+            api_type: profile.api_type.clone(),
+            api_url: String::new(),
+            api_key: String::new(),
+            model_name: String::new(),
+        });
+        for field in &locked {
+            match field.as_str() {
+                "api_type" => profile.api_type = stored_profile.api_type.clone(),
+                "api_url" => profile.api_url = stored_profile.api_url.clone(),
+                "api_key" => profile.api_key = stored_profile.api_key.clone(),
+                "model_name" => profile.model_name = stored_profile.model_name.clone(),
+                _ => {}
+            }
+        }
+    }
+}
+
+/// 配置档案是否直接访问 OpenAI：类型为 `openai`、地址没有被覆盖且为空或指向 api.openai.com
+fn uses_openai_directly(overrides: &EnvOverrides, profile: &ApiProfile) -> bool {
+    let api_url = profile.api_url.trim();
+    profile.api_type == "openai"
+        && overrides.api_url.is_none()
+        && (api_url.is_empty()
+            || url::Url::parse(api_url).is_ok_and(|url| url.host_str() == Some(OPENAI_API_HOST)))
+}
+
+/// 与 `Settings::current_profile` 选择同一个配置档案
+fn active_profile_mut(settings: &mut Settings) -> Option<&mut ApiProfile> {
+    let index = settings.profiles.iter().position(|p| p.name == settings.active_profile).unwrap_or(0);
+    settings.profiles.get_mut(index)
+}
+
+/// 读取 `.env`，不修改进程环境变量。文件不存在或无法解析的行被忽略。
+fn read_env_file(path: &Path) -> HashMap<String, String> {
+    // `from_path` 会写入进程环境变量，只有已弃用的 `from_path_iter` 可以只读取文件
+    #[allow(deprecated)]
+    let entries = dotenv::from_path_iter(path);
+    match entries {
+        Ok(entries) => entries.filter_map(Result::ok).collect(),
+        Err(_) => HashMap::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(entries: &[(&str, &str)]) -> HashMap<String, String> {
+        entries.iter().map(|(key, value)| (key.to_string(), value.to_string())).collect()
+    }
+
+    fn with_profile(api_type: &str, api_url: &str) -> Settings {
+        let mut settings = Settings::default();
+        settings.profiles[0].api_type = api_type.to_string();
+        settings.profiles[0].api_url = api_url.to_string();
+        settings.profiles[0].api_key = "stored-key".to_string();
+        settings
+    }
+
+    fn applied(overrides: &EnvOverrides, mut settings: Settings) -> ApiProfile {
+        overrides.apply(&mut settings);
+        settings.current_profile()
+    }
+
+    #[test]
+    fn process_env_takes_precedence_over_env_file() {
+        let overrides = EnvOverrides::from_sources(
+            &vars(&[("AI_WINDOW_MODEL", "from-process")]),
+            &vars(&[("AI_WINDOW_MODEL", "from-file"), ("AI_WINDOW_API_KEY", "file-key")]),
+        );
+        let profile = applied(&overrides, with_profile("openai", ""));
+        assert_eq!(profile.model_name, "from-process");
+        assert_eq!(profile.api_key, "file-key");
+    }
+
+    #[test]
+    fn blank_values_are_ignored() {
+        let overrides =
+            EnvOverrides::from_sources(&vars(&[("AI_WINDOW_MODEL", "  ")]), &vars(&[("AI_WINDOW_MODEL", "gpt-4o")]));
+        assert_eq!(applied(&overrides, with_profile("openai", "")).model_name, "gpt-4o");
+
+        let overrides = EnvOverrides::from_sources(&vars(&[]), &vars(&[("AI_WINDOW_API_KEY", "")]));
+        assert_eq!(applied(&overrides, with_profile("openai", "")).api_key, "stored-key");
+        assert!(overrides.locked_fields(&with_profile("openai", "")).is_empty());
+    }
+
+    #[test]
+    fn overrides_take_precedence_over_stored_settings() {
+        let overrides = EnvOverrides::from_sources(
+            &vars(&[("AI_WINDOW_API_URL", "https://gateway.example.com/v1/chat/completions")]),
+            &vars(&[("AI_WINDOW_API_KEY", "env-key")]),
+        );
+        let profile = applied(&overrides, with_profile("openai", ""));
+        assert_eq!(profile.api_type, "openai-compatible");
+        assert_eq!(profile.api_url, "https://gateway.example.com/v1/chat/completions");
+        assert_eq!(profile.api_key, "env-key");
+        assert_eq!(
+            overrides.locked_fields(&with_profile("openai", "")),
+            vec!["api_type", "api_url", "api_key"]
+        );
+    }
+
+    #[test]
+    fn openai_key_is_only_sent_to_openai() {
+        let overrides = EnvOverrides::from_sources(&vars(&[("OPENAI_API_KEY", "sk-openai")]), &vars(&[]));
+        assert_eq!(applied(&overrides, with_profile("openai", "")).api_key, "sk-openai");
+        assert_eq!(
+            applied(&overrides, with_profile("openai", "https://api.openai.com/v1/chat/completions")).api_key,
+            "sk-openai"
+        );
+        let gateway = with_profile("openai-compatible", "https://gateway.example.com/v1");
+        assert_eq!(applied(&overrides, gateway.clone()).api_key, "stored-key");
+        assert!(overrides.locked_fields(&gateway).is_empty());
+        assert_eq!(applied(&overrides, with_profile("openai", "https://gateway.example.com/v1")).api_key, "stored-key");
+
+        // 地址被覆盖时不使用 OpenAI 的密钥
+        let overrides = EnvOverrides::from_sources(
+            &vars(&[("OPENAI_API_KEY", "sk-openai"), ("AI_WINDOW_API_URL", "https://gateway.example.com/v1")]),
+            &vars(&[]),
+        );
+        assert_eq!(applied(&overrides, with_profile("openai", "")).api_key, "stored-key");
+    }
+
+    #[test]
+    fn generic_key_applies_to_every_api_type() {
+        let overrides = EnvOverrides::from_sources(
+            &vars(&[("AI_WINDOW_API_KEY", "generic"), ("OPENAI_API_KEY", "sk-openai")]),
+            &vars(&[]),
+        );
+        assert_eq!(applied(&overrides, with_profile("openai", "")).api_key, "generic");
+        let gateway = with_profile("openai-compatible", "https://gateway.example.com/v1");
+        assert_eq!(applied(&overrides, gateway).api_key, "generic");
+    }
+
+    #[test]
+    fn restore_stored_keeps_overridden_values_out_of_settings() {
+        let overrides = EnvOverrides::from_sources(
+            &vars(&[("AI_WINDOW_API_KEY", "env-key"), ("AI_WINDOW_MODEL", "env-model")]),
+            &vars(&[]),
+        );
+        let stored = with_profile("openai", "");
+        let mut settings = stored.clone();
+        overrides.apply(&mut settings);
+        settings.system_prompt = "changed".to_string();
+        overrides.restore_stored(&mut settings, &stored);
+
+        let profile = settings.current_profile();
+        assert_eq!(profile.api_key, "stored-key");
+        assert_eq!(profile.model_name, stored.current_profile().model_name);
+        assert_eq!(settings.system_prompt, "changed");
+    }
+}
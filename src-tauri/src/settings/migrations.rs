@@ -0,0 +1,398 @@
+// --- Settings Migrations ---
+//
+// settings.dat 的结构变化通过 `MIGRATIONS` 管理，已执行的版本号保存在 `settings_version` 键中。
+// 每个迁移只追加不修改；执行前先把当前的 settings.dat 复制为 `settings.dat.v<版本>.bak`。
+// 需要数据密钥的迁移在加密已启用但尚未解锁时推迟，解锁后继续执行；未启用加密时跳过。
+// 备份与 settings.dat 一样保存 API 密钥：密钥加密之后以及启用加密、更换密钥时，备份中的密钥一并处理，
+// 无法处理的备份直接删除，不在磁盘上留下明文密钥。
+
+use std::path::{Path, PathBuf};
+
+use serde_json::{Map, Value};
+use tauri::{AppHandle, Manager};
+
+use super::{open_store, DEFAULT_PROFILE_NAME, SETTINGS_FILE};
+use crate::vault;
+
+/// 保存已执行迁移版本号的键
+const VERSION_KEY: &str = "settings_version";
+
+/// 一次设置迁移
+struct Migration {
+    description: &'static str,
+    /// 需要数据密钥才能执行
+    needs_key: bool,
+    apply: fn(&mut Map<String, Value>) -> Result<(), String>,
+}
+
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        description: "Rename legacy settings keys",
+        needs_key: false,
+        apply: rename_legacy_keys,
+    },
+    Migration {
+        description: "Move the API configuration into the profile list",
+        needs_key: false,
+        apply: move_api_config_to_profiles,
+    },
+    Migration {
+        description: "Encrypt API keys",
+        needs_key: true,
+        apply: encrypt_secrets,
+    },
+];
+
+/// 旧键名与新键名
+const RENAMED_KEYS: &[(&str, &str)] = &[("selectedSystemPromptPreset", "system_prompt_preset")];
+
+/// 旧版本中直接保存在设置顶层的 API 配置字段及其默认值
+const LEGACY_PROFILE_KEYS: &[(&str, &str)] = &[
+    ("api_type", "openai"),
+    ("api_url", ""),
+    ("api_key", ""),
+    ("model_name", "gpt-4o-mini"),
+];
+
+fn rename_legacy_keys(settings: &mut Map<String, Value>) -> Result<(), String> {
+    for (old, new) in RENAMED_KEYS {
+        if let Some(value) = settings.remove(*old) {
+            // 两个键都存在时以新键为准
+            settings.entry(new.to_string()).or_insert(value);
+        }
+    }
+    Ok(())
+}
+
+fn move_api_config_to_profiles(settings: &mut Map<String, Value>) -> Result<(), String> {
+    let mut profile = Map::new();
+    for (key, default) in LEGACY_PROFILE_KEYS {
+        if let Some(value) = settings.remove(*key) {
+            profile.insert(key.to_string(), value);
+        } else {
+            profile.insert(key.to_string(), Value::String(default.to_string()));
+        }
+    }
+    // 从未保存过任何 API 配置时没有需要移动的内容，使用默认的配置档案
+    let unchanged = LEGACY_PROFILE_KEYS.iter().all(|(key, default)| profile[*key] == Value::String(default.to_string()));
+    if unchanged && !settings.contains_key("profiles") {
+        return Ok(());
+    }
+
+    let mut profiles = match settings.remove("profiles") {
+        Some(Value::Array(profiles)) => profiles,
+        _ => Vec::new(),
+    };
+    // 已有同名的配置档案时加上序号
+    let taken = |name: &str| profiles.iter().any(|p| p.get("name").and_then(|v| v.as_str()) == Some(name));
+    let mut name = DEFAULT_PROFILE_NAME.to_string();
+    let mut suffix = 2;
+    while taken(&name) {
+        name = format!("{} {}", DEFAULT_PROFILE_NAME, suffix);
+        suffix += 1;
+    }
+    profile.insert("name".to_string(), Value::String(name.clone()));
+    profiles.insert(0, Value::Object(profile));
+
+    settings.insert("profiles".to_string(), Value::Array(profiles));
+    settings.insert("active_profile".to_string(), Value::String(name));
+    Ok(())
+}
+
+fn encrypt_secrets(settings: &mut Map<String, Value>) -> Result<(), String> {
+    vault::map_secret_values(settings, vault::seal_secret).map_err(|e| e.to_string())
+}
+
+fn stored_version(settings: &Map<String, Value>) -> Result<usize, String> {
+    let version = settings.get(VERSION_KEY).and_then(|v| v.as_u64()).unwrap_or(0) as usize;
+    if version > MIGRATIONS.len() {
+        return Err(format!(
+            "Settings were saved by a newer version of AI Window (settings version {})",
+            version
+        ));
+    }
+    Ok(version)
+}
+
+/// 依次执行 `settings` 尚未执行的迁移。`key_available` 为 false 时在需要数据密钥的迁移处停止。
+/// 每个迁移执行前调用 `before`（备份），执行后调用 `after`（保存）。返回执行的迁移数。
+fn apply_pending(
+    settings: &mut Map<String, Value>,
+    vault_enabled: bool,
+    key_available: bool,
+    mut before: impl FnMut(usize) -> Result<(), String>,
+    mut after: impl FnMut(&Migration, &Map<String, Value>) -> Result<(), String>,
+) -> Result<u32, String> {
+    let version = stored_version(settings)?;
+    let mut applied = 0;
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+        if migration.needs_key && vault_enabled && !key_available {
+            break;
+        }
+        before(index)?;
+
+        // 未启用加密时没有需要加密的密钥
+        if !migration.needs_key || vault_enabled {
+            (migration.apply)(settings)
+                .map_err(|e| format!("Settings migration '{}' failed: {}", migration.description, e))?;
+        }
+        settings.insert(VERSION_KEY.to_string(), Value::from(index + 1));
+
+        after(migration, settings)?;
+        applied += 1;
+    }
+    Ok(applied)
+}
+
+/// 执行尚未执行的迁移，每个迁移完成后立即保存。返回执行的迁移数。
+pub fn migrate(app: &AppHandle) -> Result<u32, String> {
+    let config_dir = app.path().app_config_dir().map_err(|e| e.to_string())?;
+    let store = open_store(app)?;
+    let mut settings: Map<String, Value> = store.entries().into_iter().collect();
+    let path = config_dir.join(SETTINGS_FILE);
+
+    let vault_enabled = vault::is_enabled(&config_dir);
+    let key_available = !vault_enabled || vault::status(&config_dir).unlocked;
+    apply_pending(
+        &mut settings,
+        vault_enabled,
+        key_available,
+        |index| {
+            if path.exists() {
+                std::fs::copy(&path, backup_path(&config_dir, index))
+                    .map_err(|e| format!("Failed to back up settings before migrating: {}", e))?;
+            }
+            Ok(())
+        },
+        |migration, settings| {
+            store.clear();
+            for (key, value) in settings {
+                store.set(key.clone(), value.clone());
+            }
+            store.save().map_err(|e| e.to_string())?;
+            // 密钥加密之后，之前的备份中仍是明文
+            if migration.needs_key && vault_enabled {
+                map_backup_secrets(&config_dir, vault::seal_secret)?;
+            }
+            Ok(())
+        },
+    )
+}
+
+fn backup_path(config_dir: &Path, index: usize) -> PathBuf {
+    config_dir.join(format!("{}.v{}.bak", SETTINGS_FILE, index))
+}
+
+/// 配置目录中迁移前保存的全部备份
+fn backup_paths(config_dir: &Path) -> Vec<PathBuf> {
+    let prefix = format!("{}.v", SETTINGS_FILE);
+    let Ok(entries) = std::fs::read_dir(config_dir) else {
+        return Vec::new();
+    };
+    entries
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| {
+            let name = path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+            name.starts_with(&prefix) && name.ends_with(".bak")
+        })
+        .collect()
+}
+
+/// 用 `f` 处理每个迁移备份中的 API 密钥，与 `vault::map_secrets` 处理 settings.dat 的方式相同。
+/// 无法读取或处理的备份被删除。
+pub fn map_backup_secrets(
+    config_dir: &Path,
+    f: impl Fn(&str) -> Result<String, vault::VaultError>,
+) -> Result<(), String> {
+    for path in backup_paths(config_dir) {
+        let sealed = std::fs::read(&path)
+            .ok()
+            .and_then(|bytes| serde_json::from_slice::<Map<String, Value>>(&bytes).ok())
+            .and_then(|mut settings| {
+                vault::map_secret_values(&mut settings, &f).ok()?;
+                serde_json::to_vec_pretty(&settings).ok()
+            });
+        let written = match sealed {
+            Some(bytes) => {
+                let temp_path = path.with_extension("bak.tmp");
+                let result = std::fs::write(&temp_path, bytes).and_then(|_| std::fs::rename(&temp_path, &path));
+                if result.is_err() {
+                    let _ = std::fs::remove_file(&temp_path);
+                }
+                result.is_ok()
+            }
+            None => false,
+        };
+        if !written {
+            std::fs::remove_file(&path)
+                .map_err(|e| format!("Failed to remove settings backup {}: {}", path.display(), e))?;
+        }
+    }
+    Ok(())
+}
+
+/// 把备份中的设置升级到当前版本。需要数据密钥的迁移跳过，恢复时会按当前的加密状态重新加密密钥。
+pub fn upgrade_entries(settings: &mut Map<String, Value>) -> Result<(), String> {
+    let version = stored_version(settings)?;
+    for migration in MIGRATIONS.iter().skip(version).filter(|migration| !migration.needs_key) {
+        (migration.apply)(settings)
+            .map_err(|e| format!("Settings migration '{}' failed: {}", migration.description, e))?;
+    }
+    settings.insert(VERSION_KEY.to_string(), Value::from(MIGRATIONS.len()));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn object(value: Value) -> Map<String, Value> {
+        match value {
+            Value::Object(map) => map,
+            _ => unreachable!(),
+        }
+    }
+
+    fn run(settings: &mut Map<String, Value>, vault_enabled: bool, key_available: bool) -> Result<u32, String> {
+        apply_pending(settings, vault_enabled, key_available, |_| Ok(()), |_, _| Ok(()))
+    }
+
+    #[test]
+    fn migrates_v0_settings_to_the_current_shape() {
+        let mut settings = object(json!({
+            "selectedSystemPromptPreset": "minimal",
+            "api_type": "openai-compatible",
+            "api_url": "https://gateway.example.com/v1/chat/completions",
+            "api_key": "sk-test",
+            "model_name": "llama3",
+            "shortcut": "Alt+A",
+        }));
+
+        let mut backups = Vec::new();
+        let mut saved = Vec::new();
+        let applied = apply_pending(
+            &mut settings,
+            false,
+            true,
+            |index| {
+                backups.push(index);
+                Ok(())
+            },
+            |_, settings| {
+                saved.push(settings[VERSION_KEY].clone());
+                Ok(())
+            },
+        )
+        .unwrap();
+
+        assert_eq!(applied, MIGRATIONS.len() as u32);
+        assert_eq!(backups, vec![0, 1, 2]);
+        assert_eq!(saved, vec![json!(1), json!(2), json!(3)]);
+        assert_eq!(
+            Value::Object(settings),
+            json!({
+                "system_prompt_preset": "minimal",
+                "shortcut": "Alt+A",
+                "active_profile": "default",
+                "profiles": [{
+                    "name": "default",
+                    "api_type": "openai-compatible",
+                    "api_url": "https://gateway.example.com/v1/chat/completions",
+                    "api_key": "sk-test",
+                    "model_name": "llama3",
+                }],
+                "settings_version": MIGRATIONS.len(),
+            })
+        );
+    }
+
+    #[test]
+    fn rerunning_is_a_no_op() {
+        let mut settings = object(json!({ "api_key": "sk-test", "selectedSystemPromptPreset": "minimal" }));
+        run(&mut settings, false, true).unwrap();
+        let migrated = settings.clone();
+
+        let applied = apply_pending(
+            &mut settings,
+            false,
+            true,
+            |_| panic!("no backup expected"),
+            |_, _| panic!("no save expected"),
+        )
+        .unwrap();
+        assert_eq!(applied, 0);
+        assert_eq!(settings, migrated);
+    }
+
+    #[test]
+    fn fresh_settings_keep_the_default_profile() {
+        let mut settings = Map::new();
+        run(&mut settings, false, true).unwrap();
+        assert_eq!(Value::Object(settings), json!({ "settings_version": MIGRATIONS.len() }));
+    }
+
+    #[test]
+    fn legacy_profile_name_does_not_clash() {
+        let mut settings = object(json!({
+            "api_key": "sk-legacy",
+            "profiles": [{ "name": "default", "api_type": "openai", "api_url": "", "api_key": "", "model_name": "m" }],
+        }));
+        run(&mut settings, false, true).unwrap();
+        assert_eq!(settings["active_profile"], json!("default 2"));
+        assert_eq!(settings["profiles"][0]["name"], json!("default 2"));
+        assert_eq!(settings["profiles"][0]["api_key"], json!("sk-legacy"));
+        assert_eq!(settings["profiles"][1]["name"], json!("default"));
+    }
+
+    #[test]
+    fn key_migrations_wait_for_the_vault_to_be_unlocked() {
+        let mut settings = object(json!({ "api_key": "sk-test" }));
+        assert_eq!(run(&mut settings, true, false).unwrap(), 2);
+        assert_eq!(settings[VERSION_KEY], json!(2));
+        assert_eq!(settings["profiles"][0]["api_key"], json!("sk-test"));
+        // 仍然锁定时再次执行不会跳过加密迁移
+        assert_eq!(run(&mut settings, true, false).unwrap(), 0);
+        assert_eq!(settings[VERSION_KEY], json!(2));
+    }
+
+    #[test]
+    fn newer_settings_are_rejected() {
+        let mut settings = object(json!({ "settings_version": MIGRATIONS.len() + 1 }));
+        assert!(run(&mut settings, false, true).is_err());
+        assert!(upgrade_entries(&mut settings).is_err());
+    }
+
+    #[test]
+    fn upgrade_entries_skips_key_migrations_and_sets_the_version() {
+        let mut settings = object(json!({ "api_key": "sk-test" }));
+        upgrade_entries(&mut settings).unwrap();
+        assert_eq!(settings[VERSION_KEY], json!(MIGRATIONS.len()));
+        assert_eq!(settings["profiles"][0]["api_key"], json!("sk-test"));
+    }
+
+    #[test]
+    fn backup_secrets_are_mapped_or_removed() {
+        let dir = std::env::temp_dir().join(format!("ai-window-migrations-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let readable = backup_path(&dir, 0);
+        let corrupt = backup_path(&dir, 1);
+        let unrelated = dir.join("settings.dat");
+        std::fs::write(&readable, json!({ "profiles": [{ "name": "default", "api_key": "sk-test" }] }).to_string())
+            .unwrap();
+        std::fs::write(&corrupt, "not json").unwrap();
+        std::fs::write(&unrelated, "{}").unwrap();
+
+        map_backup_secrets(&dir, |value| Ok(format!("sealed:{}", value))).unwrap();
+        let mapped: Value = serde_json::from_slice(&std::fs::read(&readable).unwrap()).unwrap();
+        assert_eq!(mapped["profiles"][0]["api_key"], json!("sealed:sk-test"));
+        assert!(!corrupt.exists());
+        assert!(unrelated.exists());
+
+        map_backup_secrets(&dir, |_| Err(vault::VaultError::Locked)).unwrap();
+        assert!(!readable.exists());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,174 @@
+// --- Settings ---
+//
+// 所有设置的唯一定义。settings.dat 中每个字段保存为一个同名的键，缺失的键使用默认值；
+// 类型不对的旧值被忽略并回退到默认值，不影响其它字段。settings.dat 中不属于这里的键保持不变。
+// 旧版本的 settings.dat 在启动时由 `migrations` 升级到当前结构。
+// 运行时设置由 `service` 缓存在内存中，`load` 从中返回应用了环境变量覆盖（见 `env`）的设置，
+// `from_store` 只读取 settings.dat。
+
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager};
+use tauri_plugin_store::StoreBuilder;
+use ts_rs::TS;
+
+use crate::{compaction, ApiProfile, DEFAULT_MAX_RESPONSE_CHARS};
+
+mod env;
+mod migrations;
+mod service;
+mod validate;
+
+pub use env::{EnvOverrides, LoadedSettings};
+pub use migrations::{map_backup_secrets, migrate, upgrade_entries};
+pub use service::{spawn_watcher, SettingsService, SETTINGS_CHANGED_EVENT};
+pub use validate::{validate, SettingsError};
+
+/// 没有配置档案时使用的默认配置名称
+pub const DEFAULT_PROFILE_NAME: &str = "default";
+
+/// 设置文件名（位于应用配置目录）
+pub const SETTINGS_FILE: &str = "settings.dat";
+
+pub type SettingsStore = tauri_plugin_store::Store<tauri::Wry>;
+
+#[derive(Serialize, Deserialize, Debug, Clone, TS)]
+#[serde(default)]
+#[ts(export)]
+pub struct Settings {
+    /// 当前使用的 API 配置名称
+    pub active_profile: String,
+    /// 全部命名的 API 配置
+    pub profiles: Vec<ApiProfile>,
+    pub system_prompt: String,
+    /// 提示库中所选提示的 id，为 `custom` 时使用 `system_prompt`
+    pub system_prompt_preset: String,
+    /// 生成标题和摘要使用的模型，为空时沿用当前模型
+    pub summary_model: String,
+    pub auto_summaries: bool,
+
+    pub shortcut: String,
+    pub clear_chat_shortcut: String,
+    pub borderless_shortcut: String,
+    pub borderless: bool,
+
+    /// 开发者模式：记录请求与响应
+    pub capture_requests: bool,
+    /// 回答的最大字符数，0 表示不截断
+    pub max_response_chars: u32,
+    /// 允许访问的内部网关
+    pub endpoint_allowlist: Vec<String>,
+
+    pub auto_compaction: bool,
+    pub context_window_tokens: u32,
+    pub compaction_threshold: f64,
+    pub compaction_keep_turns: u32,
+
+    pub incognito: bool,
+    /// 历史保留天数，0 表示永久保留
+    pub retention_days: u32,
+    /// 最多保留的对话数，0 表示不限制
+    pub retention_max_conversations: u32,
+    pub retention_keep_pinned: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            active_profile: DEFAULT_PROFILE_NAME.to_string(),
+            profiles: vec![default_profile()],
+            system_prompt: "You are a helpful assistant.".to_string(),
+            system_prompt_preset: "default".to_string(),
+            summary_model: String::new(),
+            auto_summaries: true,
+            shortcut: "Alt+Space".to_string(),
+            clear_chat_shortcut: "Ctrl+Q".to_string(),
+            borderless_shortcut: "Ctrl+Shift+B".to_string(),
+            borderless: false,
+            capture_requests: false,
+            max_response_chars: DEFAULT_MAX_RESPONSE_CHARS as u32,
+            endpoint_allowlist: Vec::new(),
+            auto_compaction: true,
+            context_window_tokens: compaction::DEFAULT_CONTEXT_WINDOW,
+            compaction_threshold: compaction::DEFAULT_THRESHOLD,
+            compaction_keep_turns: compaction::DEFAULT_KEEP_TURNS as u32,
+            incognito: false,
+            retention_days: 0,
+            retention_max_conversations: 0,
+            retention_keep_pinned: true,
+        }
+    }
+}
+
+fn default_profile() -> ApiProfile {
+    ApiProfile {
+        name: DEFAULT_PROFILE_NAME.to_string(),
+        api_type: "openai".to_string(),
+        api_url: "https://api.openai.com/v1/chat/completions".to_string(),
+        api_key: String::new(),
+        model_name: "gpt-4o-mini".to_string(),
+    }
+}
+
+/// 打开 settings.dat。同一路径返回同一个 store，保存时机由 `SettingsService` 控制。
+pub fn open_store(app: &AppHandle) -> Result<Arc<SettingsStore>, String> {
+    let config_dir = app.path().app_config_dir().map_err(|e| e.to_string())?;
+    StoreBuilder::new(app.app_handle(), config_dir.join(SETTINGS_FILE))
+        .disable_auto_save()
+        .build()
+        .map_err(|e| e.to_string())
+}
+
+fn to_map(settings: &Settings) -> serde_json::Map<String, serde_json::Value> {
+    match serde_json::to_value(settings) {
+        Ok(serde_json::Value::Object(map)) => map,
+        _ => serde_json::Map::new(),
+    }
+}
+
+impl Settings {
+    pub fn from_store(store: &SettingsStore) -> Self {
+        let defaults = to_map(&Settings::default());
+        let mut merged = defaults.clone();
+        for (key, default) in &defaults {
+            let Some(value) = store.get(key) else {
+                continue;
+            };
+            merged.insert(key.clone(), value);
+            // 逐个字段检查，类型不对的值只影响该字段
+            if serde_json::from_value::<Settings>(serde_json::Value::Object(merged.clone())).is_err() {
+                merged.insert(key.clone(), default.clone());
+            }
+        }
+        serde_json::from_value(serde_json::Value::Object(merged)).unwrap_or_default()
+    }
+
+    pub fn load(app: &AppHandle) -> Result<Self, String> {
+        let service = app
+            .try_state::<SettingsService>()
+            .ok_or_else(|| "Settings are not loaded yet".to_string())?;
+        Ok(service.effective())
+    }
+
+    /// 当前使用的 API 配置。`active_profile` 不存在时使用第一个配置。
+    pub fn current_profile(&self) -> ApiProfile {
+        self.named_profile(&self.active_profile)
+            .or_else(|| self.profiles.first().cloned())
+            .unwrap_or_else(default_profile)
+    }
+
+    /// 按名称从 `profiles` 中查找 API 配置
+    pub fn named_profile(&self, name: &str) -> Option<ApiProfile> {
+        self.profiles.iter().find(|p| p.name == name).cloned()
+    }
+
+    /// 用于生成标题、摘要和压缩上下文的配置：与 `profile` 相同，模型可单独指定
+    pub fn summary_profile(&self, profile: &ApiProfile) -> ApiProfile {
+        let mut profile = profile.clone();
+        if !self.summary_model.trim().is_empty() {
+            profile.model_name = self.summary_model.trim().to_string();
+        }
+        profile
+    }
+}
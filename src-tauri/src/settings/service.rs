@@ -0,0 +1,218 @@
+// --- Settings Service ---
+//
+// 设置在内存中保存一份，命令直接读取，不再每次从磁盘重新加载。修改先写入内存中的 store，
+// 短暂延迟后合并为一次保存；定期检查 settings.dat 和 `.env` 的修改时间，发现外部修改时重新读取。
+// 设置有变化时发出 `settings-changed` 事件并附带变化的字段名，所有窗口据此同步。
+
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, SystemTime};
+
+use serde::Serialize;
+use tauri::{AppHandle, Emitter, Manager};
+
+use super::env::ENV_FILE;
+use super::{open_store, to_map, EnvOverrides, Settings, SettingsStore, SETTINGS_FILE};
+use crate::{privacy, register_shortcut, vault, SecurityLogger};
+
+pub const SETTINGS_CHANGED_EVENT: &str = "settings-changed";
+/// 连续修改合并为一次保存的等待时间
+const SAVE_DELAY: Duration = Duration::from_millis(500);
+/// 检查外部修改的间隔
+const WATCH_INTERVAL: Duration = Duration::from_secs(2);
+
+#[derive(Serialize, Clone)]
+struct SettingsChanged {
+    keys: Vec<String>,
+}
+
+pub struct SettingsService {
+    store: Arc<SettingsStore>,
+    config_dir: PathBuf,
+    settings: RwLock<Settings>,
+    env: RwLock<EnvOverrides>,
+    /// 每次修改递增，延迟保存时据此判断之后是否还有新的修改
+    generation: AtomicU64,
+    /// 有尚未保存到磁盘的修改
+    dirty: AtomicBool,
+    /// 最近一次读取或保存时 settings.dat 和 `.env` 的修改时间
+    settings_modified: Mutex<Option<SystemTime>>,
+    env_modified: Mutex<Option<SystemTime>>,
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// 两份设置中值不同的字段名
+fn changed_keys(old: &Settings, new: &Settings) -> Vec<String> {
+    let old = to_map(old);
+    to_map(new)
+        .into_iter()
+        .filter(|(key, value)| old.get(key) != Some(value))
+        .map(|(key, _)| key)
+        .collect()
+}
+
+impl SettingsService {
+    pub fn new(app: &AppHandle) -> Result<Self, String> {
+        let config_dir = app.path().app_config_dir().map_err(|e| e.to_string())?;
+        let store = open_store(app)?;
+        let env_path = config_dir.join(ENV_FILE);
+        Ok(SettingsService {
+            settings: RwLock::new(Settings::from_store(&store)),
+            env: RwLock::new(EnvOverrides::from_file(&env_path)),
+            generation: AtomicU64::new(0),
+            dirty: AtomicBool::new(false),
+            settings_modified: Mutex::new(modified(&config_dir.join(SETTINGS_FILE))),
+            env_modified: Mutex::new(modified(&env_path)),
+            store,
+            config_dir,
+        })
+    }
+
+    pub fn store(&self) -> Arc<SettingsStore> {
+        self.store.clone()
+    }
+
+    /// settings.dat 中保存的设置
+    pub fn get(&self) -> Settings {
+        self.settings.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    pub fn env_overrides(&self) -> EnvOverrides {
+        self.env.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// 应用了环境变量覆盖的设置
+    pub fn effective(&self) -> Settings {
+        let mut settings = self.get();
+        self.env_overrides().apply(&mut settings);
+        settings
+    }
+
+    /// 写入新的设置，稍后保存到磁盘。启用加密时先加密 API 密钥再写入 store，
+    /// 加密失败（例如加密库已锁定）时 store 保持不变，明文密钥不会进入 store。
+    pub fn update(&self, app: &AppHandle, settings: &Settings) -> Result<(), String> {
+        let mut values = to_map(settings);
+        if vault::is_enabled(&self.config_dir) {
+            vault::map_secret_values(&mut values, vault::seal_secret)?;
+        }
+        for (key, value) in values {
+            self.store.set(key, value);
+        }
+        self.refresh(app);
+        self.schedule_save(app);
+        Ok(())
+    }
+
+    /// 直接修改 store（迁移、更换密钥、恢复备份等）之后调用：立即保存并同步内存中的设置
+    pub fn commit(&self, app: &AppHandle) -> Result<(), String> {
+        self.dirty.store(true, Ordering::SeqCst);
+        self.flush()?;
+        self.refresh(app);
+        Ok(())
+    }
+
+    /// 立即保存尚未保存的修改
+    pub fn flush(&self) -> Result<(), String> {
+        if !self.dirty.swap(false, Ordering::SeqCst) {
+            return Ok(());
+        }
+        if let Err(e) = self.store.save() {
+            self.dirty.store(true, Ordering::SeqCst);
+            return Err(e.to_string());
+        }
+        *self.settings_modified.lock().unwrap_or_else(|e| e.into_inner()) =
+            modified(&self.config_dir.join(SETTINGS_FILE));
+        Ok(())
+    }
+
+    /// 从 store 重新读取设置，有变化时通知前端
+    fn refresh(&self, app: &AppHandle) -> Vec<String> {
+        let settings = Settings::from_store(&self.store);
+        self.replace(app, Some(settings), None)
+    }
+
+    /// 替换内存中的设置或环境变量覆盖，返回生效值有变化的字段名
+    fn replace(&self, app: &AppHandle, settings: Option<Settings>, env: Option<EnvOverrides>) -> Vec<String> {
+        let before = self.effective();
+        if let Some(settings) = settings {
+            *self.settings.write().unwrap_or_else(|e| e.into_inner()) = settings;
+        }
+        if let Some(env) = env {
+            *self.env.write().unwrap_or_else(|e| e.into_inner()) = env;
+        }
+        let after = self.effective();
+
+        let keys = changed_keys(&before, &after);
+        if !keys.is_empty() {
+            privacy::sync_incognito(&after);
+            let _ = app.emit(SETTINGS_CHANGED_EVENT, SettingsChanged { keys: keys.clone() });
+        }
+        keys
+    }
+
+    fn schedule_save(&self, app: &AppHandle) {
+        self.dirty.store(true, Ordering::SeqCst);
+        let generation = self.generation.fetch_add(1, Ordering::SeqCst) + 1;
+        let app = app.clone();
+        tauri::async_runtime::spawn(async move {
+            tokio::time::sleep(SAVE_DELAY).await;
+            let service = app.state::<SettingsService>();
+            if service.generation.load(Ordering::SeqCst) != generation {
+                return;
+            }
+            if let Err(e) = service.flush() {
+                SecurityLogger::log_error(&app, &format!("Failed to save settings: {}", e));
+            }
+        });
+    }
+
+    /// 检查 settings.dat 和 `.env` 是否被其它程序修改
+    fn check_external_changes(&self, app: &AppHandle) {
+        let env_path = self.config_dir.join(ENV_FILE);
+        let env_modified = modified(&env_path);
+        let env_changed = {
+            let mut known = self.env_modified.lock().unwrap_or_else(|e| e.into_inner());
+            std::mem::replace(&mut *known, env_modified) != env_modified
+        };
+        if env_changed {
+            self.replace(app, None, Some(EnvOverrides::from_file(&env_path)));
+        }
+
+        // 尚未保存的修改优先，保存时会覆盖外部修改
+        if self.dirty.load(Ordering::SeqCst) {
+            return;
+        }
+        let settings_modified = modified(&self.config_dir.join(SETTINGS_FILE));
+        let settings_changed = {
+            let mut known = self.settings_modified.lock().unwrap_or_else(|e| e.into_inner());
+            std::mem::replace(&mut *known, settings_modified) != settings_modified
+        };
+        if settings_changed {
+            if let Err(e) = self.store.reload() {
+                SecurityLogger::log_error(app, &format!("Failed to reload settings: {}", e));
+                return;
+            }
+            // 设置页保存时会先注册新的快捷键，外部修改需要在这里注册
+            if self.refresh(app).iter().any(|key| key == "shortcut") {
+                if let Err(e) = register_shortcut(app.clone(), self.get().shortcut) {
+                    SecurityLogger::log_error(app, &format!("Failed to register the global shortcut: {}", e));
+                }
+            }
+        }
+    }
+}
+
+/// 定期检查设置文件的外部修改
+pub fn spawn_watcher(app: AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        let mut interval = tokio::time::interval(WATCH_INTERVAL);
+        loop {
+            interval.tick().await;
+            app.state::<SettingsService>().check_external_changes(&app);
+        }
+    });
+}
@@ -0,0 +1,188 @@
+// --- Settings Validation ---
+//
+// 保存前校验全部字段，错误按字段名收集后一起返回，设置页据此标出每个出错的输入框。
+// API 地址和模型名称使用与发送请求前（`resolve_profile`）相同的网络策略和模型语法。
+
+use std::collections::BTreeMap;
+use std::ops::RangeInclusive;
+use std::str::FromStr;
+
+use serde::Serialize;
+use tauri_plugin_global_shortcut::Shortcut;
+use ts_rs::TS;
+use url::Url;
+
+use super::Settings;
+use crate::{effective_api_url, net_policy, providers, ApiProfile};
+
+/// 支持的 API 类型
+const API_TYPES: &[&str] = &["openai", "openai-compatible", "openrouter", "ollama", "gemini", "cloudflare", "mock"];
+
+const MAX_RESPONSE_CHARS_RANGE: RangeInclusive<u32> = 100..=10_000_000;
+const CONTEXT_WINDOW_RANGE: RangeInclusive<u32> = 1_000..=10_000_000;
+const COMPACTION_THRESHOLD_RANGE: RangeInclusive<f64> = 0.1..=1.0;
+const MAX_COMPACTION_KEEP_TURNS: u32 = 100;
+const MAX_RETENTION_DAYS: u32 = 36_500;
+
+#[derive(Debug, Clone, Serialize, TS)]
+#[ts(export)]
+#[serde(tag = "type")]
+pub enum SettingsError {
+    /// 校验失败。键为字段名，配置档案中的字段为 `profiles.<序号>.<字段>`
+    Invalid { fields: BTreeMap<String, String> },
+    Internal { message: String },
+}
+
+impl SettingsError {
+    pub fn field(field: &str, message: impl Into<String>) -> Self {
+        SettingsError::Invalid { fields: BTreeMap::from([(field.to_string(), message.into())]) }
+    }
+}
+
+impl From<String> for SettingsError {
+    fn from(message: String) -> Self {
+        SettingsError::Internal { message }
+    }
+}
+
+#[derive(Default)]
+struct FieldErrors(BTreeMap<String, String>);
+
+impl FieldErrors {
+    /// 每个字段只保留第一个错误
+    fn add(&mut self, field: impl Into<String>, message: impl Into<String>) {
+        self.0.entry(field.into()).or_insert_with(|| message.into());
+    }
+
+    fn check(&mut self, field: &str, result: Result<(), String>) {
+        if let Err(message) = result {
+            self.add(field, message);
+        }
+    }
+}
+
+/// 校验全部设置，返回每个出错字段的说明
+pub fn validate(settings: &Settings) -> Result<(), SettingsError> {
+    let mut errors = FieldErrors::default();
+
+    for (index, entry) in settings.endpoint_allowlist.iter().enumerate() {
+        errors.check(&format!("endpoint_allowlist.{}", index), net_policy::check_allowlist_entry(entry));
+    }
+    let allowlist = &settings.endpoint_allowlist;
+
+    if settings.named_profile(&settings.active_profile).is_none() {
+        errors.add("active_profile", format!("There is no profile named '{}'", settings.active_profile));
+    }
+    let active = settings.current_profile();
+    if !settings.summary_model.trim().is_empty() && active.api_type != "mock" {
+        let api_url = effective_api_url(&active.api_type, &active.api_url);
+        errors.check(
+            "summary_model",
+            providers::validate_model_name(&active.api_type, &api_url, settings.summary_model.trim()),
+        );
+    }
+
+    let mut names = Vec::new();
+    for (index, profile) in settings.profiles.iter().enumerate() {
+        let prefix = format!("profiles.{}.", index);
+        let name = profile.name.trim();
+        if name.is_empty() {
+            errors.add(format!("{}name", prefix), "Profile name cannot be empty");
+        } else if names.contains(&name) {
+            errors.add(format!("{}name", prefix), format!("Another profile is already named '{}'", name));
+        }
+        names.push(name);
+        check_profile(&mut errors, &prefix, profile, allowlist);
+    }
+
+    check_shortcuts(
+        &mut errors,
+        &[
+            ("shortcut", &settings.shortcut),
+            ("clear_chat_shortcut", &settings.clear_chat_shortcut),
+            ("borderless_shortcut", &settings.borderless_shortcut),
+        ],
+    );
+
+    if settings.max_response_chars != 0 && !MAX_RESPONSE_CHARS_RANGE.contains(&settings.max_response_chars) {
+        errors.add("max_response_chars", out_of_range(&MAX_RESPONSE_CHARS_RANGE, " (or 0 for no limit)"));
+    }
+    if !CONTEXT_WINDOW_RANGE.contains(&settings.context_window_tokens) {
+        errors.add("context_window_tokens", out_of_range(&CONTEXT_WINDOW_RANGE, ""));
+    }
+    if !COMPACTION_THRESHOLD_RANGE.contains(&settings.compaction_threshold) {
+        errors.add("compaction_threshold", out_of_range(&COMPACTION_THRESHOLD_RANGE, ""));
+    }
+    if settings.compaction_keep_turns > MAX_COMPACTION_KEEP_TURNS {
+        errors.add("compaction_keep_turns", out_of_range(&(0..=MAX_COMPACTION_KEEP_TURNS), ""));
+    }
+    if settings.retention_days > MAX_RETENTION_DAYS {
+        errors.add("retention_days", out_of_range(&(0..=MAX_RETENTION_DAYS), " (or 0 to keep forever)"));
+    }
+
+    if errors.0.is_empty() {
+        Ok(())
+    } else {
+        Err(SettingsError::Invalid { fields: errors.0 })
+    }
+}
+
+fn out_of_range<T: std::fmt::Display>(range: &RangeInclusive<T>, note: &str) -> String {
+    format!("Must be between {} and {}{}", range.start(), range.end(), note)
+}
+
+/// 校验 API 类型、地址和模型名称，`prefix` 为字段名前缀
+fn check_profile(errors: &mut FieldErrors, prefix: &str, profile: &ApiProfile, allowlist: &[String]) {
+    let field = |name: &str| format!("{}{}", prefix, name);
+
+    if !API_TYPES.contains(&profile.api_type.as_str()) {
+        errors.add(field("api_type"), format!("Unknown API type '{}'", profile.api_type));
+        return;
+    }
+    // mock 类型不访问网络，api_url 仅用于指定 fixture 文件
+    if profile.api_type == "mock" {
+        return;
+    }
+
+    let api_url = effective_api_url(&profile.api_type, &profile.api_url);
+    if api_url.is_empty() {
+        errors.add(field("api_url"), "API URL cannot be empty");
+    } else {
+        let checked = Url::parse(&api_url)
+            .map_err(|e| format!("Invalid URL: {}", e))
+            .and_then(|url| net_policy::check_url(&url, allowlist));
+        errors.check(&field("api_url"), checked);
+    }
+
+    if profile.model_name.trim().is_empty() {
+        errors.add(field("model_name"), "Model name cannot be empty");
+    } else {
+        errors.check(
+            &field("model_name"),
+            providers::validate_model_name(&profile.api_type, &api_url, &profile.model_name),
+        );
+    }
+}
+
+/// 快捷键必须能被解析，且彼此不能相同
+fn check_shortcuts(errors: &mut FieldErrors, shortcuts: &[(&str, &String)]) {
+    let mut parsed: Vec<(&str, Shortcut)> = Vec::new();
+    for &(field, value) in shortcuts {
+        if value.trim().is_empty() {
+            errors.add(field, "Shortcut cannot be empty");
+            continue;
+        }
+        let shortcut = match Shortcut::from_str(value.trim()) {
+            Ok(shortcut) => shortcut,
+            Err(e) => {
+                errors.add(field, format!("Invalid shortcut '{}': {}", value, e));
+                continue;
+            }
+        };
+        if let Some((other, _)) = parsed.iter().find(|(_, existing)| *existing == shortcut) {
+            errors.add(field, format!("'{}' is already used by {}", value, other));
+            continue;
+        }
+        parsed.push((field, shortcut));
+    }
+}
@@ -0,0 +1,196 @@
+// --- Conversation Titles & Summaries ---
+//
+// 每次保存助手回答后在后台调用一个便宜的模型：对话还在使用默认标题时生成简短标题，
+// 激活分支上新增足够多的消息后更新滚动摘要（在旧摘要的基础上合并新消息）。
+// 标题和摘要使用界面语言，并保存在历史数据库中；失败只记录日志，不影响对话本身。
+
+use std::collections::HashSet;
+use std::sync::Mutex;
+
+use serde::Serialize;
+use tauri::{AppHandle, Emitter, Manager};
+
+use crate::history::{self, message_text};
+use crate::settings::Settings;
+use crate::{resolve_profile, send_chat_request, ConversationMessage, RequestOptions, SecurityLogger};
+
+/// 摘要和标题更新后通知前端刷新历史列表
+pub const CONVERSATION_UPDATED_EVENT: &str = "conversation-updated";
+/// 激活分支新增多少条消息后更新摘要
+const SUMMARY_INTERVAL: u32 = 6;
+/// 生成标题时每条消息、以及整段对话文本的最大字符数
+const MAX_MESSAGE_CHARS: usize = 2_000;
+const MAX_TRANSCRIPT_CHARS: usize = 12_000;
+const MAX_TITLE_CHARS: usize = 60;
+
+/// 正在后台处理的对话，避免同一对话同时发起多次请求
+static IN_FLIGHT: Mutex<Option<HashSet<String>>> = Mutex::new(None);
+
+#[derive(Serialize, Clone)]
+struct ConversationUpdated {
+    id: String,
+    title: Option<String>,
+    summary: Option<String>,
+}
+
+/// 界面语言代码对应的语言名称，用于提示模型
+fn language_name(locale: &str) -> &str {
+    match locale {
+        "zh-CN" | "zh" => "Simplified Chinese",
+        "zh-TW" | "zh-HK" => "Traditional Chinese",
+        "ja" => "Japanese",
+        code if code.starts_with("en") => "English",
+        code => code,
+    }
+}
+
+fn clip(text: &str, max_chars: usize) -> String {
+    match text.char_indices().nth(max_chars) {
+        Some((index, _)) => format!("{}…", &text[..index]),
+        None => text.to_string(),
+    }
+}
+
+/// 把消息整理成纯文本记录，超出长度时保留开头和结尾、省略中间部分，
+/// 标题依赖对话开头，摘要也需要最新的消息
+fn transcript(messages: &[ConversationMessage]) -> String {
+    let lines: Vec<String> = messages
+        .iter()
+        .filter(|message| message.role != "system")
+        .map(|message| format!("{}: {}", message.role, clip(message_text(&message.content).trim(), MAX_MESSAGE_CHARS)))
+        .collect();
+    let text = lines.join("\n\n");
+    let total = text.chars().count();
+    if total <= MAX_TRANSCRIPT_CHARS {
+        return text;
+    }
+    let half = MAX_TRANSCRIPT_CHARS / 2;
+    let head: String = text.chars().take(half).collect();
+    let tail: String = text.chars().skip(total - half).collect();
+    format!("{}\n\n[…]\n\n{}", head, tail)
+}
+
+/// 清理模型返回的标题：只取第一行，去掉引号、结尾标点和 Markdown 标记
+fn clean_title(raw: &str) -> Option<String> {
+    let line = raw.lines().map(str::trim).find(|line| !line.is_empty())?;
+    let line = line.trim_start_matches('#').trim();
+    let line = line.strip_prefix("Title:").unwrap_or(line).trim();
+    let line = line.trim_matches(|c: char| matches!(c, '"' | '\'' | '“' | '”' | '「' | '」' | '*' | '`'));
+    let line = line.trim_end_matches(|c: char| matches!(c, '.' | '。'));
+    let title: String = line.chars().take(MAX_TITLE_CHARS).collect();
+    (!title.trim().is_empty()).then(|| title.trim().to_string())
+}
+
+/// 在后台更新对话的标题和摘要，供 append_message 调用
+pub fn spawn_refresh(app: AppHandle, conversation_id: String, locale: Option<String>) {
+    {
+        let mut in_flight = IN_FLIGHT.lock().unwrap_or_else(|e| e.into_inner());
+        if !in_flight.get_or_insert_with(HashSet::new).insert(conversation_id.clone()) {
+            return;
+        }
+    }
+    tauri::async_runtime::spawn(async move {
+        if let Err(e) = refresh(&app, &conversation_id, locale.as_deref()).await {
+            SecurityLogger::log_error(&app, &format!("Failed to update conversation summary: {}", e));
+        }
+        let mut in_flight = IN_FLIGHT.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(set) = in_flight.as_mut() {
+            set.remove(&conversation_id);
+        }
+    });
+}
+
+async fn refresh(app: &AppHandle, conversation_id: &str, locale: Option<&str>) -> Result<(), String> {
+    let settings = Settings::load(app)?;
+    if !settings.auto_summaries {
+        return Ok(());
+    }
+
+    let history = app.state::<history::HistoryStore>();
+    let conversation = history.load_conversation(conversation_id)?;
+    let messages = history.conversation_path(conversation_id, None)?;
+    let has_exchange = messages.iter().any(|m| m.role == "user") && messages.iter().any(|m| m.role == "assistant");
+    if !has_exchange {
+        return Ok(());
+    }
+    let needs_title = conversation.title == history::DEFAULT_TITLE;
+    let (previous_summary, summarized_count) = history.summary_state(conversation_id)?;
+    let message_count = messages.len() as u32;
+    let needs_summary = message_count >= summarized_count + SUMMARY_INTERVAL || previous_summary.is_none();
+    if !needs_title && !needs_summary {
+        return Ok(());
+    }
+
+    // 使用当前配置的服务，模型可单独指定为更便宜的模型
+    let allowlist = settings.endpoint_allowlist.clone();
+    let profile = settings.summary_profile(&settings.current_profile());
+    let profile = resolve_profile(app, profile, &allowlist).map_err(|e| format!("{:?}", e))?;
+    let language = language_name(locale.unwrap_or("en"));
+
+    let mut update = ConversationUpdated { id: conversation_id.to_string(), title: None, summary: None };
+
+    if needs_title {
+        // mock 服务会向界面推送流式事件，不用于后台请求，直接取第一条用户消息作为标题
+        let raw = if profile.api_type == "mock" {
+            messages.iter().find(|m| m.role == "user").map(|m| message_text(&m.content)).unwrap_or_default()
+        } else {
+            let prompt = format!(
+                "Write a short title (at most 6 words) for the following conversation in {}. \
+                 Reply with the title only, without quotes or punctuation at the end.\n\n{}",
+                language,
+                transcript(&messages)
+            );
+            complete(app, &profile, allowlist.clone(), prompt).await?
+        };
+        if let Some(title) = clean_title(&raw) {
+            if history.set_generated_title(conversation_id, &title)? {
+                update.title = Some(title);
+            }
+        }
+    }
+
+    if needs_summary && profile.api_type != "mock" {
+        let new_messages = &messages[(summarized_count as usize).min(messages.len())..];
+        let prompt = match &previous_summary {
+            Some(summary) => format!(
+                "Here is a summary of a conversation so far:\n\n{}\n\nUpdate it with the following new messages. \
+                 Write at most 3 sentences in {}, focusing on the user's goals, decisions and open questions. \
+                 Reply with the summary only.\n\n{}",
+                summary,
+                language,
+                transcript(new_messages)
+            ),
+            None => format!(
+                "Summarize the following conversation in at most 3 sentences in {}, focusing on the user's goals, \
+                 decisions and open questions. Reply with the summary only.\n\n{}",
+                language,
+                transcript(&messages)
+            ),
+        };
+        let summary = complete(app, &profile, allowlist, prompt).await?;
+        let summary = summary.trim();
+        if !summary.is_empty() {
+            history.set_summary(conversation_id, summary, message_count)?;
+            update.summary = Some(summary.to_string());
+        }
+    }
+
+    if update.title.is_some() || update.summary.is_some() {
+        let _ = app.emit(CONVERSATION_UPDATED_EVENT, update);
+    }
+    Ok(())
+}
+
+async fn complete(
+    app: &AppHandle,
+    profile: &crate::ApiProfile,
+    endpoint_allowlist: Vec<String>,
+    prompt: String,
+) -> Result<String, String> {
+    let messages = vec![serde_json::json!({ "role": "user", "content": prompt })];
+    // 后台请求不使用 CANCEL_REQUESTS，用户取消正在进行的对话时不会中断标题和摘要的生成
+    let options = RequestOptions { endpoint_allowlist, cancel: None, ..Default::default() };
+    send_chat_request(app, profile, messages, options)
+        .await
+        .map_err(|e| format!("{:?}", e))
+}
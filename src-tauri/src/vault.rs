@@ -0,0 +1,362 @@
+// --- Encryption at Rest ---
+//
+// 可选的静态加密。启用后生成一个随机的数据密钥，用 Argon2id 从主密码派生的密钥以
+// XChaCha20-Poly1305 包装后保存在 `vault.json` 中；数据密钥只在解锁后保存在内存里。
+// 数据密钥用于：settings.dat 中的 API 密钥（以 `enc:v1:` 前缀保存）以及 SQLCipher 加密的历史数据库。
+// 更换主密码时同时更换数据密钥，重新加密数据库和设置中的密钥。
+
+use std::path::Path;
+use std::sync::Mutex;
+
+use argon2::{Algorithm, Argon2, Params, Version};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+use zeroize::Zeroizing;
+
+/// 保存包装后数据密钥的文件（位于配置目录）
+pub const VAULT_FILE: &str = "vault.json";
+/// 加密后的设置值前缀
+const SECRET_PREFIX: &str = "enc:v1:";
+const VAULT_VERSION: u32 = 1;
+const KEY_LEN: usize = 32;
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 24;
+/// Argon2id 参数：64 MiB 内存、3 次迭代
+const ARGON2_M_COST: u32 = 64 * 1024;
+const ARGON2_T_COST: u32 = 3;
+const ARGON2_P_COST: u32 = 1;
+/// 主密码的最小长度
+const MIN_PASSPHRASE_CHARS: usize = 8;
+
+type Key = Zeroizing<[u8; KEY_LEN]>;
+
+/// 解锁后的数据密钥
+static DATA_KEY: Mutex<Option<Key>> = Mutex::new(None);
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct VaultFile {
+    version: u32,
+    salt: String,
+    m_cost: u32,
+    t_cost: u32,
+    p_cost: u32,
+    /// 用主密码派生的密钥加密的数据密钥
+    wrapped_key: String,
+}
+
+#[derive(Serialize, Debug, Clone, TS)]
+#[ts(export)]
+pub struct VaultStatus {
+    pub enabled: bool,
+    pub unlocked: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VaultError {
+    /// 已启用加密但尚未解锁
+    Locked,
+    WrongPassphrase,
+    Other(String),
+}
+
+impl std::fmt::Display for VaultError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            VaultError::Locked => write!(f, "The vault is locked. Unlock it with your master passphrase first."),
+            VaultError::WrongPassphrase => write!(f, "Incorrect master passphrase"),
+            VaultError::Other(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl From<VaultError> for String {
+    fn from(error: VaultError) -> Self {
+        error.to_string()
+    }
+}
+
+fn read_vault_file(config_dir: &Path) -> Result<Option<VaultFile>, VaultError> {
+    let path = config_dir.join(VAULT_FILE);
+    if !path.exists() {
+        return Ok(None);
+    }
+    let text = std::fs::read_to_string(&path).map_err(|e| VaultError::Other(format!("Failed to read vault: {}", e)))?;
+    parse_vault(&text).map(Some)
+}
+
+fn parse_vault(text: &str) -> Result<VaultFile, VaultError> {
+    let vault: VaultFile =
+        serde_json::from_str(text).map_err(|e| VaultError::Other(format!("Vault file is corrupted: {}", e)))?;
+    if vault.version != VAULT_VERSION {
+        return Err(VaultError::Other(format!("Unsupported vault version {}", vault.version)));
+    }
+    Ok(vault)
+}
+
+/// 先写临时文件再重命名，避免写入中断导致数据密钥丢失
+fn write_vault_file(config_dir: &Path, vault: &VaultFile) -> Result<(), VaultError> {
+    let to_error = |e: std::io::Error| VaultError::Other(format!("Failed to write vault: {}", e));
+    std::fs::create_dir_all(config_dir).map_err(to_error)?;
+    let path = config_dir.join(VAULT_FILE);
+    let temp_path = config_dir.join(format!("{}.tmp", VAULT_FILE));
+    let text = serde_json::to_string_pretty(vault).map_err(|e| VaultError::Other(e.to_string()))?;
+    std::fs::write(&temp_path, text).map_err(to_error)?;
+    std::fs::rename(&temp_path, &path).map_err(to_error)
+}
+
+fn random_key() -> Key {
+    let mut key = Zeroizing::new([0u8; KEY_LEN]);
+    OsRng.fill_bytes(&mut key[..]);
+    key
+}
+
+fn derive_key(passphrase: &str, salt: &[u8], m_cost: u32, t_cost: u32, p_cost: u32) -> Result<Key, VaultError> {
+    let params = Params::new(m_cost, t_cost, p_cost, Some(KEY_LEN)).map_err(|e| VaultError::Other(e.to_string()))?;
+    let mut key = Zeroizing::new([0u8; KEY_LEN]);
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key[..])
+        .map_err(|e| VaultError::Other(e.to_string()))?;
+    Ok(key)
+}
+
+/// 加密并编码为 base64(nonce || ciphertext)
+fn seal(key: &Key, plaintext: &[u8]) -> String {
+    let cipher = XChaCha20Poly1305::new(chacha20poly1305::Key::from_slice(&key[..]));
+    let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let ciphertext = cipher.encrypt(&nonce, plaintext).expect("XChaCha20-Poly1305 encryption cannot fail");
+    let mut sealed = nonce.to_vec();
+    sealed.extend(ciphertext);
+    BASE64.encode(sealed)
+}
+
+fn open(key: &Key, sealed: &str) -> Option<Zeroizing<Vec<u8>>> {
+    let bytes = BASE64.decode(sealed).ok()?;
+    if bytes.len() < NONCE_LEN {
+        return None;
+    }
+    let (nonce, ciphertext) = bytes.split_at(NONCE_LEN);
+    let cipher = XChaCha20Poly1305::new(chacha20poly1305::Key::from_slice(&key[..]));
+    cipher.decrypt(XNonce::from_slice(nonce), ciphertext).ok().map(Zeroizing::new)
+}
+
+fn wrap_data_key(passphrase: &str, data_key: &Key) -> Result<VaultFile, VaultError> {
+    let mut salt = [0u8; SALT_LEN];
+    OsRng.fill_bytes(&mut salt);
+    let wrapping_key = derive_key(passphrase, &salt, ARGON2_M_COST, ARGON2_T_COST, ARGON2_P_COST)?;
+    Ok(VaultFile {
+        version: VAULT_VERSION,
+        salt: BASE64.encode(salt),
+        m_cost: ARGON2_M_COST,
+        t_cost: ARGON2_T_COST,
+        p_cost: ARGON2_P_COST,
+        wrapped_key: seal(&wrapping_key, &data_key[..]),
+    })
+}
+
+fn unwrap_data_key(passphrase: &str, vault: &VaultFile) -> Result<Key, VaultError> {
+    let salt = BASE64.decode(&vault.salt).map_err(|_| VaultError::Other("Vault file is corrupted".to_string()))?;
+    let wrapping_key = derive_key(passphrase, &salt, vault.m_cost, vault.t_cost, vault.p_cost)?;
+    let bytes = open(&wrapping_key, &vault.wrapped_key).ok_or(VaultError::WrongPassphrase)?;
+    let mut key = Zeroizing::new([0u8; KEY_LEN]);
+    if bytes.len() != KEY_LEN {
+        return Err(VaultError::Other("Vault file is corrupted".to_string()));
+    }
+    key.copy_from_slice(&bytes);
+    Ok(key)
+}
+
+fn check_passphrase(passphrase: &str) -> Result<(), VaultError> {
+    if passphrase.chars().count() < MIN_PASSPHRASE_CHARS {
+        return Err(VaultError::Other(format!(
+            "The master passphrase must be at least {} characters long",
+            MIN_PASSPHRASE_CHARS
+        )));
+    }
+    Ok(())
+}
+
+fn current_key() -> Option<Key> {
+    DATA_KEY.lock().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+fn set_current_key(key: Option<Key>) {
+    *DATA_KEY.lock().unwrap_or_else(|e| e.into_inner()) = key;
+}
+
+/// SQLCipher 使用的原始密钥格式 `x'..'`
+fn database_key_for(key: &Key) -> Zeroizing<String> {
+    let hex: String = key.iter().map(|b| format!("{:02x}", b)).collect();
+    Zeroizing::new(format!("x'{}'", hex))
+}
+
+pub fn is_enabled(config_dir: &Path) -> bool {
+    config_dir.join(VAULT_FILE).exists()
+}
+
+pub fn status(config_dir: &Path) -> VaultStatus {
+    VaultStatus { enabled: is_enabled(config_dir), unlocked: current_key().is_some() }
+}
+
+/// 当前数据库密钥；未启用加密时为 None，已启用但未解锁时返回错误
+pub fn database_key(config_dir: &Path) -> Result<Option<Zeroizing<String>>, VaultError> {
+    if !is_enabled(config_dir) {
+        return Ok(None);
+    }
+    current_key().map(|key| Some(database_key_for(&key))).ok_or(VaultError::Locked)
+}
+
+/// 启用加密的各个步骤需要的密钥
+pub struct NewKey {
+    key: Key,
+    vault: VaultFile,
+}
+
+impl NewKey {
+    pub fn database_key(&self) -> Zeroizing<String> {
+        database_key_for(&self.key)
+    }
+
+    /// 提交前的新数据密钥，用于先用新密钥加密设置
+    pub fn data_key(&self) -> DataKey {
+        DataKey(self.key.clone())
+    }
+}
+
+/// 生成新的数据密钥并用主密码包装，尚未写入磁盘
+pub fn prepare_new_key(passphrase: &str) -> Result<NewKey, VaultError> {
+    check_passphrase(passphrase)?;
+    let key = random_key();
+    let vault = wrap_data_key(passphrase, &key)?;
+    Ok(NewKey { key, vault })
+}
+
+/// 写入 vault.json 并在内存中切换到新的数据密钥。这是启用加密和更换密钥的提交点。
+pub fn commit_new_key(config_dir: &Path, new_key: NewKey) -> Result<(), VaultError> {
+    write_vault_file(config_dir, &new_key.vault)?;
+    set_current_key(Some(new_key.key));
+    Ok(())
+}
+
+/// 用主密码解锁，返回数据库密钥
+pub fn unlock(config_dir: &Path, passphrase: &str) -> Result<Zeroizing<String>, VaultError> {
+    let vault = read_vault_file(config_dir)?.ok_or_else(|| VaultError::Other("Encryption is not enabled".to_string()))?;
+    let key = unwrap_data_key(passphrase, &vault)?;
+    let database_key = database_key_for(&key);
+    set_current_key(Some(key));
+    Ok(database_key)
+}
+
+/// 验证主密码但不改变解锁状态
+pub fn verify(config_dir: &Path, passphrase: &str) -> Result<(), VaultError> {
+    let vault = read_vault_file(config_dir)?.ok_or_else(|| VaultError::Other("Encryption is not enabled".to_string()))?;
+    unwrap_data_key(passphrase, &vault).map(|_| ())
+}
+
+/// 从内存中清除数据密钥
+pub fn lock() {
+    set_current_key(None);
+}
+
+pub fn is_sealed(value: &str) -> bool {
+    value.starts_with(SECRET_PREFIX)
+}
+
+/// 用当前数据密钥加密设置值。空值和已加密的值保持不变。
+pub fn seal_secret(value: &str) -> Result<String, VaultError> {
+    if value.is_empty() || is_sealed(value) {
+        return Ok(value.to_string());
+    }
+    let key = current_key().ok_or(VaultError::Locked)?;
+    Ok(format!("{}{}", SECRET_PREFIX, seal(&key, value.as_bytes())))
+}
+
+/// 解密设置值，未加密的值原样返回
+pub fn reveal(value: &str) -> Result<String, VaultError> {
+    if !is_sealed(value) {
+        return Ok(value.to_string());
+    }
+    let key = current_key().ok_or(VaultError::Locked)?;
+    reveal_with_key(value, &DataKey(key))
+}
+
+/// 用指定的数据密钥解密设置值，未加密的值原样返回
+pub fn reveal_with_key(value: &str, key: &DataKey) -> Result<String, VaultError> {
+    match value.strip_prefix(SECRET_PREFIX) {
+        Some(sealed) => reveal_with(&key.0, sealed),
+        None => Ok(value.to_string()),
+    }
+}
+
+fn reveal_with(key: &Key, sealed: &str) -> Result<String, VaultError> {
+    let bytes = open(key, sealed).ok_or_else(|| {
+        VaultError::Other("A stored secret could not be decrypted. Please enter the API key again.".to_string())
+    })?;
+    String::from_utf8(bytes.to_vec()).map_err(|e| VaultError::Other(e.to_string()))
+}
+
+/// 用旧的数据密钥解密、再用新的数据密钥加密（更换密钥时使用）。空值保持不变。
+pub fn reseal_secret(value: &str, previous: &DataKey, next: &DataKey) -> Result<String, VaultError> {
+    if value.is_empty() {
+        return Ok(String::new());
+    }
+    let plain = Zeroizing::new(reveal_with_key(value, previous)?);
+    Ok(format!("{}{}", SECRET_PREFIX, seal(&next.0, plain.as_bytes())))
+}
+
+/// 不在内存中启用的数据密钥：更换密钥前的旧密钥，或备份文件中的密钥
+pub struct DataKey(Key);
+
+impl DataKey {
+    pub fn database_key(&self) -> Zeroizing<String> {
+        database_key_for(&self.0)
+    }
+}
+
+pub fn current_data_key() -> Result<DataKey, VaultError> {
+    current_key().map(DataKey).ok_or(VaultError::Locked)
+}
+
+/// 用主密码解开另一份 vault.json（例如备份中的）里的数据密钥，不改变当前的解锁状态
+pub fn unwrap_vault_text(text: &str, passphrase: &str) -> Result<DataKey, VaultError> {
+    unwrap_data_key(passphrase, &parse_vault(text)?).map(DataKey)
+}
+
+/// 设置中需要加密的字段：顶层的 `api_key` 以及 `profiles` 中每个配置的 `api_key`
+pub fn map_secret_values(
+    settings: &mut serde_json::Map<String, serde_json::Value>,
+    f: impl Fn(&str) -> Result<String, VaultError>,
+) -> Result<(), VaultError> {
+    if let Some(serde_json::Value::String(api_key)) = settings.get_mut("api_key") {
+        *api_key = f(api_key)?;
+    }
+    if let Some(serde_json::Value::Array(profiles)) = settings.get_mut("profiles") {
+        for profile in profiles {
+            if let Some(serde_json::Value::String(api_key)) = profile.get_mut("api_key") {
+                *api_key = f(api_key)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// 对 settings.dat 中的密钥字段应用 `map_secret_values`
+pub fn map_secrets(
+    store: &tauri_plugin_store::Store<tauri::Wry>,
+    f: impl Fn(&str) -> Result<String, VaultError>,
+) -> Result<(), VaultError> {
+    let mut settings = serde_json::Map::new();
+    for key in ["api_key", "profiles"] {
+        if let Some(value) = store.get(key) {
+            settings.insert(key.to_string(), value);
+        }
+    }
+    map_secret_values(&mut settings, f)?;
+    for (key, value) in settings {
+        store.set(key, value);
+    }
+    Ok(())
+}
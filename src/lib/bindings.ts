@@ -2,13 +2,135 @@
 
 // This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
 
-export type ApiError = { "type": "NetworkError", "message": string } | { "type": "InvalidApiKey" } | { "type": "InvalidApiUrl", "message": string } | { "type": "InvalidModelName", "message": string } | { "type": "RateLimitExceeded" } | { "type": "ApiResponseError", "message": string } | { "type": "InternalError", "message": string };
+export type ApiError = { "type": "NetworkError", "message": string } | { "type": "InvalidApiKey" } | { "type": "InvalidApiUrl", "message": string } | { "type": "InvalidModelName", "message": string } | { "type": "RateLimitExceeded" } | { "type": "ApiResponseError", "message": string } | { "type": "InternalError", "message": string } | { "type": "InvalidAttachment", "message": string } | { "type": "Timeout", "message": { timeout_secs: number | null, } } | { "type": "ContextLengthExceeded", "message": { message: string, max_tokens: number | null, requested_tokens: number | null, } } | { "type": "ContentFiltered", "message": { message: string, category: string | null, } } | { "type": "InsufficientQuota", "message": { message: string, } } | { "type": "ModelNotFound", "message": { model: string, message: string, } } | { "type": "Overloaded", "message": { message: string, retry_after_secs: number | null, } } | { "type": "Cancelled" } | { "type": "VaultLocked" } | { "type": "BadRequest", "message": { message: string, param: string | null, } } | { "type": "ProviderError", "message": { status: number, error_type: string | null, code: string | null, message: string, param: string | null, } };
 
 // This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
 import type { Attachment } from "./Attachment";
 
-export type ConversationMessage = { role: string, content: any, attachment: Attachment | null, };
+export type ConversationMessage = { role: string, content: any, attachments: Array<Attachment>, };
 
 // This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
 
-export type Attachment = { name: string, type: string, content: string, previewUrl: string | null, };
\ No newline at end of file
+export type Attachment = { name: string, type: string, content: string, previewUrl: string | null, };
+
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export type ApiProfile = { name: string, api_type: string, api_url: string, api_key: string, model_name: string, };
+
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export type ProcessedImage = { data_url: string, width: number, height: number, original_bytes: number, final_bytes: number, };
+
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export type CaptureSummary = { id: string, timestamp: string, api_type: string, model_name: string, url: string, status: number | null, error: string | null, replay_of: string | null, };
+
+
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { Attachment } from "./Attachment";
+
+export type StoredMessage = { id: string, role: string, content: any, attachments: Array<Attachment>, model: string | null, created_at: string, parent_id: string | null, sibling_ids: Array<string>, pinned: boolean, };
+
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { StoredMessage } from "./StoredMessage";
+
+export type Conversation = { id: string, title: string, profile: string, created_at: string, updated_at: string, summary: string | null, folder_id: string | null, tags: Array<string>, pinned: boolean, archived: boolean, active_leaf_id: string | null, messages: Array<StoredMessage>, };
+
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export type ConversationSummary = { id: string, title: string, profile: string, created_at: string, updated_at: string, summary: string | null, folder_id: string | null, tags: Array<string>, pinned: boolean, archived: boolean, message_count: number, };
+
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { ConversationSummary } from "./ConversationSummary";
+
+export type ConversationPage = { items: Array<ConversationSummary>, total: number, offset: number, limit: number, };
+
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export type SearchQuery = { query: string, from: string | null, to: string | null, model: string | null, role: string | null, limit: number | null, offset: number | null, };
+
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export type SearchHit = { conversation_id: string, conversation_title: string, message_id: string, role: string, model: string | null, snippet: string, score: number, created_at: string, };
+
+
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export type ExportFormat = "markdown" | "json" | "html" | "docx" | "pdf";
+
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export type ImportStatus = "imported" | "duplicate" | "failed";
+
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export type ImportOutcome = { title: string, source_id: string | null, status: ImportStatus, conversation_id: string | null, message_count: number, error: string | null, };
+
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export type ImportReport = { source: string, imported: number, duplicates: number, failed: number, conversations: Array<ImportOutcome>, };
+
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export type Compaction = { id: string, conversation_id: string, summary: string, model: string | null, message_ids: Array<string>, created_at: string, undone_at: string | null, superseded_by: string | null, leaf_id: string | null, };
+
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export type Folder = { id: string, name: string, created_at: string, conversation_count: number, };
+
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export type TagCount = { tag: string, conversation_count: number, };
+
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export type ConversationFilter = { folder_id: string | null, unfiled: boolean, tags: Array<string>, pinned: boolean | null, archived: boolean, };
+
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export type VaultStatus = { enabled: boolean, unlocked: boolean, };
+
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export type BackupFile = { path: string, size: number, sha256: string, };
+
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { BackupFile } from "./BackupFile";
+
+export type BackupManifest = { format: string, version: number, app_version: string, created_at: string, includes_secrets: boolean, encrypted: boolean, conversation_count: number, files: Array<BackupFile>, };
+
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export type RestoreMode = "merge" | "replace";
+
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { BackupManifest } from "./BackupManifest";
+import type { RestoreMode } from "./RestoreMode";
+
+export type RestoreReport = { mode: RestoreMode, manifest: BackupManifest, conversations_added: number, conversations_skipped: number, settings_restored: number, prompts_restored: number, };
+
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { ApiProfile } from "./ApiProfile";
+
+export type Settings = { active_profile: string, profiles: Array<ApiProfile>, system_prompt: string, system_prompt_preset: string, summary_model: string, auto_summaries: boolean, shortcut: string, clear_chat_shortcut: string, borderless_shortcut: string, borderless: boolean, capture_requests: boolean, max_response_chars: number, endpoint_allowlist: Array<string>, auto_compaction: boolean, context_window_tokens: number, compaction_threshold: number, compaction_keep_turns: number, incognito: boolean, retention_days: number, retention_max_conversations: number, retention_keep_pinned: boolean, };
+
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export type SettingsError = { "type": "Invalid", fields: Record<string, string>, } | { "type": "Internal", message: string, };
+
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { ApiProfile } from "./ApiProfile";
+
+export type LoadedSettings = { active_profile: string, profiles: Array<ApiProfile>, system_prompt: string, system_prompt_preset: string, summary_model: string, auto_summaries: boolean, shortcut: string, clear_chat_shortcut: string, borderless_shortcut: string, borderless: boolean, capture_requests: boolean, max_response_chars: number, endpoint_allowlist: Array<string>, auto_compaction: boolean, context_window_tokens: number, compaction_threshold: number, compaction_keep_turns: number, incognito: boolean, retention_days: number, retention_max_conversations: number, retention_keep_pinned: boolean, env_locked: Array<string>, };
+
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export type Prompt = { id: string, name: string, description: string, tags: Array<string>, content: string, updated_at: string, };
+
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export type PromptDraft = { name: string, description: string, tags: Array<string>, content: string, };
+
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export type PromptImportReport = { added: number, updated: number, skipped: number, };
\ No newline at end of file
@@ -1,17 +1,21 @@
-import { writable } from 'svelte/store';
-import { _ } from 'svelte-i18n';
+import { get, writable } from 'svelte/store';
+import { _, locale } from 'svelte-i18n';
+import { invoke } from '@tauri-apps/api/core';
+import type { Conversation } from '$lib/bindings';
+import { incognito } from './settings.store';
 
 export type Attachment = {
   name: string;
   content: string; // For text, this is the text content. For images, this is the base64 data.
   type: 'text' | 'image';
   previewUrl?: string | null; // URL for image previews in the UI
+  sizeBytes?: number; // Final size after backend preprocessing (images only)
 };
 
 export type Message = {
   role: 'user' | 'assistant' | 'system';
   content: string;
-  attachment?: Attachment | null;
+  attachments?: Attachment[];
 };
 
 function createChatStore() {
@@ -39,7 +43,37 @@ function createChatStore() {
     });
   };
 
+  // The conversation in the backend history store that new messages are appended to
+  let conversationId: string | null = null;
+
+  // Saves are chained so the conversation is created exactly once, before any message is appended
+  let saveQueue: Promise<void> = Promise.resolve();
+
+  const persist = (message: Message) => {
+    if (get(incognito)) {
+      return saveQueue;
+    }
+    saveQueue = saveQueue.then(async () => {
+      try {
+        if (!conversationId) {
+          const conversation = await invoke<Conversation>('create_conversation', {});
+          conversationId = conversation.id;
+        }
+        await invoke('append_message', {
+          conversationId,
+          message: { role: message.role, content: message.content, attachments: message.attachments ?? [] },
+          // Titles and summaries are generated in the UI language
+          locale: get(locale)
+        });
+      } catch (error) {
+        console.error('Failed to save message to history:', error);
+      }
+    });
+    return saveQueue;
+  };
+
   const clearChat = () => {
+    conversationId = null;
     set([{ role: 'assistant', content: initialMessage }]);
   };
 
@@ -49,8 +83,8 @@ function createChatStore() {
     update,
     init, // Expose the init function
     clearChat,
-    addUserMessage: (content: string, attachment: Attachment | null = null) => {
-      const newMessage: Message = { role: 'user', content, attachment };
+    addUserMessage: (content: string, attachments: Attachment[] = []) => {
+      const newMessage: Message = { role: 'user', content, attachments };
       update(messages => {
         const currentMessages = messages;
         if (currentMessages.length === 1 && currentMessages[0].role === 'assistant') {
@@ -58,11 +92,20 @@ function createChatStore() {
         }
         return [...currentMessages, newMessage];
       });
+      return persist(newMessage);
     },
-    addAssistantMessage: (content: string) => {
+    addAssistantMessage: (content: string, save = true) => {
       // Remove leading and trailing whitespace/newlines
       const cleanedContent = content.trim();
-      update(messages => [...messages, { role: 'assistant', content: cleanedContent }]);
+      const newMessage: Message = { role: 'assistant', content: cleanedContent };
+      update(messages => [...messages, newMessage]);
+      // Error messages are only shown, not saved to history
+      return save ? persist(newMessage) : Promise.resolve();
+    },
+    // Resolves once pending saves finish, so the id covers every message shown
+    getConversationId: async () => {
+      await saveQueue;
+      return conversationId;
     },
   };
 }
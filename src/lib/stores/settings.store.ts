@@ -1,29 +1,39 @@
 import { writable } from 'svelte/store';
 import { invoke } from '@tauri-apps/api/core';
+import { listen } from '@tauri-apps/api/event';
+import type { Settings } from '$lib/bindings';
 
-// 创建一个简单的可写存储
-function createPersistentStore<T>(key: string, defaultValue: T) {
+// 创建一个与后端设置同步的可写存储
+function createPersistentStore<K extends keyof Settings>(key: K, defaultValue: Settings[K]) {
+  type T = Settings[K];
   const { subscribe, set, update } = writable<T>(defaultValue);
 
+  const load = () =>
+    invoke<Settings>('get_settings').then((settings) => {
+      if (settings && settings[key] !== undefined) {
+        set(settings[key]);
+      }
+    });
+
   // 初始化时从后端加载值
-  invoke('get_settings').then((settings: any) => {
-    if (settings && settings[key] !== undefined) {
-      set(settings[key]);
-    }
-  }).catch(() => {
+  load().catch(() => {
     // 如果加载失败，使用默认值
     set(defaultValue);
   });
 
+  // 其它窗口或外部修改了这个设置时重新加载
+  listen<{ keys: string[] }>('settings-changed', (event) => {
+    if (event.payload.keys.includes(key)) {
+      load().catch(console.error);
+    }
+  }).catch(console.error);
+
   return {
     subscribe,
     set: (value: T) => {
       set(value);
-      // 保存到后端
-      invoke('get_settings').then((currentSettings: any) => {
-        const updatedSettings = { ...currentSettings, [key]: value };
-        invoke('set_settings', { settings: updatedSettings });
-      }).catch(console.error);
+      // 只保存这一个字段
+      invoke('update_settings', { patch: { [key]: value } }).catch(console.error);
     },
     update,
   };
@@ -31,20 +41,13 @@ function createPersistentStore<T>(key: string, defaultValue: T) {
 
 export const clearChatShortcut = createPersistentStore('clear_chat_shortcut', 'Ctrl+Q');
 
-export const systemPromptPresets = {
-  default: 'You are a helpful assistant. Please answer the user\'s questions concisely.',
-  minimal: 'Your function is to distill every query to its absolute essence. Provide the single most critical piece of information as a declarative statement. Maximum signal, zero noise. Your response should rarely exceed one sentence.',
-  custom: ''
-};
-
-export const selectedSystemPromptPreset = createPersistentStore('selectedSystemPromptPreset', 'default');
+// 系统提示预设来自后端的提示库（list_prompts），这里保存所选提示的 id
+export const selectedSystemPromptPreset = createPersistentStore('system_prompt_preset', 'default');
 
-export const apiKey = createPersistentStore('api_key', '');
-export const apiUrl = createPersistentStore('api_url', 'https://api.openai.com/v1/chat/completions');
-export const modelName = createPersistentStore('model_name', 'gpt-4o-mini');
 export const shortcut = createPersistentStore('shortcut', 'Alt+Space');
 export const systemPrompt = createPersistentStore('system_prompt', 'You are a helpful assistant. Please answer the user\'s questions concisely.');
-export const apiType = createPersistentStore('api_type', 'openai');
 export const borderless = createPersistentStore('borderless', false);
+// Incognito conversations are never written to history; the backend enforces this as well
+export const incognito = createPersistentStore('incognito', false);
 
 export const borderlessShortcut = createPersistentStore('borderless_shortcut', 'Ctrl+Shift+B');
\ No newline at end of file